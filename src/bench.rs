@@ -0,0 +1,2356 @@
+use crate::bench_report::{
+    compare, write_timeline, BenchReport, ConnectionReport, ErrorKind, ErrorReport, FlowReport, LatencySummary,
+    OutputFormat, StageReport, StepReport, Threshold, TimelineBucket,
+};
+use crate::bench_compare::{CompareReport, LatencyMoments, Protocol, Variant, VariantResult};
+use crate::bench_conn::{ClientPool, ConnectionLimits, ConnectionUse, Lease};
+use crate::bench_expect::Expectations;
+use crate::bench_feed::Feed;
+use crate::bench_scenario::{Scenario, Template};
+use crate::bench_tui::{run_dashboard, DashboardConfig, LiveSample};
+use crate::core::{client_builder, parse_header, ClientType};
+use crate::log::{log_debug, log_info, log_warn};
+use crate::progress::{self, ProgressEvent};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use hdrhistogram::Histogram;
+use indicatif::HumanBytes;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Method,
+};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    error::Error as _,
+    fs, io,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, Notify, Semaphore},
+    task::JoinSet,
+};
+
+/// 两次 progress 事件之间的间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 空闲（暂停或超出目标并发数）的工作协程检查状态的间隔
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+/// 实际发送速率低于目标速率的这个比例时给出提示
+const RATE_WARNING_RATIO: f64 = 0.95;
+
+/// 恒定速率模式下最多积压这么长时间的请求（至少 MIN_BACKLOG 个），超出的不再发送，计为丢弃
+const MAX_BACKLOG: Duration = Duration::from_secs(1);
+const MIN_BACKLOG: usize = 100;
+
+/// 延迟直方图的上限（微秒）和有效数字位数
+const HISTOGRAM_MAX_MICROS: u64 = 3_600_000_000;
+const HISTOGRAM_SIGFIG: u8 = 3;
+
+/// 终端延迟分布图的行数和最大柱宽
+const CHART_ROWS: usize = 12;
+const CHART_WIDTH: usize = 40;
+
+/// 压测设置
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// 请求总数（指定 duration 时忽略）
+    pub requests: usize,
+    /// 并发连接数；恒定速率模式下为同时进行中的请求上限
+    pub concurrency: usize,
+    pub connect_timeout: u64,
+    pub http3: bool,
+    /// 不经协商直接使用 HTTP/2（http:// 时为 h2c）
+    pub http2: bool,
+    /// keep-alive、连接数和每个连接上的并发请求数
+    pub connections: ConnectionLimits,
+    /// 按时长压测
+    pub duration: Option<Duration>,
+    /// 恒定到达速率（请求/秒），开放模型
+    pub rate: Option<f64>,
+    /// 预热时长，期间的结果不计入统计
+    pub warmup: Option<Duration>,
+    /// 分阶段调整并发数
+    pub stages: Vec<Stage>,
+    /// 结果输出格式
+    pub output_format: OutputFormat,
+    /// 把每秒的时间线写到这个 CSV 文件
+    pub timeline: Option<PathBuf>,
+    /// 用于对比的基线结果（--output-format json 的输出）
+    pub baseline: Option<PathBuf>,
+    /// 满足任一条件时压测以失败退出
+    pub fail_if: Vec<Threshold>,
+    /// 请求方法
+    pub method: Method,
+    /// 自定义请求头（"Name: value"）
+    pub headers: Vec<String>,
+    /// 请求体，每个请求发送同一份
+    pub body: Option<Bytes>,
+    pub follow_redirects: bool,
+    /// 运行时显示全屏实时面板
+    pub tui: bool,
+    /// 多步骤场景，代替单个请求
+    pub scenario: Option<Arc<Scenario>>,
+    /// {{column}} 占位符使用的数据
+    pub feed: Option<Arc<Feed>>,
+    /// 对每个响应的检查，不满足时请求算失败
+    pub expect: Arc<Expectations>,
+    /// 对比压测的各方（--compare/--protocols）；为空时是普通压测
+    pub variants: Vec<Variant>,
+}
+
+impl BenchOptions {
+    // --http2/--http3 指定的 HTTP 版本
+    fn protocol(&self) -> Option<Protocol> {
+        match (self.http2, self.http3) {
+            (true, _) => Some(Protocol::Http2),
+            (_, true) => Some(Protocol::Http3),
+            _ => None,
+        }
+    }
+}
+
+// 一个压测请求；普通压测所有请求共用一个，带占位符时和场景中每次单独渲染
+#[derive(Clone)]
+struct BenchRequest {
+    method: Method,
+    url: String,
+    /// 客户端默认请求头之外的请求头
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Option<Bytes>,
+    /// 保留响应体（场景中需要从响应提取变量）
+    capture_body: bool,
+    expect: Arc<Expectations>,
+}
+
+// 带占位符的请求，每次按数据行和生成器渲染
+struct RequestTemplate {
+    method: Method,
+    url: Template,
+    headers: Vec<Template>,
+    body: Option<Template>,
+    feed: Arc<Feed>,
+    expect: Arc<Expectations>,
+}
+
+// 场景压测：相对 URL 按 base 拼接，每次流程使用一行数据
+struct ScenarioWorkload {
+    scenario: Arc<Scenario>,
+    base: String,
+    feed: Arc<Feed>,
+    expect: Arc<Expectations>,
+}
+
+// 工作协程每一轮执行的内容：单个请求（固定或模板），或按权重选出的一个场景流程
+#[derive(Clone)]
+enum Workload {
+    Request(Arc<BenchRequest>),
+    Template(Arc<RequestTemplate>),
+    Scenario(Arc<ScenarioWorkload>),
+}
+
+impl Workload {
+    // 按设置准备工作内容；URL、-H 和 --data 中有占位符时每个请求单独渲染
+    fn build(url: &str, options: &BenchOptions) -> Result<Self> {
+        let feed = options.feed.clone().unwrap_or_default();
+        if let Some(scenario) = &options.scenario {
+            return Ok(Self::Scenario(Arc::new(ScenarioWorkload {
+                scenario: Arc::clone(scenario),
+                base: url.to_string(),
+                feed,
+                expect: Arc::clone(&options.expect),
+            })));
+        }
+
+        let template = RequestTemplate {
+            method: options.method.clone(),
+            url: Template::parse(url)?,
+            headers: templated_headers(&options.headers)
+                .map(Template::parse)
+                .collect::<Result<_>>()?,
+            body: options
+                .body
+                .as_ref()
+                .and_then(|body| std::str::from_utf8(body).ok())
+                .map(Template::parse)
+                .transpose()?
+                .filter(|body| !body.is_static()),
+            feed,
+            expect: Arc::clone(&options.expect),
+        };
+        let templates = || std::iter::once(&template.url).chain(&template.headers).chain(&template.body);
+        if templates().all(Template::is_static) {
+            return Ok(Self::Request(Arc::new(BenchRequest {
+                method: options.method.clone(),
+                url: url.to_string(),
+                headers: Vec::new(),
+                body: options.body.clone(),
+                capture_body: false,
+                expect: template.expect,
+            })));
+        }
+        let columns = template.feed.columns();
+        if let Some(name) = templates().flat_map(Template::variables).find(|name| !columns.iter().any(|c| c == name)) {
+            return Err(anyhow!(
+                "Unknown placeholder {{{{{}}}}}: not a --feed column or a generator (uuid, seq, randint LOW HIGH)",
+                name
+            ));
+        }
+        Ok(Self::Template(Arc::new(template)))
+    }
+
+    fn stats(&self, stages: &[Stage], start: Instant) -> BenchmarkStats {
+        let stats = BenchmarkStats::new(stages, start);
+        match self {
+            Self::Scenario(workload) => stats.with_flows(&workload.scenario),
+            _ => stats,
+        }
+    }
+
+    // 单请求模式下的下一个请求；数据用完或渲染失败时返回 None
+    fn next_request(&self, state: &RunState) -> Option<Cow<'_, BenchRequest>> {
+        let template = match self {
+            Self::Request(request) => return Some(Cow::Borrowed(request)),
+            Self::Template(template) => template,
+            Self::Scenario(_) => return None,
+        };
+        let Some((variables, seq)) = template.feed.next_values() else {
+            state.exhaust();
+            return None;
+        };
+        let render = || -> Result<BenchRequest> {
+            Ok(BenchRequest {
+                method: template.method.clone(),
+                url: template.url.render(&variables, seq)?,
+                headers: template
+                    .headers
+                    .iter()
+                    .map(|header| parse_header(&header.render(&variables, seq)?))
+                    .collect::<Result<_>>()?,
+                body: template
+                    .body
+                    .as_ref()
+                    .map(|body| body.render(&variables, seq).map(Bytes::from))
+                    .transpose()?,
+                capture_body: false,
+                expect: Arc::clone(&template.expect),
+            })
+        };
+        match render() {
+            Ok(request) => Some(Cow::Owned(request)),
+            Err(e) => {
+                log_warn(&format!("Failed to render request: {}", e));
+                None
+            }
+        }
+    }
+
+    async fn run_once(&self, pool: &ClientPool, state: &RunState, stats: &mut BenchmarkStats, stage: Option<usize>) {
+        if let Self::Scenario(workload) = self {
+            return run_flow(pool, workload, state, stats, stage).await;
+        }
+        let Some(request) = self.next_request(state) else {
+            return;
+        };
+        let request_start = Instant::now();
+        let transfer = send_request(pool, &request, state).await;
+        let sample = Sample::new(request_start.elapsed(), None, transfer);
+        state.record(&sample);
+        stats.record(sample, stage);
+    }
+}
+
+// 含占位符的 -H 请求头每次单独渲染，其余的作为客户端默认请求头
+fn templated_headers(headers: &[String]) -> impl Iterator<Item = &str> {
+    headers.iter().map(String::as_str).filter(|header| header.contains("{{"))
+}
+
+// 执行一次流程：依次发送各步骤，提取变量供后续步骤使用；某一步失败时放弃流程的剩余步骤
+async fn run_flow(
+    pool: &ClientPool,
+    workload: &ScenarioWorkload,
+    state: &RunState,
+    stats: &mut BenchmarkStats,
+    stage: Option<usize>,
+) {
+    // 每次流程使用一行数据
+    let ScenarioWorkload { scenario, base, feed, expect } = workload;
+    let Some((mut variables, seq)) = feed.next_values() else {
+        state.exhaust();
+        return;
+    };
+    let flow_index = scenario.pick_flow();
+    let flow = &scenario.flows[flow_index];
+    let mut busy = Duration::ZERO;
+
+    for (step_index, step) in flow.steps.iter().enumerate() {
+        // 中止时不统计未完成的流程
+        if state.aborted() {
+            return;
+        }
+        let rendered = match step.render(base, &variables, seq) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                log_warn(&format!("Flow '{}', step '{}': {}", flow.name, step.name, e));
+                stats.record_flow(flow_index, busy, false);
+                return;
+            }
+        };
+        let request = BenchRequest {
+            method: step.method.clone(),
+            url: rendered.url,
+            headers: rendered.headers,
+            body: rendered.body.map(Bytes::from),
+            capture_body: step.extracts(),
+            expect: Arc::clone(expect),
+        };
+
+        let request_start = Instant::now();
+        let mut transfer = send_request(pool, &request, state).await;
+        if transfer.succeeded() {
+            let content = transfer.content.take().unwrap_or_default();
+            if let Err(e) = step.extract(&content, &mut variables) {
+                transfer.error = Some((ErrorKind::Scenario, format!("{}: {}", step.name, e)));
+            }
+        }
+        let sample = Sample::new(request_start.elapsed(), None, transfer);
+        let succeeded = sample.transfer.succeeded();
+        busy += sample.latency;
+        state.record(&sample);
+        stats.record_step(flow_index, step_index, &sample);
+        stats.record(sample, stage);
+        if !succeeded {
+            stats.record_flow(flow_index, busy, false);
+            return;
+        }
+        if let Some(think_time) = step.think_time {
+            tokio::time::sleep(think_time).await;
+        }
+    }
+    stats.record_flow(flow_index, busy, true);
+}
+
+/// 压测阶段：在 duration 内把并发数线性调整到 target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stage {
+    pub duration: Duration,
+    pub target: usize,
+}
+
+impl Stage {
+    /// 解析 "30s:10,60s:50,30s:0"
+    pub fn parse_list(value: &str) -> Result<Vec<Self>> {
+        value
+            .split(',')
+            .map(|stage| {
+                let (duration, target) = stage
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("Invalid stage '{}', expected DURATION:CONCURRENCY", stage))?;
+                Ok(Self {
+                    duration: parse_duration(duration)?,
+                    target: target
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid concurrency '{}' in stage '{}'", target, stage))?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn total_duration(stages: &[Self]) -> Duration {
+        stages.iter().map(|s| s.duration).sum()
+    }
+
+    /// 某一时刻所处的阶段及目标并发数（从 0 开始线性变化）
+    pub fn concurrency_at(stages: &[Self], elapsed: Duration) -> Option<(usize, usize)> {
+        let mut stage_start = Duration::ZERO;
+        let mut previous = 0;
+        for (index, stage) in stages.iter().enumerate() {
+            let stage_end = stage_start + stage.duration;
+            if elapsed < stage_end {
+                let progress = (elapsed - stage_start).as_secs_f64() / stage.duration.as_secs_f64();
+                let concurrency = previous as f64 + (stage.target as f64 - previous as f64) * progress;
+                return Some((index, concurrency.round() as usize));
+            }
+            stage_start = stage_end;
+            previous = stage.target;
+        }
+        None
+    }
+}
+
+/// 读取请求体：@path 读取文件，否则按字面内容
+pub fn load_body(value: &str) -> Result<Bytes> {
+    match value.strip_prefix('@') {
+        Some(path) => fs::read(path)
+            .map(Bytes::from)
+            .with_context(|| format!("Failed to read request body from {}", path)),
+        None => Ok(Bytes::copy_from_slice(value.as_bytes())),
+    }
+}
+
+/// 解析时长：500ms、30s、5m、1h，纯数字按秒
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration '{}', expected e.g. 30s, 5m or 1h", value))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(anyhow!("Invalid duration unit '{}' in '{}' (use ms, s, m or h)", unit, value)),
+    };
+    if seconds <= 0.0 {
+        return Err(anyhow!("Duration must be positive: {}", value));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// 解析请求速率：500、500/s、3000/m、10000/h（请求/秒）
+pub fn parse_request_rate(value: &str) -> Result<f64> {
+    let value = value.trim();
+    let (number, per) = value.split_once('/').unwrap_or((value, "s"));
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid rate '{}', expected e.g. 500/s", value))?;
+    let rate = match per.trim() {
+        "s" => number,
+        "m" => number / 60.0,
+        "h" => number / 3600.0,
+        other => return Err(anyhow!("Invalid rate unit '/{}' in '{}' (use /s, /m or /h)", other, value)),
+    };
+    if rate <= 0.0 {
+        return Err(anyhow!("Rate must be positive: {}", value));
+    }
+    Ok(rate)
+}
+
+pub async fn benchmark_url(url: &str, options: &BenchOptions) -> Result<()> {
+    log_info(&format!(
+        "Starting benchmark - URL: {}, requests: {}, concurrency: {}, duration: {:?}, rate: {:?}",
+        url, options.requests, options.concurrency, options.duration, options.rate
+    ));
+    if !options.variants.is_empty() {
+        return compare_variants(options).await;
+    }
+    let pool = build_pool(options, options.protocol())?;
+    let workload = Workload::build(url, options)?;
+    // GET 以外的方法在标题中显示出来；场景模式下 -n 计的是流程数
+    let (target, unit) = match &options.scenario {
+        Some(scenario) => (format!("{} (scenario with {} flows)", url, scenario.flows.len()), "flows"),
+        None if options.method == Method::GET => (url.to_string(), "requests"),
+        None => (format!("{} {}", options.method, url), "requests"),
+    };
+
+    let text = options.output_format == OutputFormat::Text;
+    match (options.rate, options.duration) {
+        _ if !text => {}
+        _ if !options.stages.is_empty() => println!(
+            "Benchmarking {} in {} stages over {:.0}s, peak concurrency {} (HTTP/3: {})",
+            target,
+            options.stages.len(),
+            Stage::total_duration(&options.stages).as_secs_f64(),
+            options.stages.iter().map(|s| s.target).max().unwrap_or(0),
+            options.http3
+        ),
+        (Some(rate), Some(duration)) => println!(
+            "Benchmarking {} at {:.2} req/s for {:.0}s, max {} in flight (HTTP/3: {})",
+            target, rate, duration.as_secs_f64(), options.concurrency, options.http3
+        ),
+        (Some(rate), None) => println!(
+            "Benchmarking {} with {} requests at {:.2} req/s, max {} in flight (HTTP/3: {})",
+            target, options.requests, rate, options.concurrency, options.http3
+        ),
+        (None, Some(duration)) => println!(
+            "Benchmarking {} for {:.0}s, concurrency {} (HTTP/3: {})",
+            target, duration.as_secs_f64(), options.concurrency, options.http3
+        ),
+        (None, None) => println!(
+            "Benchmarking {} with {} {}, concurrency {} (HTTP/3: {})",
+            target, options.requests, unit, options.concurrency, options.http3
+        ),
+    }
+
+    if let (true, Some(feed)) = (text, &options.feed) {
+        println!("Feeding {} rows from the data file ({} order)", feed.row_count(), feed.strategy());
+    }
+    match options.connections {
+        _ if !text => {}
+        ConnectionLimits { no_keepalive: true, .. } => println!("Opening a new connection for every request"),
+        ConnectionLimits { max_connections: Some(connections), max_streams, .. } => println!(
+            "Using at most {} connections, {} request(s) at a time on each",
+            connections,
+            max_streams.unwrap_or(1)
+        ),
+        _ => {}
+    }
+
+    // 预热：使用同一个客户端（连接池保持预热状态），结果丢弃
+    if let Some(warmup) = options.warmup {
+        if text {
+            println!("Warming up for {:.0}s (results discarded)", warmup.as_secs_f64());
+        }
+        log_info(&format!("Benchmark warm-up for {:?}", warmup));
+        let warmup_options = BenchOptions {
+            duration: Some(warmup),
+            stages: Vec::new(),
+            ..options.clone()
+        };
+        let state = Arc::new(RunState::new(options.concurrency, None));
+        run_phase(&pool, &workload, &warmup_options, &state, Instant::now()).await;
+    }
+
+    // 面板通过通道接收每个完成的请求
+    let (live, receiver) = if options.tui {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Some(sender), Some(receiver))
+    } else {
+        (None, None)
+    };
+    let state = Arc::new(RunState::new(options.concurrency, live));
+    let duration = if options.stages.is_empty() {
+        options.duration
+    } else {
+        Some(Stage::total_duration(&options.stages))
+    };
+    progress::emit_bench(ProgressEvent::BenchStarted {
+        url,
+        variant: None,
+        requests: if duration.is_some() { 0 } else { options.requests },
+        concurrency: options.concurrency,
+        http3: options.http3,
+        duration_ms: duration.map(|d| d.as_millis() as u64),
+        rate: options.rate,
+    });
+
+    let start = Instant::now();
+    let reporter = spawn_progress_reporter(url, None, &state, start);
+    let dashboard = receiver.map(|receiver| {
+        let config = DashboardConfig {
+            title: target.clone(),
+            requests: (duration.is_none() && options.scenario.is_none()).then_some(options.requests),
+            duration,
+            adjustable: options.stages.is_empty(),
+        };
+        let state = Arc::clone(&state);
+        tokio::task::spawn_blocking(move || run_dashboard(config, state, receiver, start))
+    });
+
+    let (stats, rate_outcome) = run_phase(&pool, &workload, options, &state, start).await;
+    let total_time = start.elapsed();
+
+    state.finish();
+    if let Some(reporter) = reporter {
+        reporter.abort();
+    }
+    if let Some(dashboard) = dashboard {
+        dashboard.await.context("Benchmark dashboard failed")??;
+    }
+    if state.exhausted() {
+        log_info("Benchmark stopped: feed data exhausted");
+        if text {
+            println!("Feed data exhausted, stopped after every row was used once");
+        }
+    } else if state.aborted() {
+        log_warn("Benchmark aborted from the dashboard");
+        if text {
+            println!("Benchmark aborted, results cover the requests completed so far");
+        }
+    }
+    let report = stats.report(url, total_time, options.rate, rate_outcome);
+    match options.output_format {
+        OutputFormat::Text => {
+            stats.print_results(total_time);
+            if let (Some(target), Some(outcome)) = (options.rate, rate_outcome) {
+                print_rate_summary(target, outcome);
+            }
+        }
+        OutputFormat::Json => println!("{}", report.to_json()?),
+        OutputFormat::Csv => print!("{}", report.to_csv()),
+    }
+    if let Some(path) = &options.timeline {
+        write_timeline(path, &stats.timeline)?;
+        log_info(&format!("Wrote benchmark timeline to {}", path.display()));
+    }
+    progress::emit_bench(state.progress_event(url, None, total_time, true));
+
+    log_info(&format!(
+        "Benchmark completed - Total: {:.2}s, RPS: {:.2}, Success: {}, Failed: {}",
+        total_time.as_secs_f64(),
+        stats.completed() as f64 / total_time.as_secs_f64(),
+        stats.successful_requests,
+        stats.failed_requests
+    ));
+
+    check_regressions(&report, options, text)
+}
+
+// 关键修改：使用 ClientType::Benchmark，设置 60 秒总超时。
+// protocol 为 None 时按 URL 和 ALPN 协商 HTTP 版本
+fn build_pool(options: &BenchOptions, protocol: Option<Protocol>) -> Result<ClientPool> {
+    ClientPool::new(
+        || {
+            let builder = client_builder(
+                options.follow_redirects,
+                options.connect_timeout,
+                protocol == Some(Protocol::Http3),
+                options.headers.iter().filter(|header| !header.contains("{{")).cloned().collect(),
+                ClientType::Benchmark,
+            )?;
+            Ok(match protocol {
+                Some(Protocol::Http1) => builder.http1_only(),
+                Some(Protocol::Http2) => builder.http2_prior_knowledge(),
+                _ => builder,
+            })
+        },
+        options.connections,
+    )
+}
+
+// 对比压测：各方各用自己的连接池，请求在各方之间轮流发送，
+// 这样服务端负载和网络状况的变化对各方的影响相同
+async fn compare_variants(options: &BenchOptions) -> Result<()> {
+    let pools = options
+        .variants
+        .iter()
+        .map(|variant| build_pool(options, variant.protocol.or(options.protocol())))
+        .collect::<Result<Vec<_>>>()?;
+    let text = options.output_format == OutputFormat::Text;
+    if text {
+        let labels: Vec<&str> = options.variants.iter().map(|variant| variant.label.as_str()).collect();
+        match options.duration {
+            Some(duration) => println!(
+                "Comparing {} for {:.0}s, requests interleaved, concurrency {}",
+                labels.join(", "),
+                duration.as_secs_f64(),
+                options.concurrency
+            ),
+            None => println!(
+                "Comparing {} with {} requests each, interleaved, concurrency {}",
+                labels.join(", "),
+                options.requests,
+                options.concurrency
+            ),
+        }
+    }
+
+    if let Some(warmup) = options.warmup {
+        if text {
+            println!("Warming up for {:.0}s (results discarded)", warmup.as_secs_f64());
+        }
+        let warmup_options = BenchOptions { duration: Some(warmup), ..options.clone() };
+        run_comparison(&compare_targets(options, &pools)?, &warmup_options, Instant::now()).await;
+    }
+
+    let targets = compare_targets(options, &pools)?;
+    for variant in &options.variants {
+        progress::emit_bench(ProgressEvent::BenchStarted {
+            url: &variant.url,
+            variant: Some(&variant.label),
+            requests: if options.duration.is_some() { 0 } else { options.requests },
+            concurrency: options.concurrency,
+            http3: variant.protocol.or(options.protocol()) == Some(Protocol::Http3),
+            duration_ms: options.duration.map(|d| d.as_millis() as u64),
+            rate: None,
+        });
+    }
+    let start = Instant::now();
+    let reporters: Vec<_> = options
+        .variants
+        .iter()
+        .zip(targets.iter())
+        .filter_map(|(variant, target)| {
+            spawn_progress_reporter(&variant.url, Some(&variant.label), &target.state, start)
+        })
+        .collect();
+    let stats = run_comparison(&targets, options, start).await;
+    let total_time = start.elapsed();
+    reporters.iter().for_each(|reporter| reporter.abort());
+    for (variant, target) in options.variants.iter().zip(targets.iter()) {
+        target.state.finish();
+        progress::emit_bench(target.state.progress_event(&variant.url, Some(&variant.label), total_time, true));
+    }
+    if targets.iter().any(|target| target.state.exhausted()) && text {
+        println!("Feed data exhausted, stopped after every row was used once");
+    }
+
+    let results = options
+        .variants
+        .iter()
+        .zip(&stats)
+        .map(|(variant, stats)| VariantResult {
+            variant: variant.clone(),
+            report: stats.report(&variant.url, total_time, None, None),
+            successful: stats.successful_latency,
+        })
+        .collect();
+    let report = CompareReport::new(results);
+    match options.output_format {
+        OutputFormat::Json => println!("{}", report.to_json()?),
+        _ => report.print(),
+    }
+    log_info(&format!("Comparison completed in {:.2}s", total_time.as_secs_f64()));
+    Ok(())
+}
+
+// 对比压测的一方：连接池、工作内容和这一方的运行状态
+struct CompareTarget {
+    pool: ClientPool,
+    workload: Workload,
+    state: Arc<RunState>,
+}
+
+// 每一方各用一份从头开始的数据，取到的行序列相同；各方的计数分开，用于进度事件
+fn compare_targets(options: &BenchOptions, pools: &[ClientPool]) -> Result<Arc<[CompareTarget]>> {
+    options
+        .variants
+        .iter()
+        .zip(pools)
+        .map(|(variant, pool)| {
+            let feed = options.feed.as_ref().map(|feed| Arc::new(feed.restarted()));
+            Ok(CompareTarget {
+                pool: pool.clone(),
+                workload: Workload::build(&variant.url, &BenchOptions { feed, ..options.clone() })?,
+                state: Arc::new(RunState::new(options.concurrency, None)),
+            })
+        })
+        .collect()
+}
+
+// 对比压测的工作协程：第 i 个请求发给第 i % N 方，各方的请求数最多相差一个。
+// 任何一方中止（例如顺序数据用完）时所有方一起停下
+async fn run_comparison(targets: &Arc<[CompareTarget]>, options: &BenchOptions, start: Instant) -> Vec<BenchmarkStats> {
+    let deadline = options.duration.map(|d| start + d);
+    let total = options.requests * targets.len();
+    let issued = Arc::new(AtomicUsize::new(0));
+    let mut tasks = JoinSet::new();
+
+    for _ in 0..options.concurrency {
+        let targets = Arc::clone(targets);
+        let issued = Arc::clone(&issued);
+        tasks.spawn(async move {
+            let mut stats: Vec<_> = targets.iter().map(|target| target.workload.stats(&[], start)).collect();
+            loop {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                    || targets.iter().any(|target| target.state.aborted())
+                {
+                    break;
+                }
+                let i = issued.fetch_add(1, Ordering::Relaxed);
+                if deadline.is_none() && i >= total {
+                    break;
+                }
+                let index = i % targets.len();
+                let target = &targets[index];
+                target.workload.run_once(&target.pool, &target.state, &mut stats[index], None).await;
+            }
+            stats
+        });
+    }
+
+    let mut merged: Vec<_> = targets.iter().map(|target| target.workload.stats(&[], start)).collect();
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(stats) => merged.iter_mut().zip(&stats).for_each(|(merged, stats)| merged.merge(stats)),
+            Err(e) => log_warn(&format!("Benchmark task failed: {}", e)),
+        }
+    }
+    merged
+}
+
+// 与基线对比并判定 --fail-if 条件；机器可读格式下说明写到 stderr，不混入结果
+fn check_regressions(report: &BenchReport, options: &BenchOptions, text: bool) -> Result<()> {
+    let print = |line: String| if text { println!("{}", line) } else { eprintln!("{}", line) };
+    let baseline = options.baseline.as_deref().map(BenchReport::load).transpose()?;
+
+    if let (Some(baseline), Some(path)) = (&baseline, &options.baseline) {
+        print(String::new());
+        print(format!("Compared with baseline ({}):", path.display()));
+        for (name, current, previous, change) in compare(report, baseline) {
+            let change = change.map(|c| format!("{:+.1}%", c * 100.0)).unwrap_or_else(|| "n/a".to_string());
+            print(format!("  {:<7} {:>16} (was {}, {})", format!("{}:", name), current, previous, change));
+        }
+    }
+    if options.fail_if.is_empty() {
+        return Ok(());
+    }
+
+    print(String::new());
+    print("Regression checks:".to_string());
+    let mut failures = Vec::new();
+    for threshold in &options.fail_if {
+        let (passed, message) = threshold.check(report, baseline.as_ref())?;
+        print(format!("  [{}] {}", if passed { "PASS" } else { "FAIL" }, message));
+        if !passed {
+            failures.push(message);
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        log_warn(&format!("Benchmark regression: {}", failures.join("; ")));
+        Err(anyhow!("Benchmark regression: {}", failures.join("; ")))
+    }
+}
+
+/// 恒定速率模式的发送结果
+#[derive(Debug, Clone, Copy)]
+struct RateOutcome {
+    /// 计划窗口内实际发出的速率（请求/秒）
+    achieved: f64,
+    /// 因积压过多或到了结束时间而没有发送的请求
+    dropped: usize,
+}
+
+// 按设置选择负载模型，返回合并后的统计；恒定速率模式同时返回实际发送速率
+async fn run_phase(
+    pool: &ClientPool,
+    workload: &Workload,
+    options: &BenchOptions,
+    state: &Arc<RunState>,
+    start: Instant,
+) -> (BenchmarkStats, Option<RateOutcome>) {
+    match (workload, options.rate) {
+        _ if !options.stages.is_empty() => (run_stages(pool, workload, &options.stages, state, start).await, None),
+        // 场景不支持恒定速率（命令行参数互斥）
+        (Workload::Request(_) | Workload::Template(_), Some(rate)) => {
+            let (stats, outcome) = run_open_model(pool, workload, options, rate, state, start).await;
+            (stats, Some(outcome))
+        }
+        _ => (run_closed_model(pool, workload, options, state, start).await, None),
+    }
+}
+
+// 分阶段：按峰值并发启动工作协程，第 k 个协程只在当前目标并发数大于 k 时发送请求
+async fn run_stages(
+    pool: &ClientPool,
+    workload: &Workload,
+    stages: &[Stage],
+    state: &Arc<RunState>,
+    start: Instant,
+) -> BenchmarkStats {
+    let peak = stages.iter().map(|s| s.target).max().unwrap_or(0);
+    let shared_stages: Arc<[Stage]> = stages.into();
+    let mut tasks = JoinSet::new();
+
+    for worker in 0..peak {
+        let pool = pool.clone();
+        let workload = workload.clone();
+        let state = Arc::clone(state);
+        let stages = Arc::clone(&shared_stages);
+        tasks.spawn(async move {
+            let mut stats = workload.stats(&stages, start);
+            while let Some((stage, concurrency)) = Stage::concurrency_at(&stages, start.elapsed()) {
+                if state.aborted() {
+                    break;
+                }
+                if worker == 0 {
+                    state.concurrency.store(concurrency, Ordering::Relaxed);
+                }
+                if state.idle(worker, concurrency).await {
+                    continue;
+                }
+                workload.run_once(&pool, &state, &mut stats, Some(stage)).await;
+            }
+            stats
+        });
+    }
+
+    let mut merged = workload.stats(stages, start);
+    while let Some(result) = tasks.join_next().await {
+        merge_worker(&mut merged, result);
+    }
+    merged
+}
+
+// 封闭模型：固定数量的并发连接，一个请求完成后才发下一个。
+// 工作协程按需启动，TUI 中调高并发数时再补充
+async fn run_closed_model(
+    pool: &ClientPool,
+    workload: &Workload,
+    options: &BenchOptions,
+    state: &Arc<RunState>,
+    start: Instant,
+) -> BenchmarkStats {
+    // 按时长时每个连接循环到时间用完；按数量时各连接从共享计数中领取请求（场景中为流程）
+    let deadline = options.duration.map(|d| start + d);
+    let requests = options.requests;
+    let issued = Arc::new(AtomicUsize::new(0));
+    let mut tasks = JoinSet::new();
+    let mut spawned = 0;
+    let mut merged = workload.stats(&[], start);
+
+    loop {
+        while spawned < state.concurrency() {
+            let worker = spawned;
+            let pool = pool.clone();
+            let workload = workload.clone();
+            let state = Arc::clone(state);
+            let issued = Arc::clone(&issued);
+            tasks.spawn(async move {
+                let mut stats = workload.stats(&[], start);
+                loop {
+                    let finished = match deadline {
+                        Some(deadline) => Instant::now() >= deadline,
+                        None => issued.load(Ordering::Relaxed) >= requests,
+                    };
+                    if finished || state.aborted() {
+                        break;
+                    }
+                    if state.idle(worker, state.concurrency()).await {
+                        continue;
+                    }
+                    if deadline.is_none() {
+                        let i = issued.fetch_add(1, Ordering::Relaxed);
+                        if i >= requests {
+                            break;
+                        }
+                        if (i + 1).is_multiple_of(50) {
+                            log_debug(&format!("Started {} requests", i + 1));
+                        }
+                    }
+                    workload.run_once(&pool, &state, &mut stats, None).await;
+                }
+                stats
+            });
+            spawned += 1;
+        }
+
+        tokio::select! {
+            result = tasks.join_next() => match result {
+                Some(result) => merge_worker(&mut merged, result),
+                None => break,
+            },
+            _ = state.changed.notified() => {}
+        }
+    }
+    merged
+}
+
+// 开放模型：按恒定速率安排请求，不等待前一个请求完成。
+// 延迟从计划发送时间开始计算（修正协调遗漏），排队等待也计入延迟。
+// 请求任务只返回测量结果，由调度协程记录到自己的直方图中。
+// 返回统计和实际发送速率（请求/秒）
+async fn run_open_model(
+    pool: &ClientPool,
+    workload: &Workload,
+    options: &BenchOptions,
+    rate: f64,
+    state: &Arc<RunState>,
+    start: Instant,
+) -> (BenchmarkStats, RateOutcome) {
+    let mut permits = state.concurrency();
+    let semaphore = Arc::new(Semaphore::new(permits));
+    let sent = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let backlog = ((rate * MAX_BACKLOG.as_secs_f64()).ceil() as usize).max(MIN_BACKLOG);
+    let mut stats = BenchmarkStats::new(&[], start);
+    let mut tasks = JoinSet::new();
+
+    let deadline = options.duration.map(|d| start + d);
+    let total = if options.duration.is_some() { usize::MAX } else { options.requests };
+    // 暂停后从恢复时刻重新排期，避免恢复时把积压的请求一次发出
+    let mut origin = start;
+    let mut origin_index = 0;
+
+    for i in 0..total {
+        if state.paused() {
+            while state.paused() && !state.aborted() {
+                tokio::time::sleep(IDLE_POLL).await;
+            }
+            origin = Instant::now();
+            origin_index = i;
+        }
+        if state.aborted() {
+            break;
+        }
+        let scheduled = origin + Duration::from_secs_f64((i - origin_index) as f64 / rate);
+        if deadline.is_some_and(|deadline| scheduled >= deadline) {
+            break;
+        }
+        tokio::time::sleep_until(scheduled.into()).await;
+
+        // TUI 中调整了进行中请求的上限
+        let target = state.concurrency();
+        if target > permits {
+            semaphore.add_permits(target - permits);
+            permits = target;
+        } else if target < permits {
+            permits -= semaphore.forget_permits(permits - target);
+        }
+
+        // 及时回收已完成的任务；目标饱和时排队的请求超过上限就不再发送，避免任务无限堆积
+        while let Some(result) = tasks.try_join_next() {
+            record_open_sample(&mut stats, result);
+        }
+        if tasks.len() >= permits + backlog {
+            dropped.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+
+        let pool = pool.clone();
+        let workload = workload.clone();
+        let semaphore = Arc::clone(&semaphore);
+        let state = Arc::clone(state);
+        let sent = Arc::clone(&sent);
+        let dropped = Arc::clone(&dropped);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire().await.ok()?;
+            if state.aborted() {
+                return None;
+            }
+            // 到了结束时间还在排队的请求不再发送，压测不会在 --duration 之后继续施压
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                dropped.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            let request = workload.next_request(&state)?;
+            sent.fetch_add(1, Ordering::Relaxed);
+            let request_start = Instant::now();
+            let transfer = send_request(&pool, &request, &state).await;
+            let sample = Sample::new(scheduled.elapsed(), Some(request_start.elapsed()), transfer);
+            state.record(&sample);
+            Some(sample)
+        });
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        record_open_sample(&mut stats, result);
+    }
+
+    // 实际速率按计划窗口内真正发出的请求计算；并发上限饱和时请求排队，速率随之下降
+    let window = deadline.map(|d| d - start).unwrap_or_else(|| start.elapsed());
+    let outcome = RateOutcome {
+        achieved: sent.load(Ordering::Relaxed) as f64 / window.as_secs_f64().max(0.001),
+        dropped: dropped.load(Ordering::Relaxed),
+    };
+    (stats, outcome)
+}
+
+fn record_open_sample(stats: &mut BenchmarkStats, result: Result<Option<Sample>, tokio::task::JoinError>) {
+    match result {
+        Ok(Some(sample)) => stats.record(sample, None),
+        Ok(None) => {}
+        Err(e) => log_warn(&format!("Benchmark task failed: {}", e)),
+    }
+}
+
+// 合并一个已结束工作协程的统计
+fn merge_worker(merged: &mut BenchmarkStats, result: Result<BenchmarkStats, tokio::task::JoinError>) {
+    match result {
+        Ok(stats) => merged.merge(&stats),
+        Err(e) => log_warn(&format!("Benchmark task failed: {}", e)),
+    }
+}
+
+// 发送请求并把响应体读完（内容直接丢弃），这样耗时包含完整的传输。
+// 限制了连接数时先等到有连接空出名额，等待不算进行中的请求
+async fn send_request(pool: &ClientPool, request: &BenchRequest, state: &RunState) -> Transfer {
+    let lease = pool.acquire().await;
+    state.in_flight.fetch_add(1, Ordering::Relaxed);
+    let transfer = perform_request(&lease, request).await;
+    state.in_flight.fetch_sub(1, Ordering::Relaxed);
+    transfer
+}
+
+async fn perform_request(lease: &Lease<'_>, request: &BenchRequest) -> Transfer {
+    let start = Instant::now();
+    let mut builder = lease.client().request(request.method.clone(), &request.url);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = &request.body {
+        builder = builder.body(body.clone());
+    }
+    let mut transfer = Transfer {
+        status_code: None,
+        ttfb: None,
+        connection: None,
+        bytes: 0,
+        content: None,
+        accepted: false,
+        error: None,
+    };
+    let mut content = (request.capture_body || request.expect.needs_body()).then(Vec::new);
+    let mut response = match builder.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            transfer.error = Some(classify_error(&e, false));
+            return transfer;
+        }
+    };
+    transfer.ttfb = Some(start.elapsed());
+    transfer.connection = lease.connection(&response);
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                transfer.bytes += chunk.len() as u64;
+                if let Some(content) = &mut content {
+                    content.extend_from_slice(&chunk);
+                }
+                // 超过 --max-body-size 后不再读取，丢弃响应即关闭连接
+                if let Some(max) = request.expect.max_body_size.filter(|&max| transfer.bytes > max) {
+                    let code = response.status().as_u16();
+                    transfer.status_code = Some(code);
+                    transfer.accepted = request.expect.status_ok(code);
+                    if transfer.accepted || (200..400).contains(&code) {
+                        transfer.error =
+                            Some((ErrorKind::Validation, format!("body is more than {} bytes, stopped reading", max)));
+                    }
+                    return transfer;
+                }
+            }
+            Ok(None) => {
+                let code = response.status().as_u16();
+                transfer.status_code = Some(code);
+                transfer.accepted = request.expect.status_ok(code);
+                // 不在预期中的 4xx/5xx 仍按状态码失败计，不算校验失败
+                if transfer.accepted || (200..400).contains(&code) {
+                    if let Err(reason) = request.expect.check(code, transfer.bytes, content.as_deref()) {
+                        transfer.error = Some((ErrorKind::Validation, reason));
+                    }
+                }
+                transfer.content = content.filter(|_| request.capture_body);
+                return transfer;
+            }
+            // 响应体中途断开按失败请求计
+            Err(e) => {
+                log_debug(&format!("Failed to read response body: {}", e));
+                transfer.error = Some(classify_error(&e, true));
+                return transfer;
+            }
+        }
+    }
+}
+
+// 按底层原因给请求错误分类，返回分类和完整的错误链
+fn classify_error(error: &reqwest::Error, reading_body: bool) -> (ErrorKind, String) {
+    let mut message = error.to_string();
+    let mut io_kind = None;
+    let mut source = error.source();
+    while let Some(cause) = source {
+        // hyper 的错误信息里往往已经带着底层原因，避免重复
+        let cause_message = cause.to_string();
+        if !message.contains(&cause_message) {
+            message.push_str(": ");
+            message.push_str(&cause_message);
+        }
+        if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+            io_kind.get_or_insert(io_error.kind());
+        }
+        source = cause.source();
+    }
+    let kind = classify_parts(error.is_connect(), error.is_timeout(), reading_body, io_kind, &message);
+    (kind, message)
+}
+
+fn classify_parts(
+    connect: bool,
+    timeout: bool,
+    reading_body: bool,
+    io_kind: Option<io::ErrorKind>,
+    message: &str,
+) -> ErrorKind {
+    let message = message.to_ascii_lowercase();
+    let tls = ["tls", "certificate", "handshake", "corrupt message"].iter().any(|s| message.contains(s));
+    let closed = message.contains("connection closed before message completed")
+        || matches!(
+            io_kind,
+            Some(
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            )
+        );
+
+    if connect {
+        if message.contains("dns error") || message.contains("failed to lookup address") {
+            ErrorKind::Dns
+        } else if timeout || io_kind == Some(io::ErrorKind::TimedOut) {
+            ErrorKind::ConnectTimeout
+        } else if io_kind == Some(io::ErrorKind::ConnectionRefused) {
+            ErrorKind::ConnectRefused
+        } else if tls || io_kind == Some(io::ErrorKind::InvalidData) {
+            // rustls 的握手错误包装成 InvalidData 类型的 io::Error
+            ErrorKind::Tls
+        } else {
+            ErrorKind::Other
+        }
+    } else if timeout {
+        ErrorKind::Timeout
+    } else if reading_body {
+        ErrorKind::ResetMidBody
+    } else if tls {
+        ErrorKind::Tls
+    } else if closed {
+        ErrorKind::ConnectionClosed
+    } else {
+        ErrorKind::Other
+    }
+}
+
+fn print_rate_summary(target: f64, outcome: RateOutcome) {
+    let RateOutcome { achieved, dropped } = outcome;
+    let ratio = achieved / target;
+    println!();
+    println!("Target rate: {:.2} req/s", target);
+    println!("Achieved rate: {:.2} req/s ({:.1}% of target)", achieved, ratio * 100.0);
+    if dropped > 0 {
+        println!("Not sent: {} requests (backlog full or queued past the end of the run)", dropped);
+    }
+    if ratio < RATE_WARNING_RATIO {
+        println!("Warning: target rate not reached; requests queued behind the in-flight limit (raise -c) or the client is saturated");
+        log_warn(&format!("Target rate {:.2} req/s not reached, achieved {:.2} req/s", target, achieved));
+    }
+}
+
+// JSON 进度模式下定期输出 progress 事件
+fn spawn_progress_reporter(
+    url: &str,
+    variant: Option<&str>,
+    state: &Arc<RunState>,
+    start: Instant,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if !progress::json_enabled() {
+        return None;
+    }
+    let url = url.to_string();
+    let variant = variant.map(str::to_string);
+    let state = Arc::clone(state);
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            progress::emit_bench(state.progress_event(&url, variant.as_deref(), start.elapsed(), false));
+        }
+    }))
+}
+
+/// 运行中各工作协程共享的状态：成功/失败计数、进行中的请求数，
+/// 以及 TUI 面板下发的暂停、调整并发数和中止指令
+pub struct RunState {
+    successful: AtomicU32,
+    failed: AtomicU32,
+    in_flight: AtomicUsize,
+    concurrency: AtomicUsize,
+    paused: AtomicBool,
+    aborted: AtomicBool,
+    /// 顺序使用的数据行已经用完
+    exhausted: AtomicBool,
+    finished: AtomicBool,
+    /// 目标并发数变化或中止时通知负载调度
+    changed: Notify,
+    /// 每个完成的请求发给 TUI 面板
+    live: Option<mpsc::UnboundedSender<LiveSample>>,
+}
+
+impl RunState {
+    pub fn new(concurrency: usize, live: Option<mpsc::UnboundedSender<LiveSample>>) -> Self {
+        Self {
+            successful: AtomicU32::new(0),
+            failed: AtomicU32::new(0),
+            in_flight: AtomicUsize::new(0),
+            concurrency: AtomicUsize::new(concurrency.max(1)),
+            paused: AtomicBool::new(false),
+            aborted: AtomicBool::new(false),
+            exhausted: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            changed: Notify::new(),
+            live,
+        }
+    }
+
+    fn record(&self, sample: &Sample) {
+        let transfer = &sample.transfer;
+        if transfer.succeeded() {
+            self.successful.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            match (transfer.status_code, &transfer.error) {
+                (_, Some((ErrorKind::Validation, message))) => log_warn(&format!("Validation failed: {}", message)),
+                (_, Some((ErrorKind::Scenario, message))) => log_warn(&format!("Scenario step failed: {}", message)),
+                (Some(code), _) if !transfer.accepted => log_warn(&format!("Request failed with status: {}", code)),
+                _ => {}
+            }
+        }
+        if let Some(live) = &self.live {
+            let _ = live.send(LiveSample {
+                latency: sample.latency,
+                status_code: sample.transfer.status_code,
+                error: sample.transfer.error.as_ref().map(|(kind, _)| *kind),
+                bytes: sample.transfer.bytes,
+                success: sample.transfer.succeeded(),
+            });
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.load(Ordering::Relaxed)
+    }
+
+    /// 调整目标并发数（至少为 1）
+    pub fn set_concurrency(&self, concurrency: usize) {
+        self.concurrency.store(concurrency.max(1), Ordering::Relaxed);
+        self.changed.notify_one();
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// 停止发送新请求，进行中的请求完成后按已有结果出报告
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::Relaxed);
+        self.changed.notify_one();
+    }
+
+    // 数据用完，和中止一样停止发送新请求
+    fn exhaust(&self) {
+        self.exhausted.store(true, Ordering::Relaxed);
+        self.abort();
+    }
+
+    fn exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    // 暂停中或超出目标并发数的工作协程等待一会儿，返回 true 表示这一轮不发送请求
+    async fn idle(&self, worker: usize, concurrency: usize) -> bool {
+        if self.paused() || worker >= concurrency {
+            tokio::time::sleep(IDLE_POLL).await;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn progress_event<'a>(
+        &self,
+        url: &'a str,
+        variant: Option<&'a str>,
+        elapsed: Duration,
+        finished: bool,
+    ) -> ProgressEvent<'a> {
+        let successful = self.successful.load(Ordering::Relaxed);
+        let failed = self.failed.load(Ordering::Relaxed);
+        let completed = (successful + failed) as usize;
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let requests_per_second = completed as f64 / elapsed.as_secs_f64().max(0.001);
+
+        if finished {
+            ProgressEvent::BenchCompleted {
+                url,
+                variant,
+                completed,
+                successful,
+                failed,
+                elapsed_ms,
+                requests_per_second,
+            }
+        } else {
+            ProgressEvent::BenchProgress {
+                url,
+                variant,
+                completed,
+                successful,
+                failed,
+                elapsed_ms,
+                requests_per_second,
+            }
+        }
+    }
+}
+
+// 延迟直方图（微秒），超出上限的值按上限记录
+pub fn new_histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, HISTOGRAM_MAX_MICROS, HISTOGRAM_SIGFIG).expect("valid histogram bounds")
+}
+
+pub fn micros(duration: Duration) -> u64 {
+    duration.as_micros().min(u64::MAX as u128) as u64
+}
+
+pub fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+/// 一次请求的传输结果；没有完整收到响应时 status_code 为 None
+struct Transfer {
+    status_code: Option<u16>,
+    /// 从发送到收到响应头的时间
+    ttfb: Option<Duration>,
+    /// 响应所用的连接；不知道时为 None
+    connection: Option<ConnectionUse>,
+    /// 收到的响应体字节数
+    bytes: u64,
+    /// 响应体内容，只在请求要求保留时有
+    content: Option<Vec<u8>>,
+    /// 状态码符合 --expect-status（未指定时为 2xx/3xx）
+    accepted: bool,
+    /// 失败原因和错误信息
+    error: Option<(ErrorKind, String)>,
+}
+
+impl Transfer {
+    /// 收到预期状态码的完整响应且没有其他失败原因
+    fn succeeded(&self) -> bool {
+        self.error.is_none() && self.accepted
+    }
+}
+
+/// 一次请求的测量结果
+struct Sample {
+    latency: Duration,
+    /// 开放模型下不含排队时间的服务时间
+    service_time: Option<Duration>,
+    transfer: Transfer,
+    finished: Instant,
+}
+
+impl Sample {
+    fn new(latency: Duration, service_time: Option<Duration>, transfer: Transfer) -> Self {
+        Self { latency, service_time, transfer, finished: Instant::now() }
+    }
+}
+
+// 单个阶段的统计
+struct StageStats {
+    stage: Stage,
+    response_times: Histogram<u64>,
+    failed: u32,
+}
+
+// 场景中单个步骤的统计
+struct StepStats {
+    name: String,
+    response_times: Histogram<u64>,
+    failed: u32,
+}
+
+// 场景中单个流程的统计；耗时为各步骤耗时之和，不含思考时间，只统计成功的流程
+struct FlowStats {
+    name: String,
+    weight: u32,
+    durations: Histogram<u64>,
+    iterations: u64,
+    failed: u32,
+    steps: Vec<StepStats>,
+}
+
+/// 压测统计：每个工作协程各有一份，结束后合并，运行中不需要加锁
+struct BenchmarkStats {
+    response_times: Histogram<u64>,
+    /// 开放模型下不含排队时间的服务时间
+    service_times: Histogram<u64>,
+    /// 首字节时间
+    ttfb: Histogram<u64>,
+    /// 新建连接和复用连接上的首字节时间，用来统计连接数和估算握手时间
+    new_connection_ttfb: Histogram<u64>,
+    reused_connection_ttfb: Histogram<u64>,
+    /// 仍打开的连接池连接（按本地地址）及其第一个响应的首字节时间。
+    /// 其他工作协程可能也用过同一个连接，合并时才能确定谁是第一个，所以先不计入 new_connection_ttfb
+    open_connections: HashMap<SocketAddr, u64>,
+    bytes_received: u64,
+    /// 成功请求的延迟，对比压测的显著性检验只用它
+    successful_latency: LatencyMoments,
+    stages: Vec<StageStats>,
+    flows: Vec<FlowStats>,
+    status_codes: BTreeMap<u16, u32>,
+    /// 没有收到完整响应或没通过检查的请求，按原因分类
+    errors: BTreeMap<ErrorKind, ErrorReport>,
+    successful_requests: u32,
+    failed_requests: u32,
+    /// 按请求完成时间（相对 start）每秒一个桶
+    timeline: Vec<TimelineBucket>,
+    start: Instant,
+}
+
+impl BenchmarkStats {
+    fn new(stages: &[Stage], start: Instant) -> Self {
+        Self {
+            response_times: new_histogram(),
+            service_times: new_histogram(),
+            ttfb: new_histogram(),
+            new_connection_ttfb: new_histogram(),
+            reused_connection_ttfb: new_histogram(),
+            open_connections: HashMap::new(),
+            bytes_received: 0,
+            successful_latency: LatencyMoments::default(),
+            stages: stages
+                .iter()
+                .map(|&stage| StageStats { stage, response_times: new_histogram(), failed: 0 })
+                .collect(),
+            flows: Vec::new(),
+            status_codes: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            successful_requests: 0,
+            failed_requests: 0,
+            timeline: Vec::new(),
+            start,
+        }
+    }
+
+    fn with_flows(mut self, scenario: &Scenario) -> Self {
+        self.flows = scenario
+            .flows
+            .iter()
+            .map(|flow| FlowStats {
+                name: flow.name.clone(),
+                weight: flow.weight,
+                durations: new_histogram(),
+                iterations: 0,
+                failed: 0,
+                steps: flow
+                    .steps
+                    .iter()
+                    .map(|step| StepStats { name: step.name.clone(), response_times: new_histogram(), failed: 0 })
+                    .collect(),
+            })
+            .collect();
+        self
+    }
+
+    fn completed(&self) -> usize {
+        (self.successful_requests + self.failed_requests) as usize
+    }
+
+    // 状态码正常但没通过 --expect-* 检查的请求数
+    fn validation_failures(&self) -> u32 {
+        self.errors.get(&ErrorKind::Validation).map_or(0, |error| error.count)
+    }
+
+    // 连接统计；所有响应都不知道来自哪个连接时（HTTP/3）为 None
+    fn connection_report(&self) -> Option<ConnectionReport> {
+        let mut new_connection_ttfb = self.new_connection_ttfb.clone();
+        self.open_connections.values().for_each(|&ttfb| new_connection_ttfb.saturating_record(ttfb));
+        let opened = new_connection_ttfb.len();
+        let reused = self.reused_connection_ttfb.len();
+        if opened == 0 {
+            return None;
+        }
+        let ttfb_new = LatencySummary::from_histogram(&new_connection_ttfb);
+        let ttfb_reused = (reused > 0).then(|| LatencySummary::from_histogram(&self.reused_connection_ttfb));
+        Some(ConnectionReport {
+            opened,
+            reuse_ratio: reused as f64 / (opened + reused) as f64,
+            handshake_ms: ttfb_reused.as_ref().map(|reused| (ttfb_new.p50 - reused.p50).max(0.0)),
+            ttfb_new_ms: ttfb_new,
+            ttfb_reused_ms: ttfb_reused,
+        })
+    }
+
+    fn record(&mut self, sample: Sample, stage: Option<usize>) {
+        let transfer = &sample.transfer;
+        let success = transfer.succeeded();
+        let latency = micros(sample.latency);
+        self.response_times.saturating_record(latency);
+        if let Some(service_time) = sample.service_time {
+            self.service_times.saturating_record(micros(service_time));
+        }
+        if let Some(ttfb) = transfer.ttfb {
+            self.ttfb.saturating_record(micros(ttfb));
+            if let Some(connection) = transfer.connection {
+                self.record_connection(connection, micros(ttfb));
+            }
+        }
+        self.bytes_received += transfer.bytes;
+        if let Some(code) = transfer.status_code {
+            *self.status_codes.entry(code).or_insert(0) += 1;
+        }
+        if let Some((kind, message)) = &sample.transfer.error {
+            self.errors
+                .entry(*kind)
+                .or_insert_with(|| ErrorReport { count: 0, sample: message.clone() })
+                .count += 1;
+        }
+        if success {
+            self.successful_requests += 1;
+            self.successful_latency.record(millis(latency));
+        } else {
+            self.failed_requests += 1;
+        }
+
+        let second = sample.finished.saturating_duration_since(self.start).as_secs() as usize;
+        if self.timeline.len() <= second {
+            self.timeline.resize(second + 1, TimelineBucket::default());
+        }
+        let bucket = &mut self.timeline[second];
+        bucket.merge(&TimelineBucket {
+            requests: 1,
+            failed: u32::from(!success),
+            total_micros: latency,
+            max_micros: latency,
+            bytes: transfer.bytes,
+        });
+
+        if let Some(stats) = stage.and_then(|stage| self.stages.get_mut(stage)) {
+            stats.response_times.saturating_record(latency);
+            if !success {
+                stats.failed += 1;
+            }
+        }
+    }
+
+    fn record_step(&mut self, flow: usize, step: usize, sample: &Sample) {
+        let stats = &mut self.flows[flow].steps[step];
+        stats.response_times.saturating_record(micros(sample.latency));
+        if !sample.transfer.succeeded() {
+            stats.failed += 1;
+        }
+    }
+
+    fn record_flow(&mut self, flow: usize, duration: Duration, success: bool) {
+        let stats = &mut self.flows[flow];
+        stats.iterations += 1;
+        if success {
+            stats.durations.saturating_record(micros(duration));
+        } else {
+            stats.failed += 1;
+        }
+    }
+
+    fn record_connection(&mut self, connection: ConnectionUse, ttfb: u64) {
+        match connection {
+            ConnectionUse::Fresh => self.new_connection_ttfb.saturating_record(ttfb),
+            ConnectionUse::Pooled { local_addr, closing } => {
+                match self.open_connections.entry(local_addr) {
+                    Entry::Occupied(_) => self.reused_connection_ttfb.saturating_record(ttfb),
+                    Entry::Vacant(entry) => {
+                        entry.insert(ttfb);
+                    }
+                }
+                // 关闭的连接不再出现，移出集合；本地端口之后被新连接重用时不会误判为复用
+                if closing {
+                    if let Some(first) = self.open_connections.remove(&local_addr) {
+                        self.new_connection_ttfb.saturating_record(first);
+                    }
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        // 所有直方图的范围和精度相同，合并不会失败
+        self.response_times.add(&other.response_times).expect("histograms share bounds");
+        self.service_times.add(&other.service_times).expect("histograms share bounds");
+        self.ttfb.add(&other.ttfb).expect("histograms share bounds");
+        self.new_connection_ttfb.add(&other.new_connection_ttfb).expect("histograms share bounds");
+        self.reused_connection_ttfb.add(&other.reused_connection_ttfb).expect("histograms share bounds");
+        for (&local_addr, &ttfb) in &other.open_connections {
+            match self.open_connections.entry(local_addr) {
+                // 两个工作协程都用过的连接只算一次新建，另一次是复用
+                Entry::Occupied(_) => self.reused_connection_ttfb.saturating_record(ttfb),
+                Entry::Vacant(entry) => {
+                    entry.insert(ttfb);
+                }
+            }
+        }
+        self.bytes_received += other.bytes_received;
+        self.successful_latency.merge(&other.successful_latency);
+        for (stats, other) in self.stages.iter_mut().zip(&other.stages) {
+            stats.response_times.add(&other.response_times).expect("histograms share bounds");
+            stats.failed += other.failed;
+        }
+        for (stats, other) in self.flows.iter_mut().zip(&other.flows) {
+            stats.durations.add(&other.durations).expect("histograms share bounds");
+            stats.iterations += other.iterations;
+            stats.failed += other.failed;
+            for (step, other) in stats.steps.iter_mut().zip(&other.steps) {
+                step.response_times.add(&other.response_times).expect("histograms share bounds");
+                step.failed += other.failed;
+            }
+        }
+        for (code, count) in &other.status_codes {
+            *self.status_codes.entry(*code).or_insert(0) += count;
+        }
+        for (kind, error) in &other.errors {
+            self.errors
+                .entry(*kind)
+                .or_insert_with(|| ErrorReport { count: 0, sample: error.sample.clone() })
+                .count += error.count;
+        }
+        self.successful_requests += other.successful_requests;
+        self.failed_requests += other.failed_requests;
+        if self.timeline.len() < other.timeline.len() {
+            self.timeline.resize(other.timeline.len(), TimelineBucket::default());
+        }
+        for (bucket, other) in self.timeline.iter_mut().zip(&other.timeline) {
+            bucket.merge(other);
+        }
+    }
+
+    fn report(&self, url: &str, total_time: Duration, target_rate: Option<f64>, rate: Option<RateOutcome>) -> BenchReport {
+        let requests = self.completed();
+        let mut previous = 0;
+        BenchReport {
+            url: url.to_string(),
+            total_time_secs: total_time.as_secs_f64(),
+            requests,
+            successful: self.successful_requests,
+            failed: self.failed_requests,
+            validation_failures: self.validation_failures(),
+            requests_per_second: requests as f64 / total_time.as_secs_f64().max(0.001),
+            error_rate: if requests == 0 { 0.0 } else { self.failed_requests as f64 / requests as f64 },
+            latency_ms: LatencySummary::from_histogram(&self.response_times),
+            service_time_ms: (!self.service_times.is_empty())
+                .then(|| LatencySummary::from_histogram(&self.service_times)),
+            ttfb_ms: LatencySummary::from_histogram(&self.ttfb),
+            connections: self.connection_report(),
+            bytes_received: self.bytes_received,
+            bytes_per_second: self.bytes_received as f64 / total_time.as_secs_f64().max(0.001),
+            status_codes: self.status_codes.clone(),
+            errors: self.errors.clone(),
+            target_rate,
+            achieved_rate: rate.map(|rate| rate.achieved),
+            dropped: rate.map(|rate| rate.dropped),
+            stages: self
+                .stages
+                .iter()
+                .map(|stats| {
+                    let report = StageReport {
+                        duration_secs: stats.stage.duration.as_secs_f64(),
+                        start_concurrency: previous,
+                        target: stats.stage.target,
+                        requests: stats.response_times.len(),
+                        failed: stats.failed,
+                        latency_ms: LatencySummary::from_histogram(&stats.response_times),
+                    };
+                    previous = stats.stage.target;
+                    report
+                })
+                .collect(),
+            flows: self
+                .flows
+                .iter()
+                .map(|flow| FlowReport {
+                    name: flow.name.clone(),
+                    weight: flow.weight,
+                    iterations: flow.iterations,
+                    failed: flow.failed,
+                    duration_ms: LatencySummary::from_histogram(&flow.durations),
+                    steps: flow
+                        .steps
+                        .iter()
+                        .map(|step| StepReport {
+                            name: step.name.clone(),
+                            requests: step.response_times.len(),
+                            failed: step.failed,
+                            latency_ms: LatencySummary::from_histogram(&step.response_times),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn print_results(&self, total_time: Duration) {
+        let total_requests = self.completed();
+        let rps = total_requests as f64 / total_time.as_secs_f64();
+
+        println!("\n=== Benchmark Results ===");
+        println!("Total time: {:.2}s", total_time.as_secs_f64());
+        println!("Requests per second: {:.2}", rps);
+        println!(
+            "Data received: {} ({}/s)",
+            HumanBytes(self.bytes_received),
+            HumanBytes((self.bytes_received as f64 / total_time.as_secs_f64().max(0.001)) as u64)
+        );
+        println!("Successful requests: {}", self.successful_requests);
+        println!("Failed requests: {}", self.failed_requests);
+        if self.validation_failures() > 0 {
+            println!("Validation failures: {}", self.validation_failures());
+        }
+        println!();
+        if self.service_times.is_empty() {
+            println!("Response Times (ms, until the body is fully received):");
+        } else {
+            println!("Response Times (ms, from scheduled send time, including queueing):");
+        }
+        print_latency_summary(&LatencySummary::from_histogram(&self.response_times));
+        if !self.ttfb.is_empty() {
+            println!();
+            println!("Time to First Byte (ms):");
+            print_latency_summary(&LatencySummary::from_histogram(&self.ttfb));
+        }
+        if !self.service_times.is_empty() {
+            println!();
+            println!("Service Times (ms, excluding queueing):");
+            print_latency_summary(&LatencySummary::from_histogram(&self.service_times));
+        }
+        self.print_connections();
+        print_distribution(&self.response_times);
+        self.print_stages();
+        self.print_flows();
+        println!();
+        println!("Status Code Distribution:");
+
+        for (status, count) in &self.status_codes {
+            println!(
+                "  {}: {} ({:.1}%)",
+                status,
+                count,
+                (*count as f64 / total_requests as f64) * 100.0
+            );
+        }
+
+        if !self.errors.is_empty() {
+            println!();
+            println!("Errors:");
+            for (kind, error) in &self.errors {
+                println!(
+                    "  {}: {} ({:.1}%)",
+                    kind.label(),
+                    error.count,
+                    (error.count as f64 / total_requests as f64) * 100.0
+                );
+                println!("    e.g. {}", error.sample);
+            }
+        }
+    }
+
+    fn print_stages(&self) {
+        if self.stages.is_empty() {
+            return;
+        }
+
+        println!();
+        println!("Stages:");
+        println!(
+            "  {:<6} {:>9} {:>11} {:>9} {:>9} {:>9} {:>9} {:>9} {:>8}",
+            "Stage", "Duration", "Concurrency", "Requests", "Req/s", "Avg ms", "p50 ms", "p99 ms", "Failed"
+        );
+        let mut previous = 0;
+        for (index, stats) in self.stages.iter().enumerate() {
+            let histogram = &stats.response_times;
+            println!(
+                "  {:<6} {:>8.0}s {:>11} {:>9} {:>9.2} {:>9.3} {:>9.3} {:>9.3} {:>8}",
+                index + 1,
+                stats.stage.duration.as_secs_f64(),
+                format!("{}->{}", previous, stats.stage.target),
+                histogram.len(),
+                histogram.len() as f64 / stats.stage.duration.as_secs_f64(),
+                histogram.mean() / 1000.0,
+                millis(histogram.value_at_quantile(0.5)),
+                millis(histogram.value_at_quantile(0.99)),
+                stats.failed
+            );
+            previous = stats.stage.target;
+        }
+    }
+
+    fn print_connections(&self) {
+        let Some(report) = self.connection_report() else {
+            return;
+        };
+        println!();
+        println!("Connections:");
+        println!("  Opened: {} ({:.1}% of requests reused a connection)", report.opened, report.reuse_ratio * 100.0);
+        let ttfb = |summary: &LatencySummary| format!("p50 {:.3} ms, p99 {:.3} ms", summary.p50, summary.p99);
+        println!("  TTFB on new connections: {}", ttfb(&report.ttfb_new_ms));
+        if let Some(reused) = &report.ttfb_reused_ms {
+            println!("  TTFB on reused connections: {}", ttfb(reused));
+        }
+        match report.handshake_ms {
+            Some(handshake) => println!("  Handshake (est.): {:.3} ms (difference of the p50 TTFBs)", handshake),
+            None => println!("  Handshake (est.): n/a, no reused connections to compare with"),
+        }
+    }
+
+    fn print_flows(&self) {
+        if self.flows.is_empty() {
+            return;
+        }
+
+        println!();
+        println!("Flows (ms; flow times are the sum of their steps, without think time):");
+        println!(
+            "  {:<24} {:>9} {:>8} {:>9} {:>9} {:>9}",
+            "Flow / step", "Count", "Failed", "Avg ms", "p50 ms", "p99 ms"
+        );
+        let row = |name: String, count: u64, failed: u32, histogram: &Histogram<u64>| {
+            let (avg, p50, p99) = if histogram.is_empty() {
+                (0.0, 0.0, 0.0)
+            } else {
+                (
+                    histogram.mean() / 1000.0,
+                    millis(histogram.value_at_quantile(0.5)),
+                    millis(histogram.value_at_quantile(0.99)),
+                )
+            };
+            println!("  {:<24} {:>9} {:>8} {:>9.3} {:>9.3} {:>9.3}", name, count, failed, avg, p50, p99);
+        };
+        for flow in &self.flows {
+            row(format!("{} (weight {})", flow.name, flow.weight), flow.iterations, flow.failed, &flow.durations);
+            for (index, step) in flow.steps.iter().enumerate() {
+                row(
+                    format!("  {}. {}", index + 1, step.name),
+                    step.response_times.len(),
+                    step.failed,
+                    &step.response_times,
+                );
+            }
+        }
+    }
+}
+
+fn print_latency_summary(summary: &LatencySummary) {
+    let rows = [
+        ("Min", summary.min),
+        ("Avg", summary.avg),
+        ("p50", summary.p50),
+        ("p90", summary.p90),
+        ("p99", summary.p99),
+        ("p99.9", summary.p99_9),
+        ("Max", summary.max),
+    ];
+    for (label, value) in rows {
+        println!("  {:<6} {:>10.3}", format!("{}:", label), value);
+    }
+}
+
+// 把延迟按对数刻度分桶：返回每行的（下界，上界，请求数），单位微秒
+fn distribution(histogram: &Histogram<u64>, rows: usize) -> Vec<(u64, u64, u64)> {
+    if histogram.is_empty() || rows == 0 {
+        return Vec::new();
+    }
+    let low = histogram.min().max(1);
+    let high = histogram.max().max(low);
+    let rows = if high == low { 1 } else { rows };
+    let ratio = (high as f64 / low as f64).powf(1.0 / rows as f64);
+    let edges: Vec<u64> = (0..=rows)
+        .map(|i| if i == rows { high } else { (low as f64 * ratio.powi(i as i32)).round() as u64 })
+        .collect();
+
+    let mut counts = vec![0u64; rows];
+    for value in histogram.iter_recorded() {
+        let row = edges[1..rows]
+            .iter()
+            .position(|&edge| value.value_iterated_to() < edge)
+            .unwrap_or(rows - 1);
+        counts[row] += value.count_at_value();
+    }
+    (0..rows).map(|i| (edges[i], edges[i + 1], counts[i])).collect()
+}
+
+fn print_distribution(histogram: &Histogram<u64>) {
+    let buckets = distribution(histogram, CHART_ROWS);
+    let peak = buckets.iter().map(|&(_, _, count)| count).max().unwrap_or(0);
+    if peak == 0 {
+        return;
+    }
+    let total = histogram.len() as f64;
+
+    println!();
+    println!("Latency Distribution (ms):");
+    for (low, high, count) in buckets {
+        let width = (count as f64 / peak as f64 * CHART_WIDTH as f64).round() as usize;
+        println!(
+            "  {:>10.3} - {:>10.3} |{:<width$} {} ({:.1}%)",
+            millis(low),
+            millis(high),
+            "█".repeat(width.max(usize::from(count > 0))),
+            count,
+            count as f64 / total * 100.0,
+            width = CHART_WIDTH
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench_feed::FeedStrategy;
+    use crate::test_server::{serve, Reply};
+    use std::sync::Mutex;
+
+    // 指向本地测试服务器的压测设置
+    fn test_options() -> BenchOptions {
+        BenchOptions {
+            requests: 20,
+            concurrency: 2,
+            connect_timeout: 5,
+            http3: false,
+            http2: false,
+            connections: ConnectionLimits::default(),
+            duration: None,
+            rate: None,
+            warmup: None,
+            stages: Vec::new(),
+            output_format: OutputFormat::Json,
+            timeline: None,
+            baseline: None,
+            fail_if: Vec::new(),
+            method: Method::GET,
+            headers: Vec::new(),
+            body: None,
+            follow_redirects: false,
+            tui: false,
+            scenario: None,
+            feed: None,
+            expect: Arc::default(),
+            variants: Vec::new(),
+        }
+    }
+
+    // 按 benchmark_url 的流程跑一轮（不含输出），返回报告
+    async fn run_bench(url: &str, options: &BenchOptions) -> BenchReport {
+        let pool = build_pool(options, options.protocol()).unwrap();
+        let workload = Workload::build(url, options).unwrap();
+        let state = Arc::new(RunState::new(options.concurrency, None));
+        let start = Instant::now();
+        let (stats, rate) = run_phase(&pool, &workload, options, &state, start).await;
+        stats.report(url, start.elapsed(), options.rate, rate)
+    }
+
+    #[tokio::test]
+    async fn test_transfer_bytes_ttfb_and_validation() {
+        let body = "x".repeat(1000);
+        let slow = serve(move |_| Reply::new(200, body.clone()).delay(Duration::from_millis(20))).await;
+        let report = run_bench(&slow, &BenchOptions { requests: 10, ..test_options() }).await;
+        assert_eq!((report.successful, report.failed), (10, 0));
+        assert_eq!(report.bytes_received, 10_000);
+        assert_eq!(report.status_codes.get(&200), Some(&10));
+        assert!(report.ttfb_ms.p50 >= 20.0, "ttfb {}", report.ttfb_ms.p50);
+
+        // 每隔一个响应不含期望的内容：状态码正常，算校验失败
+        let counter = AtomicUsize::new(0);
+        let alternating = serve(move |_| {
+            let body = if counter.fetch_add(1, Ordering::Relaxed).is_multiple_of(2) { "ok" } else { "bad" };
+            Reply::new(200, body)
+        })
+        .await;
+        let expect = Arc::new(Expectations { body_contains: vec!["ok".to_string()], ..Default::default() });
+        let report = run_bench(&alternating, &BenchOptions { requests: 10, expect, ..test_options() }).await;
+        assert_eq!((report.successful, report.failed, report.validation_failures), (5, 5, 5));
+        assert_eq!(report.status_codes.get(&200), Some(&10));
+        assert_eq!(report.errors[&ErrorKind::Validation].count, 5);
+
+        // 超过 --max-body-size 后停止读取，不会收完整个响应体
+        let large = serve(|_| Reply::new(200, vec![b'x'; 4 << 20])).await;
+        let expect = Arc::new(Expectations {
+            body_contains: vec!["y".to_string()],
+            max_body_size: Some(1024),
+            ..Default::default()
+        });
+        let report = run_bench(&large, &BenchOptions { requests: 2, expect, ..test_options() }).await;
+        assert_eq!(report.validation_failures, 2);
+        assert!(report.bytes_received < 2 * (4 << 20), "received {}", report.bytes_received);
+        assert!(report.errors[&ErrorKind::Validation].sample.contains("more than 1024 bytes"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_mid_body_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let refused = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let report = run_bench(&refused, &BenchOptions { requests: 4, ..test_options() }).await;
+        assert_eq!(report.failed, 4);
+        assert_eq!(report.errors[&ErrorKind::ConnectRefused].count, 4);
+
+        // 声明 100 字节，只发 10 字节就断开
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let truncated = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n0123456789").await;
+            }
+        });
+        let report = run_bench(&truncated, &BenchOptions { requests: 4, ..test_options() }).await;
+        assert_eq!(report.failed, 4);
+        assert_eq!(report.errors[&ErrorKind::ResetMidBody].count, 4);
+        assert!(report.status_codes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scenario_extracts_across_steps() {
+        let base = serve(|request| match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/api/login") => Reply::new(200, r#"{"token": "abc", "items": [{"id": 42}]}"#),
+            ("GET", "/api/items/42") if request.headers.get("authorization").is_some_and(|v| v == "Bearer abc") => {
+                Reply::new(200, "item")
+            }
+            _ => Reply::new(401, "unauthorized"),
+        })
+        .await;
+        let scenario = Scenario::parse(
+            r#"
+            [[flow]]
+            name = "browse"
+
+            [[flow.step]]
+            name = "login"
+            method = "post"
+            url = "/api/login"
+            extract = { token = "/token", item = "/items/0/id" }
+
+            [[flow.step]]
+            name = "item"
+            url = "/api/items/{{ item }}"
+            headers = ["Authorization: Bearer {{token}}"]
+            "#,
+            &[],
+        )
+        .unwrap();
+        let options = BenchOptions { requests: 4, scenario: Some(Arc::new(scenario)), ..test_options() };
+        let report = run_bench(&base, &options).await;
+
+        let flow = &report.flows[0];
+        assert_eq!((flow.iterations, flow.failed), (4, 0));
+        assert_eq!(flow.steps.iter().map(|step| (step.requests, step.failed)).collect::<Vec<_>>(), [(4, 0), (4, 0)]);
+        assert_eq!(report.status_codes.get(&200), Some(&8));
+    }
+
+    #[tokio::test]
+    async fn test_feed_renders_url_and_headers() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let base = serve(move |request| {
+            log.lock().unwrap().push((request.path.clone(), request.headers.get("x-user").cloned().unwrap_or_default()));
+            Reply::new(200, "ok")
+        })
+        .await;
+        let feed = Feed::parse("user\nalice\nbob\n", FeedStrategy::Sequential).unwrap();
+        let options = BenchOptions {
+            requests: 10,
+            concurrency: 1,
+            headers: vec!["X-User: {{user}}".to_string()],
+            feed: Some(Arc::new(feed)),
+            ..test_options()
+        };
+        let report = run_bench(&format!("{}/users/{{{{user}}}}", base), &options).await;
+
+        // 顺序读取的数据用完后压测结束
+        assert_eq!(report.successful, 2);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [("/users/alice".to_string(), "alice".to_string()), ("/users/bob".to_string(), "bob".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compare_variants_share_feed_sequence() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let base = serve(move |request| {
+            log.lock().unwrap().push(request.path.clone());
+            Reply::new(200, "ok")
+        })
+        .await;
+        let feed = Feed::parse("user\nalice\nbob\ncarol\ndave\n", FeedStrategy::Random).unwrap();
+        let variants = Variant::list(&[format!("{}/a/{{{{user}}}}", base), format!("{}/b/{{{{user}}}}", base)], &[]);
+        let options =
+            BenchOptions { requests: 12, concurrency: 1, feed: Some(Arc::new(feed)), variants, ..test_options() };
+        let pools = vec![build_pool(&options, None).unwrap(), build_pool(&options, None).unwrap()];
+        let targets = compare_targets(&options, &pools).unwrap();
+        let stats = run_comparison(&targets, &options, Instant::now()).await;
+
+        assert!(stats.iter().all(|stats| stats.successful_requests == 12));
+        // 每一方的计数分开，进度事件按方输出
+        assert!(targets.iter().all(|target| target.state.successful.load(Ordering::Relaxed) == 12));
+        let paths = seen.lock().unwrap();
+        let users = |prefix: &str| -> Vec<String> {
+            paths.iter().filter_map(|path| path.strip_prefix(prefix)).map(str::to_string).collect()
+        };
+        assert_eq!(users("/a/").len(), 12);
+        assert_eq!(users("/a/"), users("/b/"));
+    }
+
+    #[tokio::test]
+    async fn test_connection_counting() {
+        let keep_alive = serve(|_| Reply::new(200, "ok").keep_alive()).await;
+        let options = BenchOptions { requests: 10, ..test_options() };
+
+        let fresh = ConnectionLimits { no_keepalive: true, ..Default::default() };
+        let report = run_bench(&keep_alive, &BenchOptions { connections: fresh, ..options.clone() }).await;
+        let connections = report.connections.unwrap();
+        assert_eq!(connections.opened, 10);
+        assert_eq!(connections.reuse_ratio, 0.0);
+
+        let report = run_bench(&keep_alive, &options).await;
+        let connections = report.connections.unwrap();
+        assert!((1..=2).contains(&connections.opened), "opened {}", connections.opened);
+        assert!(connections.reuse_ratio >= 0.8, "reuse ratio {}", connections.reuse_ratio);
+
+        // 服务端每次都关闭连接时，即使本地端口被重用也不算复用
+        let closing = serve(|_| Reply::new(200, "ok")).await;
+        let report = run_bench(&closing, &options).await;
+        assert_eq!(report.connections.unwrap().opened, 10);
+    }
+
+    #[tokio::test]
+    async fn test_open_model_rate_and_queueing() {
+        let fast = serve(|_| Reply::new(200, "ok")).await;
+        let options =
+            BenchOptions { rate: Some(50.0), duration: Some(Duration::from_secs(1)), concurrency: 10, ..test_options() };
+        let report = run_bench(&fast, &options).await;
+        let achieved = report.achieved_rate.unwrap();
+        assert!((40.0..=55.0).contains(&achieved), "achieved {}", achieved);
+        assert_eq!(report.dropped, Some(0));
+
+        // 每个请求 50ms、同时只能有一个：只能达到约 20 req/s，其余请求排队
+        let slow = serve(|_| Reply::new(200, "ok").delay(Duration::from_millis(50))).await;
+        let options = BenchOptions { concurrency: 1, ..options };
+        let start = Instant::now();
+        let report = run_bench(&slow, &options).await;
+        // 结束时间之后排队的请求不再发送
+        assert!(start.elapsed() < Duration::from_millis(1300), "ran for {:?}", start.elapsed());
+        assert!(report.achieved_rate.unwrap() < 25.0, "achieved {:?}", report.achieved_rate);
+        assert!(report.dropped.unwrap() >= 15, "dropped {:?}", report.dropped);
+        // 延迟从计划发送时间算起，包含排队时间；服务时间不包含
+        let service = report.service_time_ms.unwrap();
+        assert!(service.p50 < 150.0, "service p50 {}", service.p50);
+        assert!(report.latency_ms.p90 > 300.0, "latency p90 {}", report.latency_ms.p90);
+    }
+
+    #[test]
+    fn test_parse_duration_and_rate() {
+        assert_eq!(parse_duration("60s").unwrap(), Duration::from_secs(60));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("0s").is_err());
+
+        assert_eq!(parse_request_rate("500/s").unwrap(), 500.0);
+        assert_eq!(parse_request_rate("500").unwrap(), 500.0);
+        assert_eq!(parse_request_rate("120/m").unwrap(), 2.0);
+        assert!(parse_request_rate("fast").is_err());
+    }
+
+    #[test]
+    fn test_classify_errors() {
+        let classify = |connect, timeout, reading_body, io_kind, message| {
+            classify_parts(connect, timeout, reading_body, io_kind, message)
+        };
+        assert_eq!(
+            classify(true, false, false, None, "error trying to connect: dns error: failed to lookup address information"),
+            ErrorKind::Dns
+        );
+        assert_eq!(
+            classify(true, false, false, Some(io::ErrorKind::ConnectionRefused), "tcp connect error: Connection refused"),
+            ErrorKind::ConnectRefused
+        );
+        assert_eq!(classify(true, true, false, None, "operation timed out"), ErrorKind::ConnectTimeout);
+        assert_eq!(
+            classify(true, false, false, Some(io::ErrorKind::InvalidData), "invalid peer certificate: UnknownIssuer"),
+            ErrorKind::Tls
+        );
+        assert_eq!(
+            classify(false, false, false, None, "connection closed before message completed"),
+            ErrorKind::ConnectionClosed
+        );
+        assert_eq!(
+            classify(false, false, true, Some(io::ErrorKind::ConnectionReset), "error decoding response body"),
+            ErrorKind::ResetMidBody
+        );
+        assert_eq!(classify(false, true, true, None, "operation timed out"), ErrorKind::Timeout);
+    }
+
+    #[test]
+    fn test_load_body() {
+        assert_eq!(load_body("a=1&b=2").unwrap(), Bytes::from_static(b"a=1&b=2"));
+
+        let path = std::env::temp_dir().join(format!("surf-bench-body-{}", std::process::id()));
+        fs::write(&path, b"{\"id\":1}").unwrap();
+        assert_eq!(load_body(&format!("@{}", path.display())).unwrap(), Bytes::from_static(b"{\"id\":1}"));
+        let _ = fs::remove_file(&path);
+        assert!(load_body("@/nonexistent/body.json").is_err());
+    }
+
+    #[test]
+    fn test_stages() {
+        let stages = Stage::parse_list("30s:10,60s:50,30s:0").unwrap();
+        assert_eq!(stages.len(), 3);
+        assert_eq!(Stage::total_duration(&stages), Duration::from_secs(120));
+
+        assert_eq!(Stage::concurrency_at(&stages, Duration::ZERO), Some((0, 0)));
+        assert_eq!(Stage::concurrency_at(&stages, Duration::from_secs(15)), Some((0, 5)));
+        assert_eq!(Stage::concurrency_at(&stages, Duration::from_secs(60)), Some((1, 30)));
+        assert_eq!(Stage::concurrency_at(&stages, Duration::from_secs(105)), Some((2, 25)));
+        assert_eq!(Stage::concurrency_at(&stages, Duration::from_secs(120)), None);
+
+        assert!(Stage::parse_list("30s").is_err());
+        assert!(Stage::parse_list("30s:many").is_err());
+    }
+
+    #[test]
+    fn test_histogram_merge_and_distribution() {
+        let start = Instant::now();
+        let mut first = BenchmarkStats::new(&[], start);
+        let mut second = BenchmarkStats::new(&[], start);
+        let transfer = |status_code, bytes| Transfer {
+            status_code,
+            ttfb: Some(Duration::from_micros(5)),
+            connection: None,
+            bytes,
+            content: None,
+            accepted: status_code.is_some_and(|code: u16| (200..400).contains(&code)),
+            error: status_code.is_none().then(|| (ErrorKind::ConnectRefused, "refused".to_string())),
+        };
+        for i in 1..=100 {
+            first.record(Sample::new(Duration::from_micros(i * 10), None, transfer(Some(200), 10)), None);
+        }
+        second.record(Sample::new(Duration::from_micros(1500), None, transfer(Some(503), 0)), None);
+        second.record(Sample::new(Duration::from_millis(5), None, transfer(None, 0)), None);
+        first.merge(&second);
+
+        assert_eq!(first.completed(), 102);
+        assert_eq!(first.successful_requests, 100);
+        assert_eq!(first.failed_requests, 2);
+        assert_eq!(first.status_codes.get(&503), Some(&1));
+        assert_eq!(first.timeline[0].requests, 102);
+        assert_eq!(first.timeline[0].failed, 2);
+        assert_eq!(first.timeline[0].bytes, 1000);
+        assert_eq!(first.bytes_received, 1000);
+        assert_eq!(first.ttfb.len(), 102);
+        assert_eq!(first.errors[&ErrorKind::ConnectRefused].count, 1);
+        // 亚毫秒精度
+        assert_eq!(first.response_times.min(), 10);
+        assert_eq!(first.response_times.value_at_quantile(0.5), 510);
+        assert_eq!(first.response_times.max(), 5003);
+
+        let buckets = distribution(&first.response_times, 8);
+        assert_eq!(buckets.len(), 8);
+        assert_eq!(buckets.iter().map(|&(_, _, count)| count).sum::<u64>(), 102);
+        assert_eq!(buckets.first().unwrap().0, 10);
+        assert_eq!(buckets.last().unwrap().1, 5003);
+        assert!(distribution(&new_histogram(), 8).is_empty());
+    }
+}
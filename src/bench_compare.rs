@@ -0,0 +1,358 @@
+use crate::bench_report::BenchReport;
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+/// 差异显著的 p 值上限
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+/// 少于这么多请求时正态近似不可靠，不做显著性检验
+const MIN_SAMPLES: usize = 30;
+
+/// 对比压测中使用的 HTTP 版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Http1,
+    Http2,
+    Http3,
+}
+
+/// 对比压测的一方：一个 URL 和可选的 HTTP 版本
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub label: String,
+    pub url: String,
+    pub protocol: Option<Protocol>,
+}
+
+/// 一方的压测结果；成功请求的延迟用于显著性检验
+pub struct VariantResult {
+    pub variant: Variant,
+    pub report: BenchReport,
+    pub successful: LatencyMoments,
+}
+
+/// 一组延迟（毫秒）的数量、和与平方和，可以合并，用来算均值和标准差
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyMoments {
+    count: usize,
+    sum: f64,
+    sum_squares: f64,
+}
+
+/// --compare/--protocols 的结果，--output-format json 输出的就是它
+#[derive(Serialize)]
+pub struct CompareReport {
+    pub variants: Vec<VariantReport>,
+    /// 其余各方与第一方的对比
+    pub comparisons: Vec<ComparisonReport>,
+}
+
+#[derive(Serialize)]
+pub struct VariantReport {
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<Protocol>,
+    /// 成功请求的平均延迟和标准差；失败的请求（例如连接被拒绝）往往很快，不参与对比
+    pub successful_avg_ms: f64,
+    pub latency_stdev_ms: f64,
+    pub report: BenchReport,
+}
+
+/// 一方相对第一方的成功请求平均延迟变化和 Welch t 检验的结果
+#[derive(Serialize)]
+pub struct ComparisonReport {
+    pub label: String,
+    pub baseline: String,
+    pub avg_delta_ms: f64,
+    /// 平均延迟的相对变化（0.1 = +10%）；基准为 0 时没有
+    pub avg_change: Option<f64>,
+    /// 成功的请求太少时不检验
+    pub p_value: Option<f64>,
+    pub significant: bool,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Http1 => "http1",
+            Self::Http2 => "http2",
+            Self::Http3 => "http3",
+        };
+        f.write_str(name)
+    }
+}
+
+impl LatencyMoments {
+    pub fn record(&mut self, latency_ms: f64) {
+        self.count += 1;
+        self.sum += latency_ms;
+        self.sum_squares += latency_ms * latency_ms;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    /// 样本标准差
+    pub fn stdev(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        let n = self.count as f64;
+        ((self.sum_squares - self.sum * self.sum / n) / (n - 1.0)).max(0.0).sqrt()
+    }
+}
+
+impl Variant {
+    /// URL 和协议的所有组合；多个 URL 依次标为 A、B，多个协议时标上协议名
+    pub fn list(urls: &[String], protocols: &[Protocol]) -> Vec<Self> {
+        let protocols: Vec<Option<Protocol>> = if protocols.is_empty() {
+            vec![None]
+        } else {
+            protocols.iter().copied().map(Some).collect()
+        };
+        let mut variants = Vec::new();
+        for (index, url) in urls.iter().enumerate() {
+            for protocol in &protocols {
+                let name = (b'A' + index as u8) as char;
+                let label = match protocol {
+                    Some(protocol) if urls.len() > 1 && protocols.len() > 1 => format!("{}/{}", name, protocol),
+                    Some(protocol) if urls.len() == 1 => protocol.to_string(),
+                    _ => name.to_string(),
+                };
+                variants.push(Self { label, url: url.clone(), protocol: *protocol });
+            }
+        }
+        variants
+    }
+}
+
+impl CompareReport {
+    pub fn new(results: Vec<VariantResult>) -> Self {
+        let comparisons = match results.split_first() {
+            Some((baseline, others)) => others.iter().map(|other| ComparisonReport::new(baseline, other)).collect(),
+            None => Vec::new(),
+        };
+        let variants = results
+            .into_iter()
+            .map(|result| VariantReport {
+                label: result.variant.label,
+                protocol: result.variant.protocol,
+                successful_avg_ms: result.successful.mean(),
+                latency_stdev_ms: result.successful.stdev(),
+                report: result.report,
+            })
+            .collect();
+        Self { variants, comparisons }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// 并排输出各方的结果，右侧是相对第一方的变化
+    pub fn print(&self) {
+        let Some(baseline) = self.variants.first() else {
+            return;
+        };
+        println!("\n=== Comparison ===");
+        for variant in &self.variants {
+            match variant.protocol {
+                Some(protocol) => println!("  {}: {} ({})", variant.label, variant.report.url, protocol),
+                None => println!("  {}: {}", variant.label, variant.report.url),
+            }
+        }
+        println!();
+
+        let columns: Vec<String> = self
+            .variants
+            .iter()
+            .map(|variant| variant.label.clone())
+            .chain(self.variants[1..].iter().map(|variant| format!("{} vs {}", variant.label, baseline.label)))
+            .collect();
+        let width = columns.iter().map(String::len).max().unwrap_or(0).max(12);
+        let mut header = format!("  {:<14}", "");
+        for column in &columns {
+            header.push_str(&format!(" {:>width$}", column));
+        }
+        println!("{}", header);
+
+        type Metric = fn(&BenchReport) -> f64;
+        // 请求轮流发送，各方的请求数和耗时相同，所以不比较吞吐量
+        let rows: [(&str, Metric, bool); 8] = [
+            ("Requests", |r| r.requests as f64, false),
+            ("Failed", |r| r.failed as f64, false),
+            ("Avg ms", |r| r.latency_ms.avg, true),
+            ("p50 ms", |r| r.latency_ms.p50, true),
+            ("p90 ms", |r| r.latency_ms.p90, true),
+            ("p99 ms", |r| r.latency_ms.p99, true),
+            ("Max ms", |r| r.latency_ms.max, true),
+            ("TTFB p50 ms", |r| r.ttfb_ms.p50, true),
+        ];
+        for (name, metric, relative) in rows {
+            let mut line = format!("  {:<14}", name);
+            for variant in &self.variants {
+                let value = metric(&variant.report);
+                if relative {
+                    line.push_str(&format!(" {:>width$.3}", value));
+                } else {
+                    line.push_str(&format!(" {:>width$}", value));
+                }
+            }
+            let base = metric(&baseline.report);
+            for variant in &self.variants[1..] {
+                let value = metric(&variant.report);
+                let delta = match relative {
+                    true if base != 0.0 => format!("{:+.1}%", (value - base) / base * 100.0),
+                    true => "n/a".to_string(),
+                    false => format!("{:+}", value - base),
+                };
+                line.push_str(&format!(" {:>width$}", delta));
+            }
+            println!("{}", line);
+        }
+
+        println!();
+        for comparison in &self.comparisons {
+            let change = comparison.avg_change.map(|c| format!(" ({:+.1}%)", c * 100.0)).unwrap_or_default();
+            let verdict = match comparison.p_value {
+                Some(p) if comparison.significant => format!("significant (Welch's t-test, p = {:.4})", p),
+                Some(p) => format!("not significant (Welch's t-test, p = {:.4})", p),
+                None => format!("too few successful requests for a significance test (need {} each)", MIN_SAMPLES),
+            };
+            println!(
+                "{} vs {}: mean latency of successful requests {:+.3} ms{}, {}",
+                comparison.label, comparison.baseline, comparison.avg_delta_ms, change, verdict
+            );
+        }
+        // 失败的请求（例如连接被拒绝）往往很快，会让表中的延迟看起来更好
+        for variant in self.variants.iter().filter(|variant| variant.report.failed > 0) {
+            println!(
+                "Warning: {} had {} failed requests ({:.1}%); the table includes them, the mean comparison does not",
+                variant.label,
+                variant.report.failed,
+                variant.report.error_rate * 100.0
+            );
+        }
+    }
+}
+
+impl ComparisonReport {
+    fn new(baseline: &VariantResult, other: &VariantResult) -> Self {
+        let (base, current) = (&baseline.successful, &other.successful);
+        let p_value = welch_p_value(
+            (base.mean(), base.stdev(), base.count),
+            (current.mean(), current.stdev(), current.count),
+        );
+        Self {
+            label: other.variant.label.clone(),
+            baseline: baseline.variant.label.clone(),
+            avg_delta_ms: current.mean() - base.mean(),
+            avg_change: (base.mean() != 0.0).then(|| (current.mean() - base.mean()) / base.mean()),
+            p_value,
+            significant: p_value.is_some_and(|p| p < SIGNIFICANCE_LEVEL),
+        }
+    }
+}
+
+// Welch t 检验（两组方差可以不同）的双侧 p 值，参数为（均值，标准差，样本数）。
+// 样本数不少于 MIN_SAMPLES 时 t 分布接近正态分布，用正态分布近似
+fn welch_p_value(a: (f64, f64, usize), b: (f64, f64, usize)) -> Option<f64> {
+    let ((mean_a, sd_a, n_a), (mean_b, sd_b, n_b)) = (a, b);
+    if n_a < MIN_SAMPLES || n_b < MIN_SAMPLES {
+        return None;
+    }
+    let standard_error = (sd_a * sd_a / n_a as f64 + sd_b * sd_b / n_b as f64).sqrt();
+    if standard_error == 0.0 {
+        return Some(if mean_a == mean_b { 1.0 } else { 0.0 });
+    }
+    let z = (mean_b - mean_a).abs() / standard_error;
+    Some(erfc(z / std::f64::consts::SQRT_2))
+}
+
+// 互补误差函数的近似（Numerical Recipes erfcc，相对误差小于 1.2e-7）
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variant_labels() {
+        let urls = ["http://a".to_string(), "http://b".to_string()];
+        let labels = |urls: &[String], protocols: &[Protocol]| -> Vec<String> {
+            Variant::list(urls, protocols).into_iter().map(|variant| variant.label).collect()
+        };
+        assert_eq!(labels(&urls, &[]), ["A", "B"]);
+        assert_eq!(labels(&urls[..1], &[Protocol::Http1, Protocol::Http2]), ["http1", "http2"]);
+        assert_eq!(labels(&urls, &[Protocol::Http2]), ["A", "B"]);
+        assert_eq!(labels(&urls, &[Protocol::Http1, Protocol::Http3]), ["A/http1", "A/http3", "B/http1", "B/http3"]);
+    }
+
+    #[test]
+    fn test_welch_p_value() {
+        // z = 1.96 对应双侧 p ≈ 0.05
+        let p = welch_p_value((10.0, 5.0, 100), (10.0 + 1.96 * 0.5f64.sqrt(), 5.0, 100)).unwrap();
+        assert!((p - 0.05).abs() < 0.001, "p = {}", p);
+        assert!(welch_p_value((10.0, 2.0, 1000), (10.0, 2.0, 1000)).unwrap() > 0.99);
+        assert!(welch_p_value((10.0, 1.0, 1000), (12.0, 1.0, 1000)).unwrap() < 1e-6);
+        assert_eq!(welch_p_value((10.0, 1.0, 10), (12.0, 1.0, 1000)), None);
+        assert!((erfc(0.0) - 1.0).abs() < 1e-6 && (erfc(-1.0) - 1.8427).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_failed_requests_excluded_from_comparison() {
+        let result = |label: &str, latencies: &[f64]| {
+            let mut successful = LatencyMoments::default();
+            latencies.iter().for_each(|&latency| successful.record(latency));
+            let report: BenchReport = serde_json::from_value(serde_json::json!({
+                "url": "http://x", "total_time_secs": 1.0, "requests": 60, "successful": latencies.len(),
+                "failed": 60 - latencies.len(), "requests_per_second": 60.0, "error_rate": 0.0,
+                "latency_ms": {"min": 0.0, "avg": 0.0, "p50": 0.0, "p90": 0.0, "p95": 0.0, "p99": 0.0,
+                    "p99.9": 0.0, "max": 0.0},
+                "status_codes": {},
+            }))
+            .unwrap();
+            let variant = Variant { label: label.to_string(), url: "http://x".to_string(), protocol: None };
+            VariantResult { variant, report, successful }
+        };
+        // 两方成功请求的延迟分布相同，B 另有一半请求很快失败：失败的不影响对比
+        let latencies: Vec<f64> = (0..60).map(|i| 10.0 + (i % 5) as f64).collect();
+        let report = CompareReport::new(vec![result("A", &latencies), result("B", &latencies[..30])]);
+        let comparison = &report.comparisons[0];
+        assert!(comparison.avg_delta_ms.abs() < 1e-9);
+        assert!(!comparison.significant);
+
+        let stdev = report.variants[0].latency_stdev_ms;
+        assert!((stdev - 1.4261).abs() < 1e-3, "stdev {}", stdev);
+        let report = CompareReport::new(vec![result("A", &latencies), result("B", &latencies[..10])]);
+        assert_eq!(report.comparisons[0].p_value, None);
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use hyper::client::connect::HttpInfo;
+use reqwest::{header::CONNECTION, Client, ClientBuilder, Response};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// 压测的连接行为
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionLimits {
+    /// 每个请求新建连接（HTTP/1.1），用来测握手和 TLS 终结能力
+    pub no_keepalive: bool,
+    /// 同时打开的连接数上限；请求在连接之间轮流分配，没有空闲名额时排队
+    pub max_connections: Option<usize>,
+    /// 每个连接上同时进行的请求数（HTTP/2 多路复用），默认 1
+    pub max_streams: Option<usize>,
+}
+
+/// 压测用的客户端：不限制连接数时是一个共享连接池的客户端，
+/// 指定 --max-connections 时每个连接一个客户端，用信号量限制每个连接上同时进行的请求
+#[derive(Clone)]
+pub struct ClientPool {
+    slots: Arc<[Slot]>,
+    /// 所有连接上空闲的请求名额；不限制连接数时为 None
+    free: Option<Arc<Semaphore>>,
+    next: Arc<AtomicUsize>,
+    no_keepalive: bool,
+}
+
+struct Slot {
+    client: Client,
+    streams: Semaphore,
+}
+
+/// 一次请求占用的客户端和连接名额，请求结束时释放
+pub struct Lease<'a> {
+    pool: &'a ClientPool,
+    client: &'a Client,
+    _permits: Option<(SemaphorePermit<'a>, SemaphorePermit<'a>)>,
+}
+
+impl ClientPool {
+    /// builder 每次返回一份相同设置的 ClientBuilder，连接相关的设置在这里追加
+    pub fn new(builder: impl Fn() -> Result<ClientBuilder>, limits: ConnectionLimits) -> Result<Self> {
+        let build = || {
+            let mut builder = builder()?;
+            if limits.no_keepalive {
+                // 连接用完即关闭；HTTP/2 会在多个请求间共享连接，所以只用 HTTP/1.1
+                builder = builder.pool_max_idle_per_host(0).http1_only();
+            } else if limits.max_connections.is_some() {
+                builder = builder.pool_max_idle_per_host(1);
+            }
+            builder.build().map_err(|e| anyhow!("Failed to build HTTP client: {}", e))
+        };
+        let connections = limits.max_connections.unwrap_or(1);
+        let streams = limits.max_streams.unwrap_or(1);
+        let slots = (0..connections)
+            .map(|_| Ok(Slot { client: build()?, streams: Semaphore::new(streams) }))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            slots: slots.into(),
+            free: limits.max_connections.map(|connections| Arc::new(Semaphore::new(connections * streams))),
+            next: Arc::new(AtomicUsize::new(0)),
+            no_keepalive: limits.no_keepalive,
+        })
+    }
+
+    /// 取一个可以发送请求的客户端；连接数受限时等到有连接空出名额
+    pub async fn acquire(&self) -> Lease<'_> {
+        let Some(free) = &self.free else {
+            return Lease { pool: self, client: &self.slots[0].client, _permits: None };
+        };
+        let total = free.acquire().await.expect("semaphore is never closed");
+        // 拿到总名额后一定有连接还有空闲名额，只是可能被同时查找的请求先占去
+        loop {
+            let first = self.next.fetch_add(1, Ordering::Relaxed);
+            for i in 0..self.slots.len() {
+                let slot = &self.slots[(first + i) % self.slots.len()];
+                if let Ok(stream) = slot.streams.try_acquire() {
+                    return Lease { pool: self, client: &slot.client, _permits: Some((total, stream)) };
+                }
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+impl Lease<'_> {
+    pub fn client(&self) -> &Client {
+        self.client
+    }
+
+    /// 响应使用的连接；拿不到连接信息（例如 HTTP/3）时返回 None。
+    /// 是否为新连接由各工作协程的统计按本地地址判断，结束时合并，请求路径上不加锁
+    pub fn connection(&self, response: &Response) -> Option<ConnectionUse> {
+        let local_addr = response.extensions().get::<HttpInfo>()?.local_addr();
+        // 服务端声明关闭的连接不会再被复用，之后同一本地端口上的是新连接
+        let closing = response
+            .headers()
+            .get(CONNECTION)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("close"));
+        if self.pool.no_keepalive {
+            return Some(ConnectionUse::Fresh);
+        }
+        Some(ConnectionUse::Pooled { local_addr, closing })
+    }
+}
+
+/// 一个响应所用的连接
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectionUse {
+    /// 关闭 keep-alive 时每个请求都新建连接
+    Fresh,
+    /// 连接池中的连接，按本地地址区分；closing 表示这个响应之后连接关闭
+    Pooled { local_addr: SocketAddr, closing: bool },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire_respects_connection_limits() {
+        let limits = ConnectionLimits { max_connections: Some(2), max_streams: Some(2), ..Default::default() };
+        let pool = ClientPool::new(|| Ok(Client::builder()), limits).unwrap();
+        let leases: Vec<_> = futures_util::future::join_all((0..4).map(|_| pool.acquire())).await;
+        // 两个连接各两个名额，名额用完后第五个请求要等待
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(20), pool.acquire()).await.is_err());
+        drop(leases);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(20), pool.acquire()).await.is_ok());
+
+        let unlimited = ClientPool::new(|| Ok(Client::builder()), ConnectionLimits::default()).unwrap();
+        let _leases: Vec<_> = futures_util::future::join_all((0..100).map(|_| unlimited.acquire())).await;
+    }
+}
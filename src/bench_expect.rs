@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// 压测时对每个响应的检查（--expect-* 和 --max-body-size）
+#[derive(Debug, Default)]
+pub struct Expectations {
+    /// 允许的状态码；为空时 2xx/3xx 算成功
+    pub status: Vec<StatusMatch>,
+    pub body_contains: Vec<String>,
+    pub json: Vec<JsonExpectation>,
+    pub max_body_size: Option<u64>,
+}
+
+/// 一个状态码或一类状态码（2xx）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusMatch {
+    Code(u16),
+    Class(u16),
+}
+
+/// 响应 JSON 中某个位置的值：存在、等于或不等于
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonExpectation {
+    pointer: String,
+    condition: Option<(bool, Value)>,
+}
+
+impl StatusMatch {
+    /// 解析 "200,201" 或 "2xx"
+    pub fn parse_list(value: &str) -> Result<Vec<Self>> {
+        value
+            .split(',')
+            .map(|item| {
+                let item = item.trim().to_ascii_lowercase();
+                let invalid = || anyhow!("Invalid status '{}', expected e.g. 200 or 2xx", item);
+                match item.strip_suffix("xx") {
+                    Some(class) => match class.parse::<u16>() {
+                        Ok(class @ 1..=5) => Ok(Self::Class(class)),
+                        _ => Err(invalid()),
+                    },
+                    None => match item.parse::<u16>() {
+                        Ok(code @ 100..=599) => Ok(Self::Code(code)),
+                        _ => Err(invalid()),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    fn matches(&self, code: u16) -> bool {
+        match self {
+            Self::Code(expected) => code == *expected,
+            Self::Class(class) => code / 100 == *class,
+        }
+    }
+}
+
+impl JsonExpectation {
+    /// 解析 "/status == \"ok\""、"/count != 0" 或只有 JSON Pointer（要求存在）；
+    /// 比较值按 JSON 解析，不是合法 JSON 时当作字符串
+    pub fn parse(value: &str) -> Result<Self> {
+        let (pointer, condition) = if let Some((pointer, expected)) = value.split_once("==") {
+            (pointer, Some((true, expected)))
+        } else if let Some((pointer, expected)) = value.split_once("!=") {
+            (pointer, Some((false, expected)))
+        } else {
+            (value, None)
+        };
+        let pointer = pointer.trim();
+        if !pointer.is_empty() && !pointer.starts_with('/') {
+            return Err(anyhow!("Invalid JSON pointer '{}' (must start with '/')", pointer));
+        }
+        let condition = condition.map(|(equal, expected)| {
+            let expected = expected.trim();
+            let expected = serde_json::from_str(expected).unwrap_or_else(|_| Value::String(expected.to_string()));
+            (equal, expected)
+        });
+        Ok(Self { pointer: pointer.to_string(), condition })
+    }
+
+    fn check(&self, json: &Value) -> Result<(), String> {
+        let actual = json.pointer(&self.pointer);
+        match (&self.condition, actual) {
+            (_, None) => Err(format!("JSON {} is missing", self.pointer)),
+            (None, Some(_)) => Ok(()),
+            (Some((equal, expected)), Some(actual)) if (actual == expected) == *equal => Ok(()),
+            (Some((equal, expected)), Some(actual)) => Err(format!(
+                "JSON {} is {}, expected {}{}",
+                self.pointer,
+                actual,
+                if *equal { "" } else { "anything but " },
+                expected
+            )),
+        }
+    }
+}
+
+impl Expectations {
+    /// 检查需要读取响应体内容
+    pub fn needs_body(&self) -> bool {
+        !self.body_contains.is_empty() || !self.json.is_empty()
+    }
+
+    /// 状态码是否算成功：指定了 --expect-status 时按指定的（可以是 4xx），否则 2xx/3xx
+    pub fn status_ok(&self, code: u16) -> bool {
+        if self.status.is_empty() {
+            (200..400).contains(&code)
+        } else {
+            self.status.iter().any(|status| status.matches(code))
+        }
+    }
+
+    /// 检查一个完整收到的响应，返回第一条不满足的原因
+    pub fn check(&self, code: u16, size: u64, body: Option<&[u8]>) -> Result<(), String> {
+        if !self.status_ok(code) {
+            return Err(format!("status {}", code));
+        }
+        if let Some(max) = self.max_body_size {
+            if size > max {
+                return Err(format!("body is {} bytes, more than {}", size, max));
+            }
+        }
+        let body = body.unwrap_or_default();
+        if let Some(text) = self.body_contains.iter().find(|text| !contains(body, text.as_bytes())) {
+            return Err(format!("body does not contain '{}'", text));
+        }
+        if !self.json.is_empty() {
+            let json: Value =
+                serde_json::from_slice(body).map_err(|e| format!("body is not JSON: {}", e))?;
+            for expectation in &self.json {
+                expectation.check(&json)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expectations() {
+        assert_eq!(
+            StatusMatch::parse_list("200, 2xx").unwrap(),
+            [StatusMatch::Code(200), StatusMatch::Class(2)]
+        );
+        assert!(StatusMatch::parse_list("9xx").is_err());
+        assert!(StatusMatch::parse_list("20").is_err());
+
+        let ok = JsonExpectation::parse("/status == \"ok\"").unwrap();
+        assert_eq!(ok.condition, Some((true, Value::String("ok".to_string()))));
+        assert_eq!(JsonExpectation::parse("/status==ok").unwrap(), ok);
+        assert_eq!(JsonExpectation::parse("/count != 0").unwrap().condition, Some((false, Value::from(0))));
+        assert_eq!(JsonExpectation::parse("/id").unwrap().condition, None);
+        assert!(JsonExpectation::parse("status == ok").is_err());
+    }
+
+    #[test]
+    fn test_check_response() {
+        let expectations = Expectations {
+            status: vec![StatusMatch::Code(200), StatusMatch::Code(404)],
+            body_contains: vec!["ok".to_string()],
+            json: vec![JsonExpectation::parse("/status == \"ok\"").unwrap()],
+            max_body_size: Some(100),
+        };
+        let body = br#"{"status": "ok"}"#;
+        assert!(expectations.status_ok(404));
+        assert_eq!(expectations.check(200, 16, Some(body)), Ok(()));
+        assert_eq!(expectations.check(201, 16, Some(body)), Err("status 201".to_string()));
+        assert!(expectations.check(200, 500, Some(body)).unwrap_err().contains("more than 100"));
+        assert!(expectations.check(200, 20, Some(b"<html>ok</html>")).unwrap_err().contains("not JSON"));
+        assert_eq!(
+            expectations.check(200, 20, Some(br#"{"status": "down", "ok": 1}"#)),
+            Err("JSON /status is \"down\", expected \"ok\"".to_string())
+        );
+        assert!(Expectations::default().status_ok(302));
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::Path,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// 取数据行的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FeedStrategy {
+    /// 每行只用一次，用完后压测停止
+    Sequential,
+    /// 每次随机取一行
+    Random,
+    /// 按顺序循环使用
+    #[default]
+    Circular,
+}
+
+/// 压测数据源：CSV 数据行（第一行是列名）和 {{seq}} 的计数
+#[derive(Debug, Default)]
+pub struct Feed {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    strategy: FeedStrategy,
+    next_row: AtomicUsize,
+    seq: AtomicU64,
+    /// 随机取行的种子；第 n 次取的行只由种子和 n 决定
+    seed: u64,
+}
+
+impl fmt::Display for FeedStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Sequential => "sequential",
+            Self::Random => "random",
+            Self::Circular => "circular",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Feed {
+    pub fn load(path: &Path, strategy: FeedStrategy) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read feed {}", path.display()))?;
+        Self::parse(&content, strategy).with_context(|| format!("Invalid feed {}", path.display()))
+    }
+
+    pub fn parse(content: &str, strategy: FeedStrategy) -> Result<Self> {
+        let mut records = parse_csv(content)?.into_iter();
+        let columns: Vec<String> = records
+            .next()
+            .ok_or_else(|| anyhow!("Feed is empty, expected a header row"))?
+            .into_iter()
+            .map(|column| column.trim().to_string())
+            .collect();
+        let invalid = columns.iter().find(|column| column.is_empty() || column.contains(char::is_whitespace));
+        if let Some(column) = invalid {
+            return Err(anyhow!("Invalid column name '{}'", column));
+        }
+        let rows: Vec<Vec<String>> = records.collect();
+        if rows.is_empty() {
+            return Err(anyhow!("Feed has no data rows"));
+        }
+        if let Some(index) = rows.iter().position(|row| row.len() != columns.len()) {
+            return Err(anyhow!(
+                "Row {} has {} fields, expected {}",
+                index + 2,
+                rows[index].len(),
+                columns.len()
+            ));
+        }
+        Ok(Self { columns, rows, strategy, seed: rand::rng().random(), ..Self::default() })
+    }
+
+    /// 同样的数据和随机种子，从第一行重新开始；对比压测中每一方各用一份，取到的行序列相同
+    pub fn restarted(&self) -> Self {
+        Self {
+            columns: self.columns.clone(),
+            rows: self.rows.clone(),
+            strategy: self.strategy,
+            seed: self.seed,
+            ..Self::default()
+        }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn strategy(&self) -> FeedStrategy {
+        self.strategy
+    }
+
+    /// 取下一轮的变量（列名 -> 值）和序号；顺序模式下数据用完时返回 None
+    pub fn next_values(&self) -> Option<(HashMap<String, String>, u64)> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.rows.is_empty() {
+            return Some((HashMap::new(), seq));
+        }
+        let index = match self.strategy {
+            FeedStrategy::Sequential => self.next_row.fetch_add(1, Ordering::Relaxed),
+            FeedStrategy::Circular => self.next_row.fetch_add(1, Ordering::Relaxed) % self.rows.len(),
+            FeedStrategy::Random => (mix(self.seed.wrapping_add(seq)) % self.rows.len() as u64) as usize,
+        };
+        let row = self.rows.get(index)?;
+        Some((self.columns.iter().cloned().zip(row.iter().cloned()).collect(), seq))
+    }
+}
+
+// splitmix64 的混合函数，把连续的序号打散成均匀分布的值
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// 解析 CSV：逗号分隔，双引号包裹的字段可以含逗号、换行和 "" 转义的引号；跳过空行
+fn parse_csv(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                if record.len() > 1 || !record[0].is_empty() {
+                    records.push(std::mem::take(&mut record));
+                } else {
+                    record.clear();
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(anyhow!("Unclosed quoted field"));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_and_strategies() {
+        let content = "user,password\nalice,\"se,cret\"\r\n\n\"bob \"\"b\"\"\",pw\n";
+        let feed = Feed::parse(content, FeedStrategy::Sequential).unwrap();
+        assert_eq!(feed.columns(), ["user", "password"]);
+        assert_eq!(feed.row_count(), 2);
+
+        let (first, seq) = feed.next_values().unwrap();
+        assert_eq!((first["user"].as_str(), first["password"].as_str(), seq), ("alice", "se,cret", 1));
+        assert_eq!(feed.next_values().unwrap().0["user"], "bob \"b\"");
+        assert!(feed.next_values().is_none());
+
+        let feed = Feed::parse(content, FeedStrategy::Circular).unwrap();
+        let users: Vec<String> = (0..3).map(|_| feed.next_values().unwrap().0["user"].clone()).collect();
+        assert_eq!(users, ["alice", "bob \"b\"", "alice"]);
+
+        // 重新开始的副本取到同样的随机行序列
+        let content = "n\n1\n2\n3\n4\n5\n6\n7\n8\n";
+        let feed = Feed::parse(content, FeedStrategy::Random).unwrap();
+        let copy = feed.restarted();
+        let rows = |feed: &Feed| -> Vec<String> { (0..20).map(|_| feed.next_values().unwrap().0["n"].clone()).collect() };
+        let first = rows(&feed);
+        assert_eq!(first, rows(&copy));
+        assert!(first.iter().any(|n| n != &first[0]));
+
+        assert!(Feed::parse("a,b\n1\n", FeedStrategy::Random).is_err());
+        assert!(Feed::parse("a\n\"1\n", FeedStrategy::Random).is_err());
+        assert!(Feed::parse("a,b\n", FeedStrategy::Random).is_err());
+    }
+}
@@ -0,0 +1,547 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write as _, fs, path::Path};
+
+/// 压测结果的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// 终端文本报告
+    #[default]
+    Text,
+    /// 完整结果的 JSON，可作为 --baseline 使用
+    Json,
+    /// 一行表头加一行汇总数据的 CSV
+    Csv,
+}
+
+/// 延迟汇总（毫秒）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub min: f64,
+    pub avg: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    #[serde(rename = "p99.9")]
+    pub p99_9: f64,
+    pub max: f64,
+}
+
+impl LatencySummary {
+    /// 从微秒直方图计算
+    pub fn from_histogram(histogram: &Histogram<u64>) -> Self {
+        let ms = |micros: u64| micros as f64 / 1000.0;
+        Self {
+            min: ms(histogram.min()),
+            avg: histogram.mean() / 1000.0,
+            p50: ms(histogram.value_at_quantile(0.5)),
+            p90: ms(histogram.value_at_quantile(0.9)),
+            p95: ms(histogram.value_at_quantile(0.95)),
+            p99: ms(histogram.value_at_quantile(0.99)),
+            p99_9: ms(histogram.value_at_quantile(0.999)),
+            max: ms(histogram.max()),
+        }
+    }
+}
+
+/// 请求失败的原因：没有收到完整响应、响应不符合 --expect-* 检查，或场景步骤无法继续
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Dns,
+    ConnectRefused,
+    ConnectTimeout,
+    Tls,
+    /// 收到响应头之前连接被关闭
+    ConnectionClosed,
+    /// 读取响应体时连接中断
+    ResetMidBody,
+    /// 超过请求总超时
+    Timeout,
+    /// 状态码正常但响应不符合 --expect-* 或 --max-body-size
+    Validation,
+    /// 场景步骤无法从响应中提取变量
+    Scenario,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Dns => "DNS failure",
+            Self::ConnectRefused => "connect refused",
+            Self::ConnectTimeout => "connect timeout",
+            Self::Tls => "TLS error",
+            Self::ConnectionClosed => "closed before response",
+            Self::ResetMidBody => "reset mid-body",
+            Self::Timeout => "total timeout",
+            Self::Validation => "validation failed",
+            Self::Scenario => "extraction failed",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// 某类失败的次数和一条示例错误信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorReport {
+    pub count: u32,
+    pub sample: String,
+}
+
+/// 单个阶段的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageReport {
+    pub duration_secs: f64,
+    /// 阶段开始和结束时的目标并发数
+    pub start_concurrency: usize,
+    pub target: usize,
+    pub requests: u64,
+    pub failed: u32,
+    pub latency_ms: LatencySummary,
+}
+
+/// 场景中单个步骤的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepReport {
+    pub name: String,
+    pub requests: u64,
+    pub failed: u32,
+    pub latency_ms: LatencySummary,
+}
+
+/// 场景中单个流程的结果；耗时为各步骤耗时之和，不含思考时间
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowReport {
+    pub name: String,
+    pub weight: u32,
+    pub iterations: u64,
+    pub failed: u32,
+    pub duration_ms: LatencySummary,
+    pub steps: Vec<StepReport>,
+}
+
+/// 连接统计；只在能区分新建和复用的连接时有（HTTP/3 没有）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionReport {
+    /// 新建的连接数
+    pub opened: u64,
+    /// 复用已有连接的请求占比（0-1）
+    pub reuse_ratio: f64,
+    /// 新建连接上请求的首字节时间，包含 TCP 和 TLS 握手
+    pub ttfb_new_ms: LatencySummary,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb_reused_ms: Option<LatencySummary>,
+    /// 估算的握手时间：新建和复用连接上首字节时间中位数之差；没有复用的连接时为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handshake_ms: Option<f64>,
+}
+
+/// 一次压测的完整结果，--output-format json 输出的就是它
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub url: String,
+    pub total_time_secs: f64,
+    pub requests: usize,
+    pub successful: u32,
+    pub failed: u32,
+    /// 失败请求中响应检查不通过的部分
+    #[serde(default)]
+    pub validation_failures: u32,
+    pub requests_per_second: f64,
+    /// 失败请求占比（0-1）
+    pub error_rate: f64,
+    pub latency_ms: LatencySummary,
+    /// 开放模型下不含排队时间的服务时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_time_ms: Option<LatencySummary>,
+    /// 首字节时间；旧版本保存的基线中没有这些字段
+    #[serde(default)]
+    pub ttfb_ms: LatencySummary,
+    #[serde(default)]
+    pub bytes_received: u64,
+    #[serde(default)]
+    pub bytes_per_second: f64,
+    pub status_codes: BTreeMap<u16, u32>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<ErrorKind, ErrorReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_rate: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub achieved_rate: Option<f64>,
+    /// 恒定速率模式下没有发送的请求（积压超过上限或到了结束时间）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dropped: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<StageReport>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flows: Vec<FlowReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<ConnectionReport>,
+}
+
+impl BenchReport {
+    /// 读取之前用 --output-format json 保存的结果
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read baseline: {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid baseline file: {}", path.display()))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let l = &self.latency_ms;
+        let mut csv = String::from(
+            "url,total_time_secs,requests,successful,failed,requests_per_second,error_rate,\
+             min_ms,avg_ms,p50_ms,p90_ms,p95_ms,p99_ms,p99_9_ms,max_ms,\
+             ttfb_p50_ms,ttfb_p99_ms,bytes_received,bytes_per_second\n",
+        );
+        let _ = writeln!(
+            csv,
+            "{},{:.3},{},{},{},{:.2},{:.4},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{},{:.0}",
+            csv_field(&self.url),
+            self.total_time_secs,
+            self.requests,
+            self.successful,
+            self.failed,
+            self.requests_per_second,
+            self.error_rate,
+            l.min,
+            l.avg,
+            l.p50,
+            l.p90,
+            l.p95,
+            l.p99,
+            l.p99_9,
+            l.max,
+            self.ttfb_ms.p50,
+            self.ttfb_ms.p99,
+            self.bytes_received,
+            self.bytes_per_second
+        );
+        csv
+    }
+
+    /// 取指标的值：延迟为毫秒，errors 为失败百分比
+    fn metric(&self, metric: Metric) -> f64 {
+        let l = &self.latency_ms;
+        match metric {
+            Metric::Min => l.min,
+            Metric::Avg => l.avg,
+            Metric::P50 => l.p50,
+            Metric::P90 => l.p90,
+            Metric::P95 => l.p95,
+            Metric::P99 => l.p99,
+            Metric::P999 => l.p99_9,
+            Metric::Max => l.max,
+            Metric::Rps => self.requests_per_second,
+            Metric::Errors => self.error_rate * 100.0,
+        }
+    }
+}
+
+// CSV 字段中有逗号、引号或换行时加引号
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 每秒的时间线数据
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimelineBucket {
+    pub requests: u32,
+    pub failed: u32,
+    pub total_micros: u64,
+    pub max_micros: u64,
+    pub bytes: u64,
+}
+
+impl TimelineBucket {
+    pub fn merge(&mut self, other: &Self) {
+        self.requests += other.requests;
+        self.failed += other.failed;
+        self.total_micros += other.total_micros;
+        self.max_micros = self.max_micros.max(other.max_micros);
+        self.bytes += other.bytes;
+    }
+}
+
+/// 把按秒统计的时间线写成 CSV
+pub fn write_timeline(path: &Path, timeline: &[TimelineBucket]) -> Result<()> {
+    let mut csv = String::from("second,requests,successful,failed,error_rate,avg_ms,max_ms,bytes\n");
+    for (second, bucket) in timeline.iter().enumerate() {
+        let (error_rate, avg_ms) = if bucket.requests == 0 {
+            (0.0, 0.0)
+        } else {
+            (
+                bucket.failed as f64 / bucket.requests as f64,
+                bucket.total_micros as f64 / bucket.requests as f64 / 1000.0,
+            )
+        };
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{:.4},{:.3},{:.3},{}",
+            second + 1,
+            bucket.requests,
+            bucket.requests - bucket.failed,
+            bucket.failed,
+            error_rate,
+            avg_ms,
+            bucket.max_micros as f64 / 1000.0,
+            bucket.bytes
+        );
+    }
+    fs::write(path, csv).with_context(|| format!("Failed to write timeline: {}", path.display()))
+}
+
+/// 可用于回归判定的指标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Min,
+    Avg,
+    P50,
+    P90,
+    P95,
+    P99,
+    P999,
+    Max,
+    Rps,
+    Errors,
+}
+
+impl Metric {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name.trim().to_ascii_lowercase().as_str() {
+            "min" => Self::Min,
+            "avg" | "mean" => Self::Avg,
+            "p50" | "median" => Self::P50,
+            "p90" => Self::P90,
+            "p95" => Self::P95,
+            "p99" => Self::P99,
+            "p99.9" | "p999" => Self::P999,
+            "max" => Self::Max,
+            "rps" => Self::Rps,
+            "errors" => Self::Errors,
+            other => {
+                return Err(anyhow!(
+                    "Unknown metric '{}' (use min, avg, p50, p90, p95, p99, p99.9, max, rps or errors)",
+                    other
+                ))
+            }
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Avg => "avg",
+            Self::P50 => "p50",
+            Self::P90 => "p90",
+            Self::P95 => "p95",
+            Self::P99 => "p99",
+            Self::P999 => "p99.9",
+            Self::Max => "max",
+            Self::Rps => "rps",
+            Self::Errors => "errors",
+        }
+    }
+
+    fn format(&self, value: f64) -> String {
+        match self {
+            Self::Rps => format!("{:.2} req/s", value),
+            Self::Errors => format!("{:.2}%", value),
+            _ => format!("{:.3} ms", value),
+        }
+    }
+}
+
+/// 阈值：带正负号的百分比相对基线，否则为绝对值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Absolute(f64),
+    /// 相对基线的变化比例，+10% 为 0.1
+    Relative(f64),
+}
+
+/// 一条 --fail-if 条件，例如 p95>+10%、errors>1%、rps<-5%、p99>250ms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    pub metric: Metric,
+    /// true 表示超过阈值时失败（>），false 表示低于阈值时失败（<）
+    pub above: bool,
+    pub limit: Limit,
+}
+
+impl Threshold {
+    /// 解析逗号分隔的条件列表
+    pub fn parse_list(value: &str) -> Result<Vec<Self>> {
+        value.split(',').filter(|c| !c.trim().is_empty()).map(Self::parse).collect()
+    }
+
+    pub fn parse(condition: &str) -> Result<Self> {
+        let condition = condition.trim();
+        let (metric, above, limit) = if let Some((metric, limit)) = condition.split_once('>') {
+            (metric, true, limit)
+        } else if let Some((metric, limit)) = condition.split_once('<') {
+            (metric, false, limit)
+        } else {
+            return Err(anyhow!("Invalid condition '{}', expected e.g. p95>+10% or errors>1%", condition));
+        };
+        let metric = Metric::parse(metric)?;
+        let limit = limit.trim();
+        let invalid = || anyhow!("Invalid threshold '{}' in condition '{}'", limit, condition);
+
+        let limit = if limit.starts_with(['+', '-']) {
+            let percent = limit.strip_suffix('%').ok_or_else(invalid)?;
+            Limit::Relative(percent.parse::<f64>().map_err(|_| invalid())? / 100.0)
+        } else {
+            let (number, scale) = match metric {
+                Metric::Errors => (limit.strip_suffix('%').unwrap_or(limit), 1.0),
+                Metric::Rps => (limit.strip_suffix("/s").unwrap_or(limit), 1.0),
+                _ => {
+                    if let Some(ms) = limit.strip_suffix("ms") {
+                        (ms, 1.0)
+                    } else if let Some(s) = limit.strip_suffix('s') {
+                        (s, 1000.0)
+                    } else {
+                        (limit, 1.0)
+                    }
+                }
+            };
+            Limit::Absolute(number.trim().parse::<f64>().map_err(|_| invalid())? * scale)
+        };
+        Ok(Self { metric, above, limit })
+    }
+
+    pub fn is_relative(&self) -> bool {
+        matches!(self.limit, Limit::Relative(_))
+    }
+
+    /// 判定条件；返回（是否通过，说明）
+    pub fn check(&self, report: &BenchReport, baseline: Option<&BenchReport>) -> Result<(bool, String)> {
+        let current = report.metric(self.metric);
+        let bound = match self.limit {
+            Limit::Absolute(value) => value,
+            Limit::Relative(change) => {
+                let baseline = baseline
+                    .ok_or_else(|| anyhow!("Condition on {} needs --baseline", self.metric.name()))?;
+                baseline.metric(self.metric) * (1.0 + change)
+            }
+        };
+        let failed = if self.above { current > bound } else { current < bound };
+        let relation = match (self.above, failed) {
+            (true, true) => ">",
+            (true, false) => "<=",
+            (false, true) => "<",
+            (false, false) => ">=",
+        };
+        let mut message = format!(
+            "{} {} {} {}",
+            self.metric.name(),
+            self.metric.format(current),
+            relation,
+            self.metric.format(bound)
+        );
+        if let (Limit::Relative(change), Some(baseline)) = (self.limit, baseline) {
+            let _ = write!(
+                message,
+                " (baseline {} {:+.0}%)",
+                self.metric.format(baseline.metric(self.metric)),
+                change * 100.0
+            );
+        }
+        Ok((!failed, message))
+    }
+}
+
+/// 与基线对比的主要指标：（名称，当前值，基线值，变化比例）
+pub fn compare(report: &BenchReport, baseline: &BenchReport) -> Vec<(&'static str, String, String, Option<f64>)> {
+    [Metric::Avg, Metric::P50, Metric::P95, Metric::P99, Metric::Max, Metric::Rps, Metric::Errors]
+        .into_iter()
+        .map(|metric| {
+            let current = report.metric(metric);
+            let previous = baseline.metric(metric);
+            let change = (previous != 0.0).then(|| (current - previous) / previous);
+            (metric.name(), metric.format(current), metric.format(previous), change)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(p95: f64, rps: f64, error_rate: f64) -> BenchReport {
+        BenchReport {
+            url: "http://example.com/a,b".to_string(),
+            total_time_secs: 10.0,
+            requests: 1000,
+            successful: 990,
+            failed: 10,
+            validation_failures: 0,
+            requests_per_second: rps,
+            error_rate,
+            latency_ms: LatencySummary { p95, ..Default::default() },
+            service_time_ms: None,
+            ttfb_ms: LatencySummary::default(),
+            bytes_received: 0,
+            bytes_per_second: 0.0,
+            status_codes: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            target_rate: None,
+            achieved_rate: None,
+            dropped: None,
+            stages: Vec::new(),
+            flows: Vec::new(),
+            connections: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_thresholds() {
+        let conditions = Threshold::parse_list("p95>+10%, errors>1%,rps<-5%,p99>1.5s").unwrap();
+        assert_eq!(
+            conditions,
+            vec![
+                Threshold { metric: Metric::P95, above: true, limit: Limit::Relative(0.1) },
+                Threshold { metric: Metric::Errors, above: true, limit: Limit::Absolute(1.0) },
+                Threshold { metric: Metric::Rps, above: false, limit: Limit::Relative(-0.05) },
+                Threshold { metric: Metric::P99, above: true, limit: Limit::Absolute(1500.0) },
+            ]
+        );
+        assert!(Threshold::parse("p95=10").is_err());
+        assert!(Threshold::parse("p42>10").is_err());
+        assert!(Threshold::parse("p95>+10").is_err());
+    }
+
+    #[test]
+    fn test_check_against_baseline() {
+        let baseline = report(100.0, 500.0, 0.001);
+        let slower = report(115.0, 480.0, 0.02);
+
+        let p95 = Threshold::parse("p95>+10%").unwrap();
+        assert!(!p95.check(&slower, Some(&baseline)).unwrap().0);
+        assert!(p95.check(&baseline, Some(&baseline)).unwrap().0);
+        assert!(p95.check(&slower, None).is_err());
+
+        let errors = Threshold::parse("errors>1%").unwrap();
+        assert!(!errors.check(&slower, None).unwrap().0);
+        assert!(errors.check(&baseline, None).unwrap().0);
+
+        let rps = Threshold::parse("rps<-5%").unwrap();
+        assert!(rps.check(&slower, Some(&baseline)).unwrap().0);
+
+        let round_trip: BenchReport = serde_json::from_str(&slower.to_json().unwrap()).unwrap();
+        assert_eq!(round_trip.latency_ms.p95, 115.0);
+        assert!(slower.to_csv().lines().nth(1).unwrap().starts_with("\"http://example.com/a,b\",10.000,1000,"));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::bench::parse_duration;
+use crate::core::parse_header;
+use anyhow::{anyhow, Context, Result};
+use rand::Rng;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Method,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path, time::Duration};
+use uuid::Uuid;
+
+/// 压测场景：按权重随机选择的多个请求流程
+#[derive(Debug)]
+pub struct Scenario {
+    pub flows: Vec<Flow>,
+}
+
+/// 一个流程：依次执行的步骤，例如登录、列表、详情
+#[derive(Debug)]
+pub struct Flow {
+    pub name: String,
+    pub weight: u32,
+    pub steps: Vec<Step>,
+}
+
+/// 流程中的一个请求
+#[derive(Debug)]
+pub struct Step {
+    pub name: String,
+    pub method: Method,
+    url: Template,
+    headers: Vec<Template>,
+    body: Option<Template>,
+    /// 变量名 -> 响应 JSON 中的 JSON Pointer
+    extract: Vec<(String, String)>,
+    /// 这一步完成后的等待时间，不计入耗时
+    pub think_time: Option<Duration>,
+}
+
+/// 渲染好的步骤请求
+pub struct StepRequest {
+    pub url: String,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Option<String>,
+}
+
+// 场景文件的 TOML 结构
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    #[serde(rename = "flow")]
+    flows: Vec<FlowFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FlowFile {
+    name: String,
+    #[serde(default = "default_weight")]
+    weight: u32,
+    #[serde(rename = "step")]
+    steps: Vec<StepFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepFile {
+    name: Option<String>,
+    method: Option<String>,
+    url: String,
+    #[serde(default)]
+    headers: Vec<String>,
+    body: Option<String>,
+    #[serde(default)]
+    extract: HashMap<String, String>,
+    think_time: Option<String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl Scenario {
+    /// 读取场景文件；columns 是 --feed 数据的列名，步骤中可以直接使用
+    pub fn load(path: &Path, columns: &[String]) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read scenario {}", path.display()))?;
+        Self::parse(&content, columns).with_context(|| format!("Invalid scenario {}", path.display()))
+    }
+
+    pub fn parse(content: &str, columns: &[String]) -> Result<Self> {
+        let file: ScenarioFile = toml::from_str(content).map_err(|e| anyhow!("{}", e))?;
+        if file.flows.is_empty() {
+            return Err(anyhow!("Scenario has no [[flow]] entries"));
+        }
+        let flows = file
+            .flows
+            .into_iter()
+            .map(|flow| Flow::compile(flow, columns))
+            .collect::<Result<Vec<_>>>()?;
+        if flows.iter().all(|flow| flow.weight == 0) {
+            return Err(anyhow!("At least one flow needs a weight above 0"));
+        }
+        Ok(Self { flows })
+    }
+
+    /// 按权重随机选择一个流程
+    pub fn pick_flow(&self) -> usize {
+        let total: u32 = self.flows.iter().map(|flow| flow.weight).sum();
+        let mut point = rand::rng().random_range(0..total);
+        for (index, flow) in self.flows.iter().enumerate() {
+            if point < flow.weight {
+                return index;
+            }
+            point -= flow.weight;
+        }
+        self.flows.len() - 1
+    }
+}
+
+impl Flow {
+    fn compile(file: FlowFile, columns: &[String]) -> Result<Self> {
+        if file.steps.is_empty() {
+            return Err(anyhow!("Flow '{}' has no [[flow.step]] entries", file.name));
+        }
+        // 步骤只能使用数据列和前面步骤提取出的变量
+        let mut known: Vec<String> = columns.to_vec();
+        let mut steps = Vec::with_capacity(file.steps.len());
+        for (index, step) in file.steps.into_iter().enumerate() {
+            let step = Step::compile(step, index)
+                .with_context(|| format!("Flow '{}', step {}", file.name, index + 1))?;
+            for variable in step.variables() {
+                if !known.iter().any(|name| name == variable) {
+                    return Err(anyhow!(
+                        "Flow '{}', step '{}' uses {{{{{}}}}}, which is neither a --feed column nor extracted by an earlier step",
+                        file.name,
+                        step.name,
+                        variable
+                    ));
+                }
+            }
+            known.extend(step.extract.iter().map(|(name, _)| name.clone()));
+            steps.push(step);
+        }
+        Ok(Self { name: file.name, weight: file.weight, steps })
+    }
+}
+
+impl Step {
+    fn compile(file: StepFile, index: usize) -> Result<Self> {
+        let method = file.method.as_deref().unwrap_or("GET");
+        let method = Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| anyhow!("Invalid HTTP method: {}", method))?;
+        let mut extract: Vec<(String, String)> = file.extract.into_iter().collect();
+        extract.sort();
+        let invalid = extract.iter().find(|(_, pointer)| !pointer.is_empty() && !pointer.starts_with('/'));
+        if let Some((name, pointer)) = invalid {
+            return Err(anyhow!("Invalid JSON pointer '{}' for '{}' (must start with '/')", pointer, name));
+        }
+        Ok(Self {
+            name: file.name.unwrap_or_else(|| format!("step {}", index + 1)),
+            method,
+            url: Template::parse(&file.url)?,
+            headers: file.headers.iter().map(|header| Template::parse(header)).collect::<Result<_>>()?,
+            body: file.body.as_deref().map(Template::parse).transpose()?,
+            extract,
+            think_time: file.think_time.as_deref().map(parse_duration).transpose()?,
+        })
+    }
+
+    fn variables(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(&self.url)
+            .chain(&self.headers)
+            .chain(&self.body)
+            .flat_map(Template::variables)
+    }
+
+    /// 是否需要保留响应体用于提取变量
+    pub fn extracts(&self) -> bool {
+        !self.extract.is_empty()
+    }
+
+    /// 用当前变量渲染请求；相对 URL 拼接在压测 URL 之后
+    pub fn render(&self, base: &str, variables: &HashMap<String, String>, seq: u64) -> Result<StepRequest> {
+        let url = self.url.render(variables, seq)?;
+        let url = if url.contains("://") {
+            url
+        } else {
+            format!("{}/{}", base.trim_end_matches('/'), url.trim_start_matches('/'))
+        };
+        let headers = self
+            .headers
+            .iter()
+            .map(|header| parse_header(&header.render(variables, seq)?))
+            .collect::<Result<_>>()?;
+        let body = self.body.as_ref().map(|body| body.render(variables, seq)).transpose()?;
+        Ok(StepRequest { url, headers, body })
+    }
+
+    /// 从 JSON 响应体中提取变量，字符串取原值，其他类型取 JSON 文本
+    pub fn extract(&self, body: &[u8], variables: &mut HashMap<String, String>) -> Result<()> {
+        if self.extract.is_empty() {
+            return Ok(());
+        }
+        let json: serde_json::Value =
+            serde_json::from_slice(body).map_err(|e| anyhow!("response is not JSON: {}", e))?;
+        for (name, pointer) in &self.extract {
+            let value = json
+                .pointer(pointer)
+                .ok_or_else(|| anyhow!("'{}' not found in response for '{}'", pointer, name))?;
+            let value = match value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            variables.insert(name.clone(), value);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Variable(String),
+    /// {{uuid}}：随机 UUID v4
+    Uuid,
+    /// {{randint LOW HIGH}}：闭区间内的随机整数
+    RandInt(i64, i64),
+    /// {{seq}}：本轮的序号，从 1 开始
+    Seq,
+}
+
+impl Segment {
+    fn placeholder(name: &str) -> Result<Self> {
+        let mut words = name.split_whitespace();
+        match (words.next(), words.next(), words.next(), words.next()) {
+            (Some("uuid"), None, ..) => Ok(Self::Uuid),
+            (Some("seq"), None, ..) => Ok(Self::Seq),
+            (Some("randint"), Some(low), Some(high), None) => {
+                let parse = |value: &str| {
+                    value
+                        .parse::<i64>()
+                        .map_err(|_| anyhow!("Invalid number '{}' in {{{{{}}}}}", value, name))
+                };
+                let (low, high) = (parse(low)?, parse(high)?);
+                if low > high {
+                    return Err(anyhow!("Empty range in {{{{{}}}}}", name));
+                }
+                Ok(Self::RandInt(low, high))
+            }
+            (Some(variable), None, ..) => Ok(Self::Variable(variable.to_string())),
+            _ => Err(anyhow!(
+                "Unknown generator {{{{{}}}}} (use uuid, seq or randint LOW HIGH)",
+                name
+            )),
+        }
+    }
+}
+
+/// 带占位符的文本：{{name}} 取变量，{{uuid}}、{{seq}}、{{randint 1 1000}} 为内置生成器；
+/// {{{{ 表示字面的 {{
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = text;
+        while let Some(open) = rest.find("{{") {
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            if rest[open..].starts_with("{{{{") {
+                segments.push(Segment::Literal("{{".to_string()));
+                rest = &rest[open + 4..];
+                continue;
+            }
+            let close = rest[open..]
+                .find("}}")
+                .ok_or_else(|| anyhow!("Unclosed '{{{{' in '{}'", text))?;
+            let name = rest[open + 2..open + close].trim();
+            if name.is_empty() {
+                return Err(anyhow!("Empty placeholder in '{}'", text));
+            }
+            segments.push(Segment::placeholder(name)?);
+            rest = &rest[open + close + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(Self { segments })
+    }
+
+    /// 没有任何占位符
+    pub fn is_static(&self) -> bool {
+        self.segments.iter().all(|segment| matches!(segment, Segment::Literal(_)))
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Variable(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn render(&self, variables: &HashMap<String, String>, seq: u64) -> Result<String> {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => output.push_str(text),
+                Segment::Variable(name) => output.push_str(
+                    variables.get(name).ok_or_else(|| anyhow!("Variable '{}' is not set", name))?,
+                ),
+                Segment::Uuid => output.push_str(&Uuid::new_v4().to_string()),
+                Segment::RandInt(low, high) => output.push_str(&rand::rng().random_range(*low..=*high).to_string()),
+                Segment::Seq => output.push_str(&seq.to_string()),
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENARIO: &str = r#"
+        [[flow]]
+        name = "browse"
+        weight = 3
+
+        [[flow.step]]
+        name = "login"
+        method = "post"
+        url = "/login"
+        headers = ["Content-Type: application/json"]
+        body = '{"user": "demo"}'
+        extract = { token = "/token", item = "/items/0/id" }
+        think_time = "500ms"
+
+        [[flow.step]]
+        name = "item"
+        url = "/items/{{ item }}"
+        headers = ["Authorization: Bearer {{token}}"]
+    "#;
+
+    #[test]
+    fn test_parse_and_render_scenario() {
+        let scenario = Scenario::parse(SCENARIO, &[]).unwrap();
+        let flow = &scenario.flows[0];
+        assert_eq!(flow.weight, 3);
+        assert_eq!(flow.steps[0].method, Method::POST);
+        assert_eq!(flow.steps[0].think_time, Some(Duration::from_millis(500)));
+        assert_eq!(scenario.pick_flow(), 0);
+
+        let mut variables = HashMap::new();
+        flow.steps[0]
+            .extract(br#"{"token": "abc", "items": [{"id": 42}]}"#, &mut variables)
+            .unwrap();
+        let request = flow.steps[1].render("http://localhost:8080/api/", &variables, 1).unwrap();
+        assert_eq!(request.url, "http://localhost:8080/api/items/42");
+        assert_eq!(request.headers[0].1, "Bearer abc");
+        assert!(flow.steps[0].extract(br#"{"token": "abc"}"#, &mut variables).is_err());
+
+        // 使用尚未提取的变量、未闭合的占位符
+        let unknown = SCENARIO.replace("{{token}}", "{{session}}");
+        assert!(Scenario::parse(&unknown, &[]).is_err());
+        assert!(Scenario::parse(&unknown, &["session".to_string()]).is_ok());
+        assert!(Template::parse("/items/{{id").is_err());
+    }
+
+    #[test]
+    fn test_template_generators() {
+        let template = Template::parse("/users/{{user}}/{{seq}}?n={{ randint 5 5 }}&id={{uuid}}").unwrap();
+        assert_eq!(template.variables().collect::<Vec<_>>(), ["user"]);
+        let variables = HashMap::from([("user".to_string(), "alice".to_string())]);
+        let rendered = template.render(&variables, 7).unwrap();
+        assert!(rendered.starts_with("/users/alice/7?n=5&id="));
+        assert_eq!(rendered.len(), "/users/alice/7?n=5&id=".len() + 36);
+
+        assert!(Template::parse("plain").unwrap().is_static());
+        // {{{{ 转义为字面的 {{，不算占位符
+        let escaped = Template::parse(r#"{"q": "{{{{name}}", "n": {{seq}}}"#).unwrap();
+        assert_eq!(escaped.render(&HashMap::new(), 3).unwrap(), r#"{"q": "{{name}}", "n": 3}"#);
+        assert!(Template::parse("{{{{ unclosed").unwrap().is_static());
+        assert!(Template::parse("{{randint 10 1}}").is_err());
+        assert!(Template::parse("{{random 1 10}}").is_err());
+    }
+}
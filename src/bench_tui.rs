@@ -0,0 +1,400 @@
+use crate::bench::{micros, millis, new_histogram, RunState};
+use crate::bench_report::ErrorKind;
+use anyhow::Result;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use hdrhistogram::Histogram;
+use indicatif::HumanBytes;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, VecDeque},
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Gauge, LineGauge, Paragraph, Sparkline},
+    Frame, Terminal,
+};
+
+/// 面板刷新间隔
+const TICK_RATE: Duration = Duration::from_millis(200);
+
+/// 火花线保留的秒数
+const HISTORY_LEN: usize = 300;
+
+/// 面板上显示的百分位
+const PERCENTILES: [(&str, f64); 4] = [("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("p99.9", 99.9)];
+
+/// 一个完成的请求，由压测协程发给面板
+pub struct LiveSample {
+    pub latency: Duration,
+    pub status_code: Option<u16>,
+    pub error: Option<ErrorKind>,
+    pub bytes: u64,
+    /// 按 --expect-status 等检查判定的结果
+    pub success: bool,
+}
+
+/// 面板需要的压测设置
+pub struct DashboardConfig {
+    /// 标题中显示的目标（方法和 URL）
+    pub title: String,
+    /// 按数量压测时的请求总数
+    pub requests: Option<usize>,
+    /// 按时长（或分阶段）压测时的总时长
+    pub duration: Option<Duration>,
+    /// 分阶段压测时并发数由阶段决定，不能手动调整
+    pub adjustable: bool,
+}
+
+// 面板汇总的实时数据
+struct Dashboard {
+    config: DashboardConfig,
+    latencies: Histogram<u64>,
+    /// 当前这一秒内的延迟，用于每秒的 p99
+    window: Histogram<u64>,
+    /// 已汇总到历史中的秒数
+    seconds: u64,
+    rps_history: VecDeque<u64>,
+    p99_history: VecDeque<u64>,
+    status_codes: BTreeMap<u16, u64>,
+    errors: BTreeMap<ErrorKind, u64>,
+    completed: u64,
+    failed: u64,
+    bytes: u64,
+}
+
+impl Dashboard {
+    fn new(config: DashboardConfig) -> Self {
+        Self {
+            config,
+            latencies: new_histogram(),
+            window: new_histogram(),
+            seconds: 0,
+            rps_history: VecDeque::with_capacity(HISTORY_LEN),
+            p99_history: VecDeque::with_capacity(HISTORY_LEN),
+            status_codes: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            completed: 0,
+            failed: 0,
+            bytes: 0,
+        }
+    }
+
+    fn record(&mut self, sample: LiveSample) {
+        let latency = micros(sample.latency);
+        self.latencies.saturating_record(latency);
+        self.window.saturating_record(latency);
+        self.completed += 1;
+        self.bytes += sample.bytes;
+        if !sample.success {
+            self.failed += 1;
+        }
+        if let Some(code) = sample.status_code {
+            *self.status_codes.entry(code).or_insert(0) += 1;
+        }
+        if let Some(kind) = sample.error {
+            *self.errors.entry(kind).or_insert(0) += 1;
+        }
+    }
+
+    // 把到 elapsed 为止已结束的每一秒汇总进火花线；没有请求完成的秒记为 0
+    fn roll(&mut self, elapsed: Duration) {
+        while self.seconds < elapsed.as_secs() {
+            let requests = self.window.len();
+            let p99 = if requests > 0 { self.window.value_at_quantile(0.99) } else { 0 };
+            for (history, value) in [(&mut self.rps_history, requests), (&mut self.p99_history, p99)] {
+                if history.len() == HISTORY_LEN {
+                    history.pop_front();
+                }
+                history.push_back(value);
+            }
+            self.window.reset();
+            self.seconds += 1;
+        }
+    }
+
+    fn progress(&self, elapsed: Duration) -> f64 {
+        let ratio = match (self.config.duration, self.config.requests) {
+            (Some(duration), _) => elapsed.as_secs_f64() / duration.as_secs_f64(),
+            (None, Some(requests)) => self.completed as f64 / requests.max(1) as f64,
+            (None, None) => 0.0,
+        };
+        ratio.clamp(0.0, 1.0)
+    }
+}
+
+/// 在当前终端显示实时面板，直到压测结束（state.finished()）。
+/// 按键：空格/p 暂停，+/- 和 ]/[ 调整并发数，q/Esc/Ctrl-C 中止
+pub fn run_dashboard(
+    config: DashboardConfig,
+    state: Arc<RunState>,
+    mut receiver: mpsc::UnboundedReceiver<LiveSample>,
+    start: Instant,
+) -> Result<()> {
+    // 设置终端
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let mut dashboard = Dashboard::new(config);
+    let result = (|| -> Result<()> {
+        while !state.finished() {
+            while let Ok(sample) = receiver.try_recv() {
+                dashboard.record(sample);
+            }
+            dashboard.roll(start.elapsed());
+            terminal.draw(|f| draw_dashboard(f, &dashboard, &state, start.elapsed()))?;
+
+            if event::poll(TICK_RATE)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    let adjustable = dashboard.config.adjustable;
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => state.abort(),
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => state.abort(),
+                        KeyCode::Char(' ') | KeyCode::Char('p') => state.toggle_pause(),
+                        KeyCode::Char('+') | KeyCode::Char('=') if adjustable => {
+                            state.set_concurrency(state.concurrency() + 1)
+                        }
+                        KeyCode::Char('-') if adjustable => {
+                            state.set_concurrency(state.concurrency().saturating_sub(1))
+                        }
+                        KeyCode::Char(']') if adjustable => state.set_concurrency(state.concurrency() + 10),
+                        KeyCode::Char('[') if adjustable => {
+                            state.set_concurrency(state.concurrency().saturating_sub(10))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    // 恢复终端
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn draw_dashboard<B: tui::backend::Backend>(
+    f: &mut Frame<B>,
+    dashboard: &Dashboard,
+    state: &RunState,
+    elapsed: Duration,
+) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Min(5),
+            Constraint::Length(6),
+            Constraint::Length(1),
+        ])
+        .split(f.size());
+
+    let (status, color) = if state.aborted() {
+        ("ABORTING", Color::Red)
+    } else if state.paused() {
+        ("PAUSED", Color::Yellow)
+    } else {
+        ("RUNNING", Color::Green)
+    };
+    let elapsed_secs = elapsed.as_secs_f64().max(0.001);
+    let header = Paragraph::new(Spans::from(vec![
+        Span::styled(status, Style::default().fg(color).add_modifier(Modifier::BOLD)),
+        Span::raw(format!(
+            "  {:.0}s  requests {}  failed {}  avg {:.1} req/s  received {} ({}/s)",
+            elapsed.as_secs_f64(),
+            dashboard.completed,
+            dashboard.failed,
+            dashboard.completed as f64 / elapsed_secs,
+            HumanBytes(dashboard.bytes),
+            HumanBytes((dashboard.bytes as f64 / elapsed_secs) as u64),
+        )),
+    ]))
+    .block(Block::default().borders(Borders::ALL).title(format!(" surf bench {} ", dashboard.config.title)));
+    f.render_widget(header, chunks[0]);
+
+    let ratio = dashboard.progress(elapsed);
+    let progress = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title(" Progress "))
+        .gauge_style(Style::default().fg(Color::Cyan))
+        .ratio(ratio)
+        .label(format!("{:.0}%", ratio * 100.0));
+    f.render_widget(progress, chunks[1]);
+
+    draw_sparkline(
+        f,
+        chunks[2],
+        format!(" Requests/s (last {}) ", dashboard.rps_history.back().copied().unwrap_or(0)),
+        &dashboard.rps_history,
+        Color::Green,
+    );
+    draw_sparkline(
+        f,
+        chunks[3],
+        format!(
+            " p99 latency (last {:.1} ms) ",
+            millis(dashboard.p99_history.back().copied().unwrap_or(0))
+        ),
+        &dashboard.p99_history,
+        Color::Magenta,
+    );
+
+    let details = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(35), Constraint::Percentage(25)])
+        .split(chunks[4]);
+    draw_percentiles(f, details[0], dashboard);
+    draw_breakdown(f, details[1], dashboard);
+
+    let connections = Paragraph::new(vec![
+        Spans::from(format!("In flight:   {}", state.in_flight())),
+        Spans::from(format!("Concurrency: {}", state.concurrency())),
+    ])
+    .block(Block::default().borders(Borders::ALL).title(" Connections "));
+    f.render_widget(connections, details[2]);
+
+    let help = if dashboard.config.adjustable {
+        "space: pause/resume  +/-: concurrency ±1  ]/[: ±10  q: abort and report"
+    } else {
+        "space: pause/resume  q: abort and report"
+    };
+    f.render_widget(Paragraph::new(Span::styled(help, Style::default().fg(Color::DarkGray))), chunks[5]);
+}
+
+// 火花线只显示能放下的最近几秒
+fn draw_sparkline<B: tui::backend::Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    title: String,
+    history: &VecDeque<u64>,
+    color: Color,
+) {
+    let width = area.width.saturating_sub(2) as usize;
+    let data: Vec<u64> = history.iter().skip(history.len().saturating_sub(width)).copied().collect();
+    let sparkline = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .style(Style::default().fg(color))
+        .data(&data);
+    f.render_widget(sparkline, area);
+}
+
+// 各百分位相对最大延迟的比例
+fn draw_percentiles<B: tui::backend::Backend>(f: &mut Frame<B>, area: Rect, dashboard: &Dashboard) {
+    let block = Block::default().borders(Borders::ALL).title(" Latency (ms) ");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1); PERCENTILES.len()])
+        .split(inner);
+    let max = dashboard.latencies.max().max(1);
+    for ((name, percentile), row) in PERCENTILES.iter().zip(rows) {
+        let value = if dashboard.latencies.is_empty() {
+            0
+        } else {
+            dashboard.latencies.value_at_percentile(*percentile)
+        };
+        let gauge = LineGauge::default()
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio((value as f64 / max as f64).clamp(0.0, 1.0))
+            .label(format!("{:<5} {:>9.2}", name, millis(value)));
+        f.render_widget(gauge, row);
+    }
+}
+
+// 状态码和错误类别分布，按数量从多到少
+fn draw_breakdown<B: tui::backend::Backend>(f: &mut Frame<B>, area: Rect, dashboard: &Dashboard) {
+    let mut lines: Vec<(String, u64, Color)> = dashboard
+        .status_codes
+        .iter()
+        .map(|(code, count)| {
+            let color = if (200..400).contains(code) { Color::Green } else { Color::Red };
+            (code.to_string(), *count, color)
+        })
+        .collect();
+    lines.extend(
+        dashboard
+            .errors
+            .iter()
+            .map(|(kind, count)| (kind.label().to_string(), *count, Color::Red)),
+    );
+    lines.sort_by_key(|line| Reverse(line.1));
+
+    let total = dashboard.completed.max(1) as f64;
+    let text: Vec<Spans> = lines
+        .into_iter()
+        .map(|(name, count, color)| {
+            Spans::from(vec![
+                Span::styled(format!("{:<22}", name), Style::default().fg(color)),
+                Span::raw(format!("{:>8} ({:.1}%)", count, count as f64 * 100.0 / total)),
+            ])
+        })
+        .collect();
+    let breakdown = Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(" Status / errors "));
+    f.render_widget(breakdown, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(millis: u64, status_code: Option<u16>) -> LiveSample {
+        LiveSample {
+            latency: Duration::from_millis(millis),
+            status_code,
+            error: status_code.is_none().then_some(ErrorKind::ConnectRefused),
+            bytes: 100,
+            success: status_code.is_some_and(|code| (200..400).contains(&code)),
+        }
+    }
+
+    #[test]
+    fn test_roll_per_second_history() {
+        let mut dashboard = Dashboard::new(DashboardConfig {
+            title: "http://localhost/".to_string(),
+            requests: Some(10),
+            duration: None,
+            adjustable: true,
+        });
+        dashboard.record(sample(10, Some(200)));
+        dashboard.record(sample(20, Some(500)));
+        dashboard.record(sample(30, None));
+
+        // 第一秒还没结束时不汇总
+        dashboard.roll(Duration::from_millis(900));
+        assert!(dashboard.rps_history.is_empty());
+
+        // 跨过空白的秒时补 0
+        dashboard.roll(Duration::from_millis(3100));
+        assert_eq!(dashboard.rps_history, [3, 0, 0]);
+        assert_eq!(dashboard.p99_history[0] / 1000, 30);
+        assert_eq!(dashboard.p99_history[1], 0);
+
+        assert_eq!(dashboard.failed, 2);
+        assert_eq!(dashboard.status_codes[&500], 1);
+        assert_eq!(dashboard.errors[&ErrorKind::ConnectRefused], 1);
+        assert_eq!(dashboard.bytes, 300);
+        assert!((dashboard.progress(Duration::ZERO) - 0.3).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{anyhow, Context, Result};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 支持的哈希算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// 只用于 Metalink 分片哈希等外部给定的校验值，许多 .meta4 文件的分片使用 SHA-1
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+/// 增量哈希计算器
+enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha384(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        match self {
+            Hasher::Sha1(h) => hex::encode(h.finalize()),
+            Hasher::Sha256(h) => hex::encode(h.finalize()),
+            Hasher::Sha384(h) => hex::encode(h.finalize()),
+            Hasher::Sha512(h) => hex::encode(h.finalize()),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    /// 根据名称解析算法，兼容 "sha-256" 与 "sha256" 两种写法
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "sha384" => Some(HashAlgorithm::Sha384),
+            "sha512" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// 算法的标准名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha-1",
+            HashAlgorithm::Sha256 => "sha-256",
+            HashAlgorithm::Sha384 => "sha-384",
+            HashAlgorithm::Sha512 => "sha-512",
+        }
+    }
+
+    /// 算法强度，用于在多个可选哈希中挑选最强的一个
+    pub fn strength(&self) -> u8 {
+        match self {
+            HashAlgorithm::Sha1 => 0,
+            HashAlgorithm::Sha256 => 1,
+            HashAlgorithm::Sha384 => 2,
+            HashAlgorithm::Sha512 => 3,
+        }
+    }
+
+    fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha384 => Hasher::Sha384(Sha384::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    /// 计算内存数据的哈希值（十六进制小写）
+    pub fn digest(&self, data: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize_hex()
+    }
+}
+
+/// 计算整个文件的哈希值
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open file for hashing: {}", path.display()))?;
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+
+    loop {
+        let read = file.read(&mut buffer).context("Failed to read file for hashing")?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize_hex())
+}
+
+/// 计算文件中指定范围的哈希值
+pub fn hash_file_range(file: &mut File, start: u64, len: u64, algorithm: HashAlgorithm) -> Result<String> {
+    file.seek(SeekFrom::Start(start)).context("Failed to seek file for hashing")?;
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    let mut remaining = len;
+
+    while remaining > 0 {
+        let want = std::cmp::min(remaining, buffer.len() as u64) as usize;
+        let read = file.read(&mut buffer[..want]).context("Failed to read file for hashing")?;
+        if read == 0 {
+            return Err(anyhow!("Unexpected end of file while hashing range {}-{}", start, start + len));
+        }
+        hasher.update(&buffer[..read]);
+        remaining -= read as u64;
+    }
+
+    Ok(hasher.finalize_hex())
+}
+
+/// 比较两个十六进制哈希值（忽略大小写）
+pub fn hashes_match(expected: &str, actual: &str) -> bool {
+    expected.trim().eq_ignore_ascii_case(actual.trim())
+}
+
+/// 用户指定的期望哈希值，格式为 "sha256:<hex>"
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedChecksum {
+    pub algorithm: HashAlgorithm,
+    pub hex: String,
+}
+
+impl ExpectedChecksum {
+    pub fn parse(value: &str) -> Result<Self> {
+        let (name, hex) = value
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid checksum '{}', expected <algorithm>:<hex>", value))?;
+        let algorithm = HashAlgorithm::from_name(name)
+            .ok_or_else(|| anyhow!("Unsupported checksum algorithm: {}", name))?;
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("Invalid checksum value: {}", hex));
+        }
+        Ok(Self {
+            algorithm,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+
+    /// 校验文件，不匹配时返回错误
+    pub fn verify(&self, path: &Path) -> Result<()> {
+        let actual = hash_file(path, self.algorithm)?;
+        if hashes_match(&self.hex, &actual) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "{} checksum mismatch for {}: expected {}, got {}",
+                self.algorithm.name(),
+                path.display(),
+                self.hex,
+                actual
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_algorithm_names() {
+        assert_eq!(HashAlgorithm::from_name("sha-256"), Some(HashAlgorithm::Sha256));
+        assert_eq!(HashAlgorithm::from_name("SHA512"), Some(HashAlgorithm::Sha512));
+        assert_eq!(HashAlgorithm::from_name("md5"), None);
+    }
+
+    #[test]
+    fn test_digest() {
+        assert_eq!(
+            HashAlgorithm::Sha256.digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(HashAlgorithm::Sha1.digest(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert!(hashes_match("BA7816BF", "ba7816bf"));
+    }
+
+    #[test]
+    fn test_expected_checksum_parse() {
+        let expected = ExpectedChecksum::parse("SHA-256:BA7816BF").unwrap();
+        assert_eq!(expected.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(expected.hex, "ba7816bf");
+        assert!(ExpectedChecksum::parse("md5:abc").is_err());
+        assert!(ExpectedChecksum::parse("ba7816bf").is_err());
+    }
+}
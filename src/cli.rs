@@ -1,16 +1,36 @@
-use crate::core::{benchmark_url, build_client, download_file, TimeoutError, ClientType};
+use crate::core::{build_client, download_file, parse_header, parse_size, TimeoutError, ClientType};
+use crate::bench::{benchmark_url, load_body, parse_duration, parse_request_rate, BenchOptions, Stage};
+use crate::bench_report::{OutputFormat, Threshold};
+use crate::bench_scenario::Scenario;
+use crate::bench_feed::{Feed, FeedStrategy};
+use crate::bench_compare::{Protocol, Variant};
+use crate::bench_conn::ConnectionLimits;
+use crate::bench_expect::{Expectations, JsonExpectation, StatusMatch};
 use crate::log::{init_logger, log_info, log_error, log_debug, log_warn};
 use crate::config::{Config, Profile};
 use crate::history::{RequestHistory, HistoryEntry};
 use crate::response::{ResponseFormatter, ResponseAnalyzer};
 use crate::cache::CachedConfig;
+use crate::metalink::download_metalink;
+use crate::extract::{extract_archive, ArchiveKind};
+use crate::progress::{init_progress, ProgressFormat};
+use crate::hooks::{DownloadHooks, HookContext};
+use crate::schedule::{self, init_schedule, parse_time_of_day, BandwidthRule, DownloadPaused, Schedule, TimeWindow};
+use crate::mirror::{mirror_site, MirrorOptions};
+use crate::stream::{detect_manifest, download_stream, StreamSelection};
+use crate::checksum::ExpectedChecksum;
+use crate::store::{self, Store, StoreLookup};
+use crate::signature::SignatureCheck;
+use crate::oci::{pull_image, OciPullOptions, Platform};
 use anyhow::{Result, anyhow};
 use clap::{Parser, Subcommand};
 use indicatif::HumanBytes;
+use reqwest::Method;
 use std::{
     collections::HashMap,
-    io::Write,
+    io::{self, IsTerminal, Write},
     path::PathBuf,
+    sync::Arc,
     time::Instant,
 };
 
@@ -42,6 +62,7 @@ pub struct Cli {
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)] // 只在启动时解析一次，不值得装箱
 enum Commands {
     /// Play a hidden snake game (Easter egg! 🎮)
     Play,
@@ -96,8 +117,9 @@ enum Commands {
         /// URL to download
         url: String,
 
-        /// Output file name
-        output: PathBuf,
+        /// Output file name (with --metalink: output file or directory, defaults to the names in the metalink)
+        #[arg(required_unless_present = "metalink")]
+        output: Option<PathBuf>,
 
         /// Number of parallel connections
         #[arg(short = 'p', long, default_value = "4")]
@@ -114,12 +136,105 @@ enum Commands {
         /// Use HTTP/3 (experimental)
         #[arg(long)]
         http3: bool,
+
+        /// Treat URL as a Metalink (RFC 5854) document, local path or URL
+        #[arg(long)]
+        metalink: bool,
+
+        /// Only download if the remote file changed (conditional request on ETag/Last-Modified)
+        #[arg(long)]
+        sync: bool,
+
+        /// HLS/DASH: pick the highest variant at or below this bandwidth (bit/s)
+        #[arg(long)]
+        max_bandwidth: Option<u64>,
+
+        /// HLS/DASH: pick the variant with this resolution (e.g. 1280x720)
+        #[arg(long)]
+        resolution: Option<String>,
+
+        /// Extract the downloaded archive (.tar, .tar.gz, .tar.zst, .zip, .gz) into DIR (defaults to the output directory)
+        #[arg(long, value_name = "DIR", num_args = 0..=1)]
+        extract: Option<Option<PathBuf>>,
+
+        /// Strip this many leading path components when extracting
+        #[arg(long, default_value = "0", requires = "extract")]
+        strip_components: usize,
+
+        /// Progress output: human (progress bars) or json (JSON Lines events on stderr)
+        #[arg(long, value_enum, default_value = "human")]
+        progress: ProgressFormat,
+
+        /// Write JSON progress events to this file descriptor instead of stderr (needs --progress json)
+        #[arg(long, value_name = "FD")]
+        progress_fd: Option<i32>,
+
+        /// Start the download at this local time (HH:MM)
+        #[arg(long, value_name = "HH:MM")]
+        at: Option<String>,
+
+        /// Only transfer during this daily local time window, pausing outside it (e.g. 01:00-06:00)
+        #[arg(long, value_name = "HH:MM-HH:MM")]
+        window: Option<String>,
+
+        /// Limit bandwidth during a time of day, e.g. 08:00-18:00=500K (can be repeated)
+        #[arg(long, value_name = "HH:MM-HH:MM=RATE")]
+        bandwidth_schedule: Vec<String>,
+
+        /// Command to run after a successful download (SURF_PATH, SURF_SIZE, SURF_CHECKSUM, ... are set)
+        #[arg(long, value_name = "CMD")]
+        on_complete: Option<String>,
+
+        /// Command to run after a failed download (SURF_URL, SURF_ERROR, ... are set)
+        #[arg(long, value_name = "CMD")]
+        on_fail: Option<String>,
+
+        /// Reuse files from the local download store and add new downloads to it
+        #[arg(long)]
+        store: bool,
+
+        /// Expected checksum of the file (e.g. sha256:<hex>), verified after download and used as store key
+        #[arg(long, value_name = "ALGO:HEX")]
+        checksum: Option<String>,
+
+        /// Verify a minisign or SSH signature (file path or URL) after download; bad files are quarantined
+        #[arg(long, value_name = "SIGFILE|URL", requires = "pubkey")]
+        verify_sig: Option<String>,
+
+        /// Public key for --verify-sig: minisign key, SSH public key, or a file containing one
+        #[arg(long, value_name = "KEY", requires = "verify_sig")]
+        pubkey: Option<String>,
+
+        /// Namespace the SSH signature was created with (ssh-keygen -Y sign -n)
+        #[arg(long, value_name = "NAMESPACE", default_value = "file")]
+        sig_namespace: String,
     },
 
     /// Benchmark a URL by sending multiple requests
     Bench {
         /// URL to benchmark
-        url: String,
+        #[arg(required_unless_present = "compare", conflicts_with = "compare")]
+        url: Option<String>,
+
+        /// Compare two URLs side by side; requests alternate between them and -n counts requests per URL
+        #[arg(
+            long,
+            num_args = 2,
+            value_names = ["URL_A", "URL_B"],
+            conflicts_with_all = ["scenario", "stages", "rate", "tui", "baseline", "fail_if", "timeline"]
+        )]
+        compare: Vec<String>,
+
+        /// Compare HTTP versions (e.g. http1,http2,http3) against the URL, or against both --compare URLs
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            conflicts_with_all = [
+                "http2", "http3", "no_keepalive", "scenario", "stages", "rate", "tui", "baseline", "fail_if", "timeline"
+            ]
+        )]
+        protocols: Vec<Protocol>,
 
         /// Number of requests to send
         #[arg(short = 'n', long, default_value = "100")]
@@ -136,6 +251,148 @@ enum Commands {
         /// Use HTTP/3 (experimental)
         #[arg(long)]
         http3: bool,
+
+        /// Use HTTP/2 without negotiation (prior knowledge); also works for plain http:// (h2c)
+        #[arg(long, conflicts_with = "http3")]
+        http2: bool,
+
+        /// Open a new connection for every request (HTTP/1.1) to measure handshake and TLS termination cost
+        #[arg(long, conflicts_with_all = ["http2", "http3", "max_connections"])]
+        no_keepalive: bool,
+
+        /// Open at most N connections; requests take turns on them and queue when all are busy
+        #[arg(long, value_name = "N")]
+        max_connections: Option<usize>,
+
+        /// With --http2 and --max-connections, run up to N requests at once on each connection (default 1)
+        #[arg(long, value_name = "N", requires_all = ["http2", "max_connections"])]
+        max_streams: Option<usize>,
+
+        /// HTTP method (default: GET, or POST when --data is given)
+        #[arg(short = 'X', long, value_name = "METHOD")]
+        method: Option<String>,
+
+        /// Set custom headers (e.g., "Authorization: Bearer token")
+        #[arg(short = 'H', long)]
+        headers: Vec<String>,
+
+        /// Request body sent with every request; use @file to read it from a file
+        #[arg(long, value_name = "DATA")]
+        data: Option<String>,
+
+        /// Do not follow redirects; 3xx responses are recorded as they are
+        #[arg(long)]
+        no_follow: bool,
+
+        /// Run for this long instead of a fixed request count (e.g. 30s, 5m)
+        #[arg(short = 'd', long, value_name = "DURATION")]
+        duration: Option<String>,
+
+        /// Send requests at a constant rate (e.g. 500/s) instead of as fast as possible; -c caps requests in flight
+        #[arg(long, value_name = "RATE")]
+        rate: Option<String>,
+
+        /// Send load for this long before measuring; warm-up results are discarded (e.g. 10s)
+        #[arg(long, value_name = "DURATION")]
+        warmup: Option<String>,
+
+        /// Ramp concurrency through stages of DURATION:TARGET (e.g. 30s:10,60s:50,30s:0)
+        #[arg(long, value_name = "STAGES", conflicts_with_all = ["duration", "rate"])]
+        stages: Option<String>,
+
+        /// Result format: text, json (full report, usable as --baseline) or csv (one summary row)
+        #[arg(long, value_enum, default_value = "text")]
+        output_format: OutputFormat,
+
+        /// Write a per-second timeline (requests, errors, avg/max latency) to this CSV file
+        #[arg(long, value_name = "FILE")]
+        timeline: Option<PathBuf>,
+
+        /// Compare against a previous run saved with --output-format json
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+
+        /// Exit non-zero if any condition holds, e.g. "p95>+10%,errors>1%" (+/-N% is relative to --baseline)
+        #[arg(long, value_name = "CONDITIONS")]
+        fail_if: Option<String>,
+
+        /// Show a live full-screen dashboard; keys pause, change concurrency and abort with the results so far
+        #[arg(long)]
+        tui: bool,
+
+        /// Run the weighted multi-step flows in this TOML file instead of one request; relative step URLs are appended to URL and -n counts flows
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["rate", "method", "data"])]
+        scenario: Option<PathBuf>,
+
+        /// CSV file (header row = column names) whose rows fill {{column}} placeholders in the URL, -H headers, --data and scenario steps
+        #[arg(long, value_name = "FILE")]
+        feed: Option<PathBuf>,
+
+        /// How rows are taken from --feed: circular (loop over the rows), sequential (each row once, then stop) or random
+        #[arg(long, value_enum, default_value = "circular", requires = "feed")]
+        feed_strategy: FeedStrategy,
+
+        /// Status codes that count as success, e.g. "200,204" or "2xx,404" (default: any 2xx/3xx)
+        #[arg(long, value_name = "CODES")]
+        expect_status: Option<String>,
+
+        /// Count a response as failed unless its body contains TEXT (repeatable)
+        #[arg(long, value_name = "TEXT")]
+        expect_body_contains: Vec<String>,
+
+        /// Check a JSON field: "/pointer == value", "/pointer != value" or just "/pointer" to require it (repeatable)
+        #[arg(long, value_name = "CHECK")]
+        expect_json: Vec<String>,
+
+        /// Count responses with a body larger than SIZE as failed (e.g. 512K, 2M)
+        #[arg(long, value_name = "SIZE")]
+        max_body_size: Option<String>,
+
+        /// Progress output: human (terminal output) or json (JSON Lines events on stderr)
+        #[arg(long, value_enum, default_value = "human")]
+        progress: ProgressFormat,
+
+        /// Write JSON progress events to this file descriptor instead of stderr (needs --progress json)
+        #[arg(long, value_name = "FD")]
+        progress_fd: Option<i32>,
+    },
+
+    /// Recursively mirror a site for offline browsing
+    Mirror {
+        /// Start URL; only pages and assets under its directory are mirrored
+        url: String,
+
+        /// Maximum link depth to follow from the start page
+        #[arg(short = 'd', long, default_value = "2")]
+        depth: usize,
+
+        /// Output directory
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+
+        /// Only mirror URLs matching this regex (can be repeated)
+        #[arg(long)]
+        include: Vec<String>,
+
+        /// Skip URLs matching this regex (can be repeated)
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// Politeness delay between requests in milliseconds
+        #[arg(long, default_value = "0")]
+        delay: u64,
+
+        /// Ignore robots.txt
+        #[arg(long)]
+        ignore_robots: bool,
+
+        /// Idle timeout in seconds (time between two packets)
+        #[arg(short = 't', long, default_value = "30")]
+        idle_timeout: u64,
+
+        /// Use HTTP/3 (experimental)
+        #[arg(long)]
+        http3: bool,
     },
 
     /// Configuration management
@@ -167,6 +424,18 @@ enum Commands {
         #[command(subcommand)]
         action: ResumeAction,
     },
+
+    /// Local download store management
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+
+    /// Pull images from OCI/Docker registries without a container runtime
+    Oci {
+        #[command(subcommand)]
+        action: OciAction,
+    },
 }
 
 #[derive(Subcommand)]
@@ -223,6 +492,12 @@ enum ProfileAction {
         /// Follow redirects
         #[arg(long)]
         follow_redirects: bool,
+        /// Default command to run after a successful download
+        #[arg(long, value_name = "CMD")]
+        on_complete: Option<String>,
+        /// Default command to run after a failed download
+        #[arg(long, value_name = "CMD")]
+        on_fail: Option<String>,
     },
     /// Delete a profile
     Delete {
@@ -260,6 +535,12 @@ enum ResumeAction {
         /// Output file path (optional, uses original if not specified)
         #[arg(short = 'o', long)]
         output: Option<PathBuf>,
+        /// Command to run after the download completes
+        #[arg(long, value_name = "CMD")]
+        on_complete: Option<String>,
+        /// Command to run if the download fails
+        #[arg(long, value_name = "CMD")]
+        on_fail: Option<String>,
     },
     /// Clean up old download metadata
     Cleanup {
@@ -274,6 +555,51 @@ enum ResumeAction {
     },
 }
 
+#[derive(Subcommand)]
+enum OciAction {
+    /// Pull an image (e.g. alpine:3.20, ghcr.io/org/app:1.0) into an OCI image layout directory
+    Pull {
+        /// Image reference: [registry/]repository[:tag|@digest]
+        reference: String,
+
+        /// Output directory (OCI image layout)
+        #[arg(long)]
+        dir: PathBuf,
+
+        /// Platform to pick from multi-platform images (default: linux/<host arch>)
+        #[arg(long, value_name = "OS/ARCH[/VARIANT]")]
+        platform: Option<String>,
+
+        /// Talk to the registry over plain HTTP (local registries)
+        #[arg(long)]
+        plain_http: bool,
+
+        /// Number of parallel connections per layer
+        #[arg(short = 'p', long, default_value = "4")]
+        parallel: usize,
+
+        /// Idle timeout in seconds (time between two packets)
+        #[arg(short = 't', long, default_value = "30")]
+        idle_timeout: u64,
+
+        /// Use HTTP/3 (experimental)
+        #[arg(long)]
+        http3: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreAction {
+    /// Show store location and size
+    Info,
+    /// Evict least recently used files until the store fits in the given size
+    Gc {
+        /// Maximum store size (e.g. 500M, 10G)
+        #[arg(long, value_name = "SIZE")]
+        max_size: String,
+    },
+}
+
 pub async fn execute() -> Result<()> {
     let args = Cli::parse();
     // Load configuration
@@ -292,12 +618,15 @@ pub async fn execute() -> Result<()> {
     // 根据命令类型确定日志目录
     let log_dir = if args.log {
         match &args.command {
-            Commands::Download { output, .. } => {
+            Commands::Download { output: Some(output), .. } => {
                 output.parent().map(|p| p.to_path_buf())
             }
             Commands::Get { output: Some(output), .. } => {
                 output.parent().map(|p| p.to_path_buf())
             }
+            Commands::Mirror { dir, .. } => {
+                Some(dir.clone())
+            }
             _ => {
                 Some(PathBuf::from("."))
             }
@@ -349,26 +678,206 @@ pub async fn execute() -> Result<()> {
             continue_download,
             idle_timeout,
             http3,
+            metalink,
+            sync,
+            max_bandwidth,
+            resolution,
+            extract,
+            strip_components,
+            progress,
+            progress_fd,
+            at,
+            window,
+            bandwidth_schedule,
+            on_complete,
+            on_fail,
+            store,
+            checksum,
+            verify_sig,
+            pubkey,
+            sig_namespace,
         } => {
+            init_progress(progress, progress_fd)?;
+            init_schedule(Schedule {
+                at: at.as_deref().map(parse_time_of_day).transpose()?,
+                window: window.as_deref().map(TimeWindow::parse).transpose()?,
+                bandwidth: bandwidth_schedule.iter().map(|rule| BandwidthRule::parse(rule)).collect::<Result<_>>()?,
+            })?;
+            if verify_sig.is_some() && (metalink || detect_manifest(&url).is_some()) {
+                return Err(anyhow!("--verify-sig is only supported for plain file downloads"));
+            }
+            let mode = if metalink {
+                DownloadMode::Metalink
+            } else if detect_manifest(&url).is_some() {
+                DownloadMode::Stream(StreamSelection {
+                    max_bandwidth,
+                    resolution: resolution.as_deref().map(StreamSelection::parse_resolution).transpose()?,
+                })
+            } else {
+                DownloadMode::Direct(DirectOptions {
+                    sync,
+                    store,
+                    checksum: checksum.as_deref().map(ExpectedChecksum::parse).transpose()?,
+                    signature: verify_sig.zip(pubkey).map(|(signature, pubkey)| SignatureCheck {
+                        signature,
+                        pubkey,
+                        namespace: sig_namespace,
+                    }),
+                })
+            };
+            let extract = extract.map(|dir| ExtractOptions { dir, strip_components });
+            let profile = args.profile.as_deref().and_then(|name| config.get_profile(name));
+            let hooks = DownloadHooks::resolve(on_complete, on_fail, profile);
+
             handle_download_with_cache(
-                &url, output, parallel, continue_download, idle_timeout, http3,
+                &url, output, parallel, continue_download, idle_timeout, http3, mode, extract, hooks,
                 args.no_color, args.use_cache, args.no_save, args.profile
             ).await
         }
 
         Commands::Bench {
             url,
+            compare,
+            protocols,
             requests,
             concurrency,
             connect_timeout,
             http3,
+            http2,
+            no_keepalive,
+            max_connections,
+            max_streams,
+            method,
+            headers,
+            data,
+            no_follow,
+            duration,
+            rate,
+            warmup,
+            stages,
+            output_format,
+            timeline,
+            baseline,
+            fail_if,
+            tui,
+            scenario,
+            feed,
+            feed_strategy,
+            expect_status,
+            expect_body_contains,
+            expect_json,
+            max_body_size,
+            progress,
+            progress_fd,
         } => {
+            init_progress(progress, progress_fd)?;
+            if tui && !io::stdout().is_terminal() {
+                return Err(anyhow!("--tui needs a terminal on stdout"));
+            }
+            let fail_if = fail_if.as_deref().map(Threshold::parse_list).transpose()?.unwrap_or_default();
+            if baseline.is_none() && fail_if.iter().any(Threshold::is_relative) {
+                return Err(anyhow!("--fail-if conditions with +/-N% need --baseline"));
+            }
+            // 压测结果依赖这些请求头（例如认证），格式错误时直接报错而不是跳过
+            for header in &headers {
+                parse_header(header)?;
+            }
+            let body = data.as_deref().map(load_body).transpose()?;
+            let feed = feed.as_deref().map(|path| Feed::load(path, feed_strategy)).transpose()?;
+            let urls = if compare.is_empty() { url.into_iter().collect() } else { compare };
+            let variants = if urls.len() > 1 || !protocols.is_empty() {
+                Variant::list(&urls, &protocols)
+            } else {
+                Vec::new()
+            };
+            if variants.len() == 1 {
+                return Err(anyhow!("--protocols needs at least two protocols to compare"));
+            }
+            if !variants.is_empty() && output_format == OutputFormat::Csv {
+                return Err(anyhow!("--output-format csv is not supported with --compare or --protocols"));
+            }
+            if max_connections == Some(0) || max_streams == Some(0) {
+                return Err(anyhow!("--max-connections and --max-streams must be at least 1"));
+            }
+            let expect = Expectations {
+                status: expect_status.as_deref().map(StatusMatch::parse_list).transpose()?.unwrap_or_default(),
+                body_contains: expect_body_contains,
+                json: expect_json.iter().map(|check| JsonExpectation::parse(check)).collect::<Result<_>>()?,
+                max_body_size: max_body_size.as_deref().map(parse_size).transpose()?,
+            };
+            let method = match method {
+                Some(method) => Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .map_err(|_| anyhow!("Invalid HTTP method: {}", method))?,
+                None if body.is_some() => Method::POST,
+                None => Method::GET,
+            };
+            let options = BenchOptions {
+                requests,
+                concurrency,
+                connect_timeout,
+                http3,
+                http2,
+                connections: ConnectionLimits { no_keepalive, max_connections, max_streams },
+                duration: duration.as_deref().map(parse_duration).transpose()?,
+                rate: rate.as_deref().map(parse_request_rate).transpose()?,
+                warmup: warmup.as_deref().map(parse_duration).transpose()?,
+                stages: stages.as_deref().map(Stage::parse_list).transpose()?.unwrap_or_default(),
+                output_format,
+                timeline,
+                baseline,
+                fail_if,
+                method,
+                headers,
+                body,
+                follow_redirects: !no_follow,
+                tui,
+                scenario: scenario
+                    .as_deref()
+                    .map(|path| Scenario::load(path, feed.as_ref().map_or(&[], |feed| feed.columns())))
+                    .transpose()?
+                    .map(Arc::new),
+                feed: feed.map(Arc::new),
+                expect: Arc::new(expect),
+                variants,
+            };
             handle_benchmark_with_cache(
-                &url, requests, concurrency, connect_timeout, http3,
-                args.no_color, args.use_cache, args.no_save, args.profile
+                &urls[0], options, args.no_color, args.use_cache, args.no_save, args.profile
             ).await
         }
 
+        Commands::Mirror {
+            url,
+            depth,
+            dir,
+            include,
+            exclude,
+            delay,
+            ignore_robots,
+            idle_timeout,
+            http3,
+        } => {
+            let options = MirrorOptions {
+                depth,
+                dir,
+                include,
+                exclude,
+                delay: std::time::Duration::from_millis(delay),
+                ignore_robots,
+                idle_timeout,
+                http3,
+            };
+            match mirror_site(&url, &options).await {
+                Ok(_) => {
+                    log_info("Mirror completed successfully");
+                    Ok(())
+                }
+                Err(e) => {
+                    log_error(&format!("Mirror failed: {}", e));
+                    Err(e)
+                }
+            }
+        }
+
         Commands::Config { action } => {
             handle_config_action(action, &mut config, &config_path).await
         }
@@ -386,7 +895,16 @@ pub async fn execute() -> Result<()> {
         }
 
         Commands::Resume { action } => {
-            handle_resume_action(action).await
+            let profile = args.profile.as_deref().and_then(|name| config.get_profile(name));
+            handle_resume_action(action, profile).await
+        }
+
+        Commands::Store { action } => {
+            handle_store_action(action).await
+        }
+
+        Commands::Oci { action } => {
+            handle_oci_action(action).await
         }
     }
 }
@@ -510,11 +1028,14 @@ async fn handle_get_request_with_cache(
 
 async fn handle_download_with_cache(
     url: &str,
-    output: PathBuf,
+    output: Option<PathBuf>,
     parallel: usize,
     continue_download: bool,
     idle_timeout: u64,
     http3: bool,
+    mode: DownloadMode,
+    extract: Option<ExtractOptions>,
+    hooks: DownloadHooks,
     no_color: bool,
     use_cache: bool,
     no_save: bool,
@@ -574,47 +1095,21 @@ async fn handle_download_with_cache(
 
         log_info("Using cached configuration for download");
         log_info(&format!("Starting download from: {}", url));
-        log_debug(&format!("Download parameters - output: {}, parallel: {}, continue: {}, timeout: {}s, http3: {}",
-                           output.display(), merged_parallel, merged_continue, merged_idle_timeout, merged_http3));
+        log_debug(&format!("Download parameters - output: {:?}, parallel: {}, continue: {}, timeout: {}s, http3: {}",
+                           output, merged_parallel, merged_continue, merged_idle_timeout, merged_http3));
 
-        match download_file(url, &output, merged_parallel, merged_continue, merged_idle_timeout, merged_http3).await {
-            Ok(_) => {
-                log_info("Download completed successfully");
-                Ok(())
-            }
-            Err(e) => {
-                if let Some(timeout_err) = e.downcast_ref::<TimeoutError>() {
-                    log_error(&format!("Download failed with timeout: {}", timeout_err));
-                    eprintln!("Download failed: {}", timeout_err);
-                } else {
-                    log_error(&format!("Download failed: {}", e));
-                    eprintln!("Download failed: {}", e);
-                }
-                Err(e)
-            }
-        }
+        let started = Instant::now();
+        let result = run_download(url, output.as_ref(), merged_parallel, merged_continue, merged_idle_timeout, merged_http3, &mode).await;
+        finish_download(url, output.as_ref(), result, extract.as_ref(), &hooks, started).await
     } else {
         // 正常执行
         log_info(&format!("Starting download from: {}", url));
-        log_debug(&format!("Download parameters - output: {}, parallel: {}, continue: {}, timeout: {}s, http3: {}",
-                           output.display(), parallel, continue_download, idle_timeout, http3));
+        log_debug(&format!("Download parameters - output: {:?}, parallel: {}, continue: {}, timeout: {}s, http3: {}",
+                           output, parallel, continue_download, idle_timeout, http3));
 
-        let result = match download_file(url, &output, parallel, continue_download, idle_timeout, http3).await {
-            Ok(_) => {
-                log_info("Download completed successfully");
-                Ok(())
-            }
-            Err(e) => {
-                if let Some(timeout_err) = e.downcast_ref::<TimeoutError>() {
-                    log_error(&format!("Download failed with timeout: {}", timeout_err));
-                    eprintln!("Download failed: {}", timeout_err);
-                } else {
-                    log_error(&format!("Download failed: {}", e));
-                    eprintln!("Download failed: {}", e);
-                }
-                Err(e)
-            }
-        };
+        let started = Instant::now();
+        let result = run_download(url, output.as_ref(), parallel, continue_download, idle_timeout, http3, &mode).await;
+        let result = finish_download(url, output.as_ref(), result, extract.as_ref(), &hooks, started).await;
 
         // 保存配置到缓存
         if !no_save && result.is_ok() {
@@ -629,17 +1124,237 @@ async fn handle_download_with_cache(
     }
 }
 
-async fn handle_benchmark_with_cache(
+// 下载来源类型
+enum DownloadMode {
+    /// 普通文件下载
+    Direct(DirectOptions),
+    /// Metalink 文档描述的下载
+    Metalink,
+    /// HLS/DASH 分段流下载
+    Stream(StreamSelection),
+}
+
+// 根据下载来源选择对应的下载方式
+async fn run_download(
     url: &str,
-    requests: usize,
-    concurrency: usize,
-    connect_timeout: u64,
+    output: Option<&PathBuf>,
+    parallel: usize,
+    continue_download: bool,
+    idle_timeout: u64,
     http3: bool,
+    mode: &DownloadMode,
+) -> Result<Vec<PathBuf>> {
+    let required_output = || output.ok_or_else(|| anyhow!("Output file is required"));
+
+    // 按 --at/--window 调度；窗口关闭时暂停，窗口再次打开后断点续传
+    schedule::wait_for_start().await;
+    let mut continue_download = continue_download;
+    loop {
+        schedule::wait_for_window().await;
+
+        let result = match mode {
+            DownloadMode::Metalink => {
+                download_metalink(url, output, parallel, continue_download, idle_timeout, http3).await
+            }
+            DownloadMode::Stream(selection) => {
+                let output = required_output()?;
+                download_stream(url, output, parallel, continue_download, idle_timeout, http3, selection).await
+                    .map(|_| vec![output.clone()])
+            }
+            DownloadMode::Direct(options) => {
+                let output = required_output()?;
+                download_direct(url, output, parallel, continue_download, idle_timeout, http3, options).await
+                    .map(|_| vec![output.clone()])
+            }
+        };
+
+        match result {
+            Err(e) if e.is::<DownloadPaused>() => {
+                log_info(&format!("{}, waiting for the next window", e));
+                println!("{}, will resume when the window opens again", e);
+                continue_download = true;
+            }
+            result => return result,
+        }
+    }
+}
+
+// 普通文件下载的设置
+struct DirectOptions {
+    /// 仅在远程文件变化时下载
+    sync: bool,
+    /// 使用本地下载存储去重
+    store: bool,
+    /// 期望的校验和
+    checksum: Option<ExpectedChecksum>,
+    /// 下载后校验签名
+    signature: Option<SignatureCheck>,
+}
+
+// 普通文件下载：先查本地存储，下载后校验校验和与签名，再登记到存储
+async fn download_direct(
+    url: &str,
+    output: &PathBuf,
+    parallel: usize,
+    continue_download: bool,
+    idle_timeout: u64,
+    http3: bool,
+    options: &DirectOptions,
+) -> Result<()> {
+    let etag = if options.store {
+        match store::restore(url, output, options.checksum.as_ref(), http3).await? {
+            StoreLookup::Hit { sha256, etag } => {
+                // 按 ETag 命中或存储被改动时内容不一定符合期望的校验和，不符时删除并重新下载
+                let verified = match &options.checksum {
+                    Some(checksum) => match checksum.verify(output) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            log_warn(&format!("Stored copy of {} rejected: {}; downloading again", url, e));
+                            std::fs::remove_file(output)?;
+                            false
+                        }
+                    },
+                    None => true,
+                };
+                if verified {
+                    println!("Restored {} from local store (sha256:{})", output.display(), sha256);
+                    // 存储中的文件可能是未校验签名时登记的，仍需校验
+                    if let Some(signature) = &options.signature {
+                        signature.verify_or_quarantine(output, http3).await?;
+                    }
+                    return Ok(());
+                }
+                etag
+            }
+            StoreLookup::Miss { etag } => etag,
+        }
+    } else {
+        None
+    };
+
+    download_file(url, output, parallel, continue_download, idle_timeout, http3, options.sync).await?;
+
+    if let Some(checksum) = &options.checksum {
+        checksum.verify(output)?;
+        log_info(&format!("Checksum verified: {}:{}", checksum.algorithm.name(), checksum.hex));
+    }
+    if let Some(signature) = &options.signature {
+        signature.verify_or_quarantine(output, http3).await?;
+    }
+    if options.store {
+        store::remember(url, output, etag.as_deref())?;
+    }
+    Ok(())
+}
+
+// 处理下载结果：记录日志、解压并执行完成/失败钩子
+async fn finish_download(
+    url: &str,
+    output: Option<&PathBuf>,
+    result: Result<Vec<PathBuf>>,
+    extract: Option<&ExtractOptions>,
+    hooks: &DownloadHooks,
+    started: Instant,
+) -> Result<()> {
+    let result = match result {
+        Ok(paths) => {
+            log_info("Download completed successfully");
+            match extract {
+                Some(options) => extract_downloads(paths.clone(), options).await.map(|_| paths),
+                None => Ok(paths),
+            }
+        }
+        Err(e) => {
+            if let Some(timeout_err) = e.downcast_ref::<TimeoutError>() {
+                log_error(&format!("Download failed with timeout: {}", timeout_err));
+                eprintln!("Download failed: {}", timeout_err);
+            } else {
+                log_error(&format!("Download failed: {}", e));
+                eprintln!("Download failed: {}", e);
+            }
+            Err(e)
+        }
+    };
+
+    match result {
+        Ok(paths) => {
+            for path in &paths {
+                hooks.run_complete(&HookContext { url, path: Some(path), duration: started.elapsed(), error: None }).await?;
+            }
+            Ok(())
+        }
+        Err(e) => {
+            hooks.run_fail(&HookContext { url, path: output, duration: started.elapsed(), error: Some(e.to_string()) }).await;
+            Err(e)
+        }
+    }
+}
+
+// 下载完成后的解压设置
+struct ExtractOptions {
+    /// 解压目录，为空时解压到下载文件所在目录
+    dir: Option<PathBuf>,
+    strip_components: usize,
+}
+
+// 解压已下载（且已通过校验）的归档文件，并把解压出的路径写入日志
+async fn extract_downloads(paths: Vec<PathBuf>, options: &ExtractOptions) -> Result<()> {
+    let archives: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| ArchiveKind::detect(path).is_some())
+        .collect();
+    if archives.is_empty() {
+        let err = anyhow!("Nothing to extract: downloaded file is not a supported archive");
+        log_error(&err.to_string());
+        eprintln!("Extraction failed: {}", err);
+        return Err(err);
+    }
+
+    for archive in archives {
+        let dest = match &options.dir {
+            Some(dir) => dir.clone(),
+            None => archive
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(".")),
+        };
+        let strip_components = options.strip_components;
+        let source = archive.clone();
+        let target = dest.clone();
+
+        let result = tokio::task::spawn_blocking(move || extract_archive(&source, &target, strip_components))
+            .await
+            .map_err(|e| anyhow!("Extraction task failed: {}", e))?;
+
+        match result {
+            Ok(extracted) => {
+                for path in &extracted {
+                    log_info(&format!("Extracted: {}", path.display()));
+                }
+                log_info(&format!("Extraction completed: {} entries from {}", extracted.len(), archive.display()));
+                println!("Extracted {} entries from {} to {}", extracted.len(), archive.display(), dest.display());
+            }
+            Err(e) => {
+                log_error(&format!("Extraction failed: {}", e));
+                eprintln!("Extraction failed: {}", e);
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_benchmark_with_cache(
+    url: &str,
+    options: BenchOptions,
     no_color: bool,
     use_cache: bool,
     no_save: bool,
     profile: Option<String>,
 ) -> Result<()> {
+    let BenchOptions { requests, concurrency, connect_timeout, http3, .. } = options;
     let cache_path = CachedConfig::get_cache_path();
     let mut cached_config = CachedConfig::load_from_file(&cache_path)?;
     if use_cache {
@@ -697,7 +1412,14 @@ async fn handle_benchmark_with_cache(
         log_debug(&format!("Benchmark parameters - requests: {}, concurrency: {}, timeout: {}s, http3: {}",
                            merged_requests, merged_concurrency, merged_connect_timeout, merged_http3));
 
-        match benchmark_url(url, merged_requests, merged_concurrency, merged_connect_timeout, merged_http3).await {
+        let options = BenchOptions {
+            requests: merged_requests,
+            concurrency: merged_concurrency,
+            connect_timeout: merged_connect_timeout,
+            http3: merged_http3,
+            ..options
+        };
+        match benchmark_url(url, &options).await {
             Ok(_) => {
                 log_info("Benchmark completed successfully");
                 Ok(())
@@ -713,7 +1435,7 @@ async fn handle_benchmark_with_cache(
         log_debug(&format!("Benchmark parameters - requests: {}, concurrency: {}, timeout: {}s, http3: {}",
                            requests, concurrency, connect_timeout, http3));
 
-        let result = match benchmark_url(url, requests, concurrency, connect_timeout, http3).await {
+        let result = match benchmark_url(url, &options).await {
             Ok(_) => {
                 log_info("Benchmark completed successfully");
                 Ok(())
@@ -757,6 +1479,55 @@ async fn handle_cache_action(action: CacheAction) -> Result<()> {
     }
 }
 
+async fn handle_oci_action(action: OciAction) -> Result<()> {
+    match action {
+        OciAction::Pull { reference, dir, platform, plain_http, parallel, idle_timeout, http3 } => {
+            let options = OciPullOptions {
+                dir,
+                platform: platform.as_deref().map(Platform::parse).transpose()?,
+                plain_http,
+                parallel,
+                idle_timeout,
+                http3,
+            };
+            match pull_image(&reference, &options).await {
+                Ok(digest) => {
+                    println!("Pulled {} ({}) into {}", reference, digest, options.dir.display());
+                    Ok(())
+                }
+                Err(e) => {
+                    log_error(&format!("OCI pull failed: {}", e));
+                    Err(e)
+                }
+            }
+        }
+    }
+}
+
+async fn handle_store_action(action: StoreAction) -> Result<()> {
+    let mut store = Store::open()?;
+    match action {
+        StoreAction::Info => {
+            println!("Store: {}", store.root().display());
+            println!("Objects: {}", store.len());
+            println!("Size: {}", HumanBytes(store.total_size()));
+            Ok(())
+        }
+        StoreAction::Gc { max_size } => {
+            let max_size = parse_size(&max_size)?;
+            let (removed, freed) = store.gc(max_size)?;
+            log_info(&format!("Store gc removed {} objects ({} bytes)", removed, freed));
+            println!(
+                "Removed {} files, freed {}; store is now {}",
+                removed,
+                HumanBytes(freed),
+                HumanBytes(store.total_size())
+            );
+            Ok(())
+        }
+    }
+}
+
 async fn handle_get_request(
     url: &str,
     include: bool,
@@ -1078,13 +1849,15 @@ async fn handle_profile_action(
             }
             Ok(())
         }
-        ProfileAction::Create { name, base_url, timeout, follow_redirects } => {
+        ProfileAction::Create { name, base_url, timeout, follow_redirects, on_complete, on_fail } => {
             let profile = Profile {
                 name: name.clone(),
                 base_url,
                 headers: HashMap::new(),
                 timeout,
                 follow_redirects,
+                on_complete,
+                on_fail,
             };
 
             config.add_profile(profile);
@@ -1107,6 +1880,12 @@ async fn handle_profile_action(
                 println!("Base URL: {}", profile.base_url.as_ref().unwrap_or(&"None".to_string()));
                 println!("Timeout: {}s", profile.timeout.unwrap_or(config.default_timeout));
                 println!("Follow redirects: {}", profile.follow_redirects);
+                if let Some(ref command) = profile.on_complete {
+                    println!("On complete: {}", command);
+                }
+                if let Some(ref command) = profile.on_fail {
+                    println!("On fail: {}", command);
+                }
 
                 if !profile.headers.is_empty() {
                     println!("Headers:");
@@ -1122,7 +1901,7 @@ async fn handle_profile_action(
     }
 }
 
-async fn handle_resume_action(action: ResumeAction) -> Result<()> {
+async fn handle_resume_action(action: ResumeAction, profile: Option<&Profile>) -> Result<()> {
     use crate::resume::{ResumeManager, DownloadStatus};
     use chrono::{DateTime, Local, Utc};
 
@@ -1242,6 +2021,20 @@ async fn handle_resume_action(action: ResumeAction) -> Result<()> {
                 println!("  Failed: {}", failed);
             }
 
+            if metadata.total_size > 0 && !metadata.chunks.is_empty() {
+                println!("\nSegment Map (# completed, + partial, . pending, x failed):");
+                println!("  [{}]", metadata.segment_map(64));
+                for chunk in &metadata.chunks {
+                    println!("  chunk {:>3}: {:>12}-{:<12} {:>10} / {:<10} {:?}",
+                             chunk.index,
+                             chunk.start,
+                             chunk.end,
+                             HumanBytes(chunk.downloaded),
+                             HumanBytes(chunk.end - chunk.start),
+                             chunk.status);
+                }
+            }
+
             if let Some(ref error) = metadata.error_message {
                 println!("\nError: {}", error);
             }
@@ -1251,7 +2044,7 @@ async fn handle_resume_action(action: ResumeAction) -> Result<()> {
             Ok(())
         }
 
-        ResumeAction::Resume { url, output } => {
+        ResumeAction::Resume { url, output, on_complete, on_fail } => {
             // 加载元数据
             let metadata = resume_manager.load_metadata(&url)?
                 .ok_or_else(|| anyhow!("No resumable download found for: {}", url))?;
@@ -1265,16 +2058,27 @@ async fn handle_resume_action(action: ResumeAction) -> Result<()> {
             println!("  Progress: {:.1}%", metadata.get_progress_percentage());
 
             // 调用下载函数
-            download_file(
+            let hooks = DownloadHooks::resolve(on_complete, on_fail, profile);
+            let started = Instant::now();
+            let result = download_file(
                 &url,
                 &output_path,
                 metadata.chunks.len(),
                 true,
                 30,
                 false,
-            ).await?;
+                false,
+            ).await;
 
-            Ok(())
+            match result {
+                Ok(()) => {
+                    hooks.run_complete(&HookContext { url: &url, path: Some(&output_path), duration: started.elapsed(), error: None }).await
+                }
+                Err(e) => {
+                    hooks.run_fail(&HookContext { url: &url, path: Some(&output_path), duration: started.elapsed(), error: Some(e.to_string()) }).await;
+                    Err(e)
+                }
+            }
         }
 
         ResumeAction::Cleanup { days } => {
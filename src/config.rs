@@ -22,6 +22,12 @@ pub struct Profile {
     pub headers: HashMap<String, String>,
     pub timeout: Option<u64>,
     pub follow_redirects: bool,
+    /// 下载完成后默认执行的命令
+    #[serde(default)]
+    pub on_complete: Option<String>,
+    /// 下载失败后默认执行的命令
+    #[serde(default)]
+    pub on_fail: Option<String>,
 }
 
 impl Default for Config {
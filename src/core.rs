@@ -1,15 +1,18 @@
 use crate::log::{log_info, log_error, log_debug, log_warn};
-use crate::resume::{DownloadMetadata, ResumeManager, ChunkStatus};
+use crate::progress::{self, ProgressEvent, ProgressTicker};
+use crate::schedule::{self, DownloadPaused};
+use crate::resume::{ChunkInfo, DownloadMetadata, DownloadStatus, ResumeManager, ChunkStatus};
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
-use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
     redirect::Policy,
     Client, ClientBuilder, StatusCode,
 };
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
     time::{Duration, Instant},
@@ -38,7 +41,7 @@ pub enum TimeoutError {
     ConnectTimeout,
 }
 
-fn parse_header(header_str: &str) -> Result<(HeaderName, HeaderValue)> {
+pub fn parse_header(header_str: &str) -> Result<(HeaderName, HeaderValue)> {
     let (key, value) = header_str
         .split_once(':')
         .ok_or_else(|| anyhow!("Malformed header: missing colon in '{}'", header_str))?;
@@ -51,7 +54,26 @@ fn parse_header(header_str: &str) -> Result<(HeaderName, HeaderValue)> {
     Ok((header_name, header_value))
 }
 
-fn create_progress_bar(total_size: u64, initial_pos: u64) -> ProgressBar {
+/// 解析带单位的大小：1024、500K、2M、10G（字节），也用于限速（字节/秒）
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&value[..value.len() - 1], 1u64 << 10),
+        Some('M') => (&value[..value.len() - 1], 1 << 20),
+        Some('G') => (&value[..value.len() - 1], 1 << 30),
+        Some('T') => (&value[..value.len() - 1], 1 << 40),
+        _ => (value, 1),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .ok()
+        .filter(|n: &f64| *n >= 0.0)
+        .ok_or_else(|| anyhow!("Invalid size '{}', expected e.g. 500M or 10G", value))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+pub(crate) fn create_progress_bar(total_size: u64, initial_pos: u64) -> ProgressBar {
     let pb = ProgressBar::new(total_size);
     pb.set_style(
         ProgressStyle::default_bar()
@@ -60,6 +82,23 @@ fn create_progress_bar(total_size: u64, initial_pos: u64) -> ProgressBar {
             .progress_chars("#>-"),
     );
     pb.set_position(initial_pos);
+    if progress::json_enabled() {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    pb
+}
+
+// 单个分片的进度条，显示分片编号、进度与速度
+fn create_chunk_progress_bar(chunk_index: usize, chunk_size: u64, initial_pos: u64) -> ProgressBar {
+    let pb = ProgressBar::new(chunk_size);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("  {prefix:>10} [{bar:30.green/white}] {bytes}/{total_bytes} | {binary_bytes_per_sec}")
+            .expect("Invalid progress bar template")
+            .progress_chars("=> "),
+    );
+    pb.set_prefix(format!("chunk {}", chunk_index));
+    pb.set_position(initial_pos);
     pb
 }
 
@@ -71,6 +110,22 @@ pub fn build_client(
     headers: Vec<String>,
     client_type: ClientType, // 新增参数
 ) -> Result<Client> {
+    client_builder(follow_redirects, connect_timeout, http3, headers, client_type)?
+        .build()
+        .map_err(|e| {
+            log_error(&format!("Failed to build HTTP client: {}", e));
+            anyhow!("Failed to build HTTP client: {}", e)
+        })
+}
+
+/// 与 build_client 相同的设置，返回还没有构建的 ClientBuilder，供调用方追加连接池等设置
+pub fn client_builder(
+    follow_redirects: bool,
+    connect_timeout: u64,
+    http3: bool,
+    headers: Vec<String>,
+    client_type: ClientType,
+) -> Result<ClientBuilder> {
     log_debug(&format!(
         "Building HTTP client - type: {:?}, redirects: {}, timeout: {}s, http3: {}",
         client_type, follow_redirects, connect_timeout, http3
@@ -135,10 +190,7 @@ pub fn build_client(
         }
     }
 
-    client_builder.build().map_err(|e| {
-        log_error(&format!("Failed to build HTTP client: {}", e));
-        anyhow!("Failed to build HTTP client: {}", e)
-    })
+    Ok(client_builder)
 }
 
 pub async fn download_file(
@@ -148,23 +200,38 @@ pub async fn download_file(
     continue_download: bool,
     idle_timeout: u64,
     http3: bool,
+    sync: bool,
+) -> Result<()> {
+    // 关键修改：使用 ClientType::Download，不设置总超时
+    let client: Client = build_client(true, DEFAULT_CONNECT_TIMEOUT, http3, vec![], ClientType::Download)?;
+    download_file_with_client(&client, url, output, parallel, continue_download, idle_timeout, sync).await
+}
+
+/// 使用调用方构建的客户端下载（例如需要附带认证头时）
+pub async fn download_file_with_client(
+    client: &Client,
+    url: &str,
+    output: &PathBuf,
+    parallel: usize,
+    continue_download: bool,
+    idle_timeout: u64,
+    sync: bool,
 ) -> Result<()> {
     log_info(&format!("Starting file download from: {}", url));
+    let download_start = Instant::now();
     log_debug(&format!(
-        "Download settings - output: {}, parallel: {}, continue: {}, idle_timeout: {}s",
+        "Download settings - output: {}, parallel: {}, continue: {}, idle_timeout: {}s, sync: {}",
         output.display(),
         parallel,
         continue_download,
-        idle_timeout
+        idle_timeout,
+        sync
     ));
     // 初始化断点续传管理器
     let resume_manager: ResumeManager = ResumeManager::new()?;
 
-    // 关键修改：使用 ClientType::Download，不设置总超时
-    let client: Client = build_client(true, DEFAULT_CONNECT_TIMEOUT, http3, vec![], ClientType::Download)?;
-
     // 获取文件信息
-    let (total_size, supports_range, etag, last_modified) = get_download_info_extended(&client, url).await?;
+    let (total_size, supports_range, etag, last_modified) = get_download_info_extended(client, url).await?;
 
     log_info(&format!(
         "File size: {}",
@@ -176,6 +243,18 @@ pub async fn download_file(
     ));
     log_debug(&format!("Range requests supported: {}", supports_range));
 
+    // 同步模式：远程文件未变化时跳过下载
+    if sync && output.exists() {
+        if let Some(reason) = check_unchanged(client, url, output, &resume_manager, total_size, last_modified.as_deref()).await? {
+            log_info(&format!("Skipping download, {} is up to date ({})", output.display(), reason));
+            println!("{} is up to date ({}), skipping download", output.display(), reason);
+            apply_last_modified(output, last_modified.as_deref());
+            return Ok(());
+        }
+    }
+
+    unshare_output(output, continue_download).await?;
+
     // 检查是否存在有效的断点续传元数据
     let mut metadata: Option<DownloadMetadata> = if continue_download {
         resume_manager.check_existing_download(
@@ -260,6 +339,7 @@ pub async fn download_file(
                 update_chunks_from_progress(meta, downloaded);
             }
         }
+        progress::emit(ProgressEvent::Started { metadata: meta });
     }
 
     let result = if use_parallel {
@@ -269,7 +349,7 @@ pub async fn download_file(
         ));
 
         download_parallel_with_resume(
-            &client,
+            client,
             url,
             output,
             total_size,
@@ -282,7 +362,7 @@ pub async fn download_file(
         log_info("Using single connection download");
 
         download_single_with_resume(
-            &client,
+            client,
             url,
             output,
             total_size,
@@ -302,14 +382,35 @@ pub async fn download_file(
                 log_info("Download completed successfully");
                 // 保存完成状态
                 let _ = resume_manager.save_metadata(meta_ref);
+
+                let elapsed = download_start.elapsed();
+                progress::emit(ProgressEvent::Completed {
+                    metadata: meta_ref,
+                    elapsed_ms: elapsed.as_millis() as u64,
+                    average_speed: meta_ref.total_size.saturating_sub(downloaded) as f64 / elapsed.as_secs_f64().max(0.001),
+                });
                 // 可以选择删除元数据文件，因为下载已完成
                 // let _ = resume_manager.delete_metadata(url);
+
+                if sync {
+                    apply_last_modified(output, last_modified.as_deref());
+                }
+            }
+            Err(e) if e.is::<DownloadPaused>() => {
+                meta_ref.mark_paused();
+                log_info(&format!("Download paused: {}", e));
+                let _ = resume_manager.save_metadata(meta_ref);
+                progress::emit(ProgressEvent::Checkpoint { metadata: meta_ref });
             }
             Err(e) => {
                 meta_ref.mark_failed(&e.to_string());
                 log_error(&format!("Download failed: {}", e));
                 // 保存失败状态，以便下次恢复
                 let _ = resume_manager.save_metadata(meta_ref);
+                progress::emit(ProgressEvent::Failed {
+                    metadata: meta_ref,
+                    error: e.to_string(),
+                });
             }
         }
     }
@@ -338,6 +439,119 @@ fn update_chunks_from_progress(metadata: &mut DownloadMetadata, downloaded: u64)
     }
 }
 
+/// 输出文件可能是从下载存储硬链接出来的只读对象，写入前断开链接：
+/// 否则普通用户写入会因权限失败，root 则会直接改写存储中的对象。
+/// 续传时复制一份保留已下载的内容，否则直接删除
+async fn unshare_output(output: &Path, keep_contents: bool) -> Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(output).await else {
+        return Ok(());
+    };
+    #[cfg(unix)]
+    let shared = {
+        use std::os::unix::fs::MetadataExt;
+        metadata.nlink() > 1
+    };
+    // 其他平台拿不到链接数，存储中的对象都是只读的
+    #[cfg(not(unix))]
+    let shared = metadata.permissions().readonly();
+    if !metadata.is_file() || !shared {
+        return Ok(());
+    }
+
+    log_debug(&format!("{} is linked from the download store, unlinking before writing", output.display()));
+    if keep_contents {
+        let mut name = output.file_name().unwrap_or_default().to_os_string();
+        name.push(".unshare");
+        let copy = output.with_file_name(name);
+        fs::copy(output, &copy).await.context("Failed to copy output file")?;
+        // 复制会带上存储对象的只读权限
+        let mut permissions = fs::metadata(&copy).await?.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | 0o200);
+        }
+        #[cfg(not(unix))]
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&copy, permissions).await?;
+        fs::rename(&copy, output).await.context("Failed to replace output file")?;
+    } else {
+        fs::remove_file(output).await.context("Failed to remove output file")?;
+    }
+    Ok(())
+}
+
+/// 同步模式下判断本地文件是否与远程一致，返回跳过下载的原因
+///
+/// 上次完整下载到同一位置时，发送带 If-None-Match / If-Modified-Since 的条件请求，
+/// 服务器返回 304 即视为未变化；否则比较文件大小与修改时间。
+/// 元数据表明下载未完成（暂停或中断）时不跳过，交给断点续传。
+async fn check_unchanged(
+    client: &Client,
+    url: &str,
+    output: &PathBuf,
+    resume_manager: &ResumeManager,
+    total_size: u64,
+    last_modified: Option<&str>,
+) -> Result<Option<String>> {
+    let local = fs::metadata(output).await?;
+    let local_mtime = local.modified().ok().map(DateTime::<Utc>::from);
+
+    let metadata = resume_manager.load_metadata(url)?.filter(|m| &m.output_path == output);
+    if metadata.as_ref().is_some_and(|m| m.status != DownloadStatus::Completed) {
+        log_debug("Previous download is incomplete, resuming");
+        return Ok(None);
+    }
+    // 只有上次完整下载到同一位置时，保存的校验信息才可信；
+    // 用本地文件的修改时间做条件请求会让截断的文件得到 304
+    let stored = metadata.filter(|m| m.total_size == local.len());
+
+    if let Some(stored) = &stored {
+        let mut request = client.head(url);
+        if let Some(etag) = stored.etag.as_deref() {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(since) = stored.last_modified.as_deref() {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, since);
+        }
+
+        log_debug(&format!("Sending conditional request (If-Modified-Since: {:?})", stored.last_modified));
+        let response = request.send().await.context("Conditional request failed")?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Some("304 Not Modified".to_string()));
+        }
+    }
+
+    let remote_mtime = last_modified.and_then(parse_http_date);
+    if total_size > 0 && total_size == local.len() {
+        if let (Some(remote), Some(local)) = (remote_mtime, local_mtime) {
+            if remote.timestamp() == local.timestamp() {
+                return Ok(Some("size and modification time match".to_string()));
+            }
+        }
+    }
+
+    log_debug("Remote file changed, downloading");
+    Ok(None)
+}
+
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// 将本地文件的修改时间设置为服务器的 Last-Modified
+fn apply_last_modified(output: &PathBuf, last_modified: Option<&str>) {
+    let Some(time) = last_modified.and_then(parse_http_date) else {
+        return;
+    };
+    let mtime = filetime::FileTime::from_unix_time(time.timestamp(), 0);
+    match filetime::set_file_mtime(output, mtime) {
+        Ok(_) => log_debug(&format!("Set modification time of {} to {}", output.display(), time)),
+        Err(e) => log_warn(&format!("Failed to set modification time of {}: {}", output.display(), e)),
+    }
+}
+
 async fn get_download_info(client: &Client, url: &str) -> Result<(u64, bool)> {
     let (size, supports, _, _) = get_download_info_extended(client, url).await?;
     Ok((size, supports))
@@ -675,172 +889,6 @@ async fn download_parallel(
     Ok(())
 }
 
-pub async fn benchmark_url(
-    url: &str,
-    requests: usize,
-    concurrency: usize,
-    connect_timeout: u64,
-    http3: bool,
-) -> Result<()> {
-    log_info(&format!(
-        "Starting benchmark - URL: {}, requests: {}, concurrency: {}",
-        url, requests, concurrency
-    ));
-    // 关键修改：使用 ClientType::Benchmark，设置 60 秒总超时
-    let client: Client = build_client(true, connect_timeout, http3, vec![], ClientType::Benchmark)?;
-
-    println!(
-        "Benchmarking {} with {} requests, concurrency {} (HTTP/3: {})",
-        url, requests, concurrency, http3
-    );
-
-    let start = Instant::now();
-    let semaphore = Arc::new(Semaphore::new(concurrency));
-    let mut tasks = Vec::new();
-
-    let stats = Arc::new(BenchmarkStats::new());
-
-    for i in 0..requests {
-        let client = client.clone();
-        let url = url.to_string();
-        let semaphore = Arc::clone(&semaphore);
-        let stats = Arc::clone(&stats);
-
-        let task = tokio::spawn(async move {
-            let _permit = semaphore.acquire().await?;
-            let request_start = Instant::now();
-
-            let result = client.get(&url).send().await;
-            let duration = request_start.elapsed();
-
-            let status_code = match &result {
-                Ok(resp) => Some(resp.status().as_u16()),
-                Err(_) => None,
-            };
-
-            stats.record_request(duration, status_code).await;
-
-            Ok::<(), anyhow::Error>(())
-        });
-
-        tasks.push(task);
-
-        if (i + 1) % 50 == 0 {
-            log_debug(&format!("Started {} requests", i + 1));
-        }
-    }
-
-    for task in tasks {
-        if let Err(e) = task.await? {
-            log_warn(&format!("Benchmark task failed: {}", e));
-        }
-    }
-
-    let total_time = start.elapsed();
-    stats.print_results(requests, total_time).await;
-
-    log_info(&format!(
-        "Benchmark completed - Total: {:.2}s, RPS: {:.2}, Success: {}, Failed: {}",
-        total_time.as_secs_f64(),
-        requests as f64 / total_time.as_secs_f64(),
-        stats.successful_requests.load(std::sync::atomic::Ordering::Relaxed),
-        stats.failed_requests.load(std::sync::atomic::Ordering::Relaxed)
-    ));
-
-    Ok(())
-}
-
-use std::sync::atomic::{AtomicU32, Ordering};
-use tokio::sync::Mutex;
-
-struct BenchmarkStats {
-    response_times: Arc<Mutex<Vec<u64>>>,
-    status_codes: Arc<Mutex<std::collections::HashMap<u16, u32>>>,
-    successful_requests: AtomicU32,
-    failed_requests: AtomicU32,
-}
-
-impl BenchmarkStats {
-    fn new() -> Self {
-        Self {
-            response_times: Arc::new(Mutex::new(Vec::new())),
-            status_codes: Arc::new(Mutex::new(std::collections::HashMap::new())),
-            successful_requests: AtomicU32::new(0),
-            failed_requests: AtomicU32::new(0),
-        }
-    }
-
-    async fn record_request(&self, duration: Duration, status_code: Option<u16>) {
-        let ms = duration.as_millis() as u64;
-        self.response_times.lock().await.push(ms);
-
-        if let Some(code) = status_code {
-            *self.status_codes.lock().await.entry(code).or_insert(0) += 1;
-
-            if (200..400).contains(&code) {
-                self.successful_requests.fetch_add(1, Ordering::Relaxed);
-            } else {
-                self.failed_requests.fetch_add(1, Ordering::Relaxed);
-                log_warn(&format!("Request failed with status: {}", code));
-            }
-        } else {
-            self.failed_requests.fetch_add(1, Ordering::Relaxed);
-        }
-    }
-
-    async fn print_results(&self, total_requests: usize, total_time: Duration) {
-        let rps = total_requests as f64 / total_time.as_secs_f64();
-
-        let mut sorted_times = self.response_times.lock().await.clone();
-        sorted_times.sort_unstable();
-
-        let min_time = sorted_times.first().copied().unwrap_or(0);
-        let max_time = sorted_times.last().copied().unwrap_or(0);
-        let avg_time = if !sorted_times.is_empty() {
-            sorted_times.iter().sum::<u64>() / sorted_times.len() as u64
-        } else {
-            0
-        };
-
-        let p50 = percentile(&sorted_times, 0.5);
-        let p95 = percentile(&sorted_times, 0.95);
-        let p99 = percentile(&sorted_times, 0.99);
-
-        println!("\n=== Benchmark Results ===");
-        println!("Total time: {:.2}s", total_time.as_secs_f64());
-        println!("Requests per second: {:.2}", rps);
-        println!("Successful requests: {}", self.successful_requests.load(Ordering::Relaxed));
-        println!("Failed requests: {}", self.failed_requests.load(Ordering::Relaxed));
-        println!();
-        println!("Response Times (ms):");
-        println!("  Min: {}", min_time);
-        println!("  Max: {}", max_time);
-        println!("  Avg: {}", avg_time);
-        println!("  50th percentile: {}", p50);
-        println!("  95th percentile: {}", p95);
-        println!("  99th percentile: {}", p99);
-        println!();
-        println!("Status Code Distribution:");
-
-        for (status, count) in &*self.status_codes.lock().await {
-            println!(
-                "  {}: {} ({:.1}%)",
-                status,
-                count,
-                (*count as f64 / total_requests as f64) * 100.0
-            );
-        }
-    }
-}
-
-fn percentile(sorted_data: &[u64], percentile: f64) -> u64 {
-    if sorted_data.is_empty() {
-        return 0;
-    }
-    let index = (sorted_data.len() as f64 * percentile) as usize;
-    sorted_data[std::cmp::min(index, sorted_data.len() - 1)]
-}
-
 // ========== 断点续传支持函数 ==========
 async fn download_single_with_resume(
     client: &Client,
@@ -909,6 +957,7 @@ async fn download_single_with_resume(
     let idle_duration = Duration::from_secs(idle_timeout);
     let mut bytes_downloaded = start_from;
     let mut save_counter = 0;
+    let mut ticker = ProgressTicker::new(start_from);
 
     loop {
         match tokio::time::timeout(idle_duration, stream.next()).await {
@@ -927,6 +976,17 @@ async fn download_single_with_resume(
                 // 更新元数据
                 metadata.update_chunk_progress(0, bytes_downloaded - metadata.chunks[0].start);
 
+                if let Some(speed) = ticker.tick(bytes_downloaded) {
+                    progress::emit(ProgressEvent::Progress {
+                        url,
+                        url_hash: &metadata.url_hash,
+                        total_size,
+                        downloaded: metadata.downloaded,
+                        chunk: &metadata.chunks[0],
+                        speed,
+                    });
+                }
+
                 // 每下载 10MB 保存一次元数据
                 save_counter += chunk_len;
                 if save_counter >= 10_000_000 {
@@ -934,6 +994,15 @@ async fn download_single_with_resume(
                     resume_manager.save_metadata(metadata)?;
                     save_counter = 0;
                     log_debug("Saved progress checkpoint");
+                    progress::emit(ProgressEvent::Checkpoint { metadata });
+                }
+
+                // 按时段限速；下载窗口关闭时保存进度并暂停
+                if let Err(e) = schedule::throttle(chunk_len).await {
+                    writer.flush().await?;
+                    resume_manager.save_metadata(metadata)?;
+                    pb.abandon_with_message("Paused");
+                    return Err(e);
                 }
             }
             Ok(None) => {
@@ -1006,8 +1075,13 @@ async fn download_parallel_with_resume(
         metadata.chunks.len()
     ));
 
-    // 创建进度条
-    let pb = create_progress_bar(total_size, metadata.downloaded);
+    // 创建总进度条与每个分片的进度条
+    let multi = if progress::json_enabled() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    };
+    let pb = multi.add(create_progress_bar(total_size, metadata.downloaded));
     let start_time = Instant::now();
 
     // 打开文件用于写入
@@ -1039,6 +1113,13 @@ async fn download_parallel_with_resume(
         let semaphore = Arc::clone(&semaphore);
 
         let chunk_info = metadata.chunks[chunk_index].clone();
+        let chunk_pb = multi.add(create_chunk_progress_bar(
+            chunk_index,
+            chunk_info.end - chunk_info.start,
+            chunk_info.downloaded,
+        ));
+        let task_chunk_pb = chunk_pb.clone();
+        let url_hash = metadata.url_hash.clone();
 
         let task = tokio::spawn(async move {
             let _permit = semaphore.acquire().await?;
@@ -1070,6 +1151,7 @@ async fn download_parallel_with_resume(
             let mut stream = response.bytes_stream();
             let mut current_pos = start;
             let idle_duration = Duration::from_secs(idle_timeout);
+            let mut ticker = ProgressTicker::new(task_chunk_pb.position());
 
             loop {
                 match tokio::time::timeout(idle_duration, stream.next()).await {
@@ -1096,7 +1178,26 @@ async fn download_parallel_with_resume(
                         }).await.context("Spawn blocking write failed")?.context("File write operation failed")?;
 
                         pb.inc(chunk_len as u64);
+                        task_chunk_pb.inc(chunk_len as u64);
                         current_pos += chunk_len as u64;
+
+                        if let Some(speed) = ticker.tick(task_chunk_pb.position()) {
+                            let chunk = ChunkInfo {
+                                downloaded: task_chunk_pb.position(),
+                                status: ChunkStatus::Downloading,
+                                ..chunk_info.clone()
+                            };
+                            progress::emit(ProgressEvent::Progress {
+                                url: &url,
+                                url_hash: &url_hash,
+                                total_size,
+                                downloaded: pb.position(),
+                                chunk: &chunk,
+                                speed,
+                            });
+                        }
+
+                        schedule::throttle(chunk_len).await?;
                     }
                     Ok(None) => {
                         break;
@@ -1107,36 +1208,52 @@ async fn download_parallel_with_resume(
                 }
             }
 
+            task_chunk_pb.finish_and_clear();
             Ok::<(), anyhow::Error>(())
         });
 
-        tasks.push((chunk_index, task));
+        tasks.push((chunk_index, chunk_pb, task));
     }
 
     // 等待所有任务完成并更新元数据
-    for (chunk_index, task) in tasks {
+    let mut paused = None;
+    for (chunk_index, chunk_pb, task) in tasks {
         match task.await {
             Ok(Ok(())) => {
                 log_debug(&format!("Chunk {} completed successfully", chunk_index));
+                // 分片进度条的位置即该分片已写入文件的字节数
+                metadata.update_chunk_progress(chunk_index, chunk_pb.position());
                 metadata.set_chunk_status(chunk_index, ChunkStatus::Completed);
 
                 // 定期保存元数据
                 if last_save.elapsed() >= metadata_save_interval {
                     resume_manager.save_metadata(metadata)?;
                     last_save = Instant::now();
+                    progress::emit(ProgressEvent::Checkpoint { metadata });
                 }
             }
+            Ok(Err(e)) if e.is::<DownloadPaused>() => {
+                // 窗口关闭时所有分片都会停下，记录各自的进度后再返回
+                log_debug(&format!("Chunk {} paused", chunk_index));
+                metadata.update_chunk_progress(chunk_index, chunk_pb.position());
+                chunk_pb.abandon();
+                paused.get_or_insert(e);
+            }
             Ok(Err(e)) => {
                 log_error(&format!("Chunk {} failed: {}", chunk_index, e));
+                metadata.update_chunk_progress(chunk_index, chunk_pb.position());
                 metadata.set_chunk_status(chunk_index, ChunkStatus::Failed);
                 resume_manager.save_metadata(metadata)?;
+                chunk_pb.abandon();
                 pb.abandon_with_message(format!("Chunk {} failed: {}", chunk_index, e));
                 return Err(e);
             }
             Err(e) => {
                 log_error(&format!("Chunk {} task panicked: {}", chunk_index, e));
+                metadata.update_chunk_progress(chunk_index, chunk_pb.position());
                 metadata.set_chunk_status(chunk_index, ChunkStatus::Failed);
                 resume_manager.save_metadata(metadata)?;
+                chunk_pb.abandon();
                 pb.abandon_with_message(format!("Chunk {} panicked", chunk_index));
                 return Err(anyhow!("Chunk task panicked: {}", e));
             }
@@ -1146,6 +1263,11 @@ async fn download_parallel_with_resume(
     // 最终保存
     resume_manager.save_metadata(metadata)?;
 
+    if let Some(e) = paused {
+        pb.abandon_with_message("Paused");
+        return Err(e);
+    }
+
     let elapsed = start_time.elapsed();
     let speed = if elapsed.as_secs_f64() > 0.0 {
         total_size as f64 / elapsed.as_secs_f64()
@@ -1165,4 +1287,19 @@ async fn download_parallel_with_resume(
         elapsed.as_secs_f64()
     ));
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("10G").unwrap(), 10 << 30);
+        assert_eq!(parse_size("1.5m").unwrap(), 1536 * 1024);
+        assert_eq!(parse_size("500K").unwrap(), 500 * 1024);
+        assert!(parse_size("big").is_err());
+        assert!(parse_size("-1M").is_err());
+    }
+}
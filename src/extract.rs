@@ -0,0 +1,392 @@
+use crate::log::{log_info, log_warn};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Component, Path, PathBuf},
+};
+
+/// 支持的归档格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+    Gz,
+}
+
+impl ArchiveKind {
+    /// 根据文件名判断归档格式
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveKind::TarZst)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".gz") {
+            Some(ArchiveKind::Gz)
+        } else {
+            None
+        }
+    }
+}
+
+/// 去掉前 strip_components 层目录，并拒绝绝对路径和 ".."，防止路径穿越
+pub fn sanitize_entry_path(path: &Path, strip_components: usize) -> Result<Option<PathBuf>> {
+    let mut result = PathBuf::new();
+    let mut depth = 0;
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                if depth >= strip_components {
+                    result.push(part);
+                }
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(anyhow!("Refusing to extract unsafe path: {}", path.display()));
+            }
+        }
+    }
+
+    if result.as_os_str().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(result))
+    }
+}
+
+/// 检查符号链接目标是否仍然位于解压目录内
+fn link_stays_inside(entry_path: &Path, target: &Path) -> bool {
+    if target.is_absolute() {
+        return false;
+    }
+
+    let mut depth: isize = entry_path.components().count() as isize - 1;
+    for component in target.components() {
+        match component {
+            Component::ParentDir => depth -= 1,
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            _ => return false,
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    true
+}
+
+// 拒绝经过已有的符号链接写入：链接目标只做了字面检查，
+// 链接链（x/y -> ..，再 x/y/z -> ..）仍然可以把后面的文件引到目录外
+fn ensure_no_symlink_parents(dest: &Path, relative: &Path) -> Result<()> {
+    let mut current = dest.to_path_buf();
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        current.push(component);
+        if fs::symlink_metadata(&current).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            return Err(anyhow!("Refusing to extract through symlink: {}", current.display()));
+        }
+    }
+    Ok(())
+}
+
+/// 解压归档到目标目录，返回解压出的路径列表
+pub fn extract_archive(archive: &Path, dest: &Path, strip_components: usize) -> Result<Vec<PathBuf>> {
+    let kind = ArchiveKind::detect(archive)
+        .ok_or_else(|| anyhow!("Unsupported archive format: {}", archive.display()))?;
+
+    fs::create_dir_all(dest).with_context(|| format!("Failed to create directory: {}", dest.display()))?;
+    log_info(&format!(
+        "Extracting {} ({:?}) to {} (strip components: {})",
+        archive.display(),
+        kind,
+        dest.display(),
+        strip_components
+    ));
+
+    let file = BufReader::new(
+        File::open(archive).with_context(|| format!("Failed to open archive: {}", archive.display()))?,
+    );
+
+    match kind {
+        ArchiveKind::Tar => extract_tar(file, dest, strip_components),
+        ArchiveKind::TarGz => extract_tar(flate2::read::GzDecoder::new(file), dest, strip_components),
+        ArchiveKind::TarZst => extract_tar(
+            zstd::stream::read::Decoder::with_buffer(file).context("Failed to initialize zstd decoder")?,
+            dest,
+            strip_components,
+        ),
+        ArchiveKind::Zip => extract_zip(archive, dest, strip_components),
+        ArchiveKind::Gz => extract_gz(file, archive, dest),
+    }
+}
+
+fn extract_tar<R: Read>(reader: R, dest: &Path, strip_components: usize) -> Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    let mut extracted = Vec::new();
+
+    for entry in archive.entries().context("Failed to read tar archive")? {
+        let mut entry = entry.context("Failed to read tar entry")?;
+        let entry_path = entry.path().context("Invalid tar entry path")?.into_owned();
+        let Some(relative) = sanitize_entry_path(&entry_path, strip_components)? else {
+            continue;
+        };
+
+        ensure_no_symlink_parents(dest, &relative)?;
+        let target = dest.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let link = entry
+                .link_name()
+                .context("Invalid link target")?
+                .ok_or_else(|| anyhow!("Link without target: {}", entry_path.display()))?
+                .into_owned();
+            let escaping = || {
+                anyhow!(
+                    "Refusing to extract link escaping the destination: {} -> {}",
+                    entry_path.display(),
+                    link.display()
+                )
+            };
+            if entry_type.is_hard_link() {
+                // 硬链接目标是归档内的路径，同样去掉前几层目录后相对解压目录解析
+                let source = sanitize_entry_path(&link, strip_components)
+                    .map_err(|_| escaping())?
+                    .ok_or_else(escaping)?;
+                ensure_no_symlink_parents(dest, &source)?;
+                let source = dest.join(source);
+                // 链接到符号链接会在别的深度得到同样的相对目标，可能指到目录外
+                if fs::symlink_metadata(&source).is_ok_and(|meta| meta.file_type().is_symlink()) {
+                    return Err(escaping());
+                }
+                if fs::symlink_metadata(&target).is_ok() {
+                    fs::remove_file(&target)?;
+                }
+                fs::hard_link(&source, &target)
+                    .with_context(|| format!("Failed to extract {}", entry_path.display()))?;
+                extracted.push(target);
+                continue;
+            }
+            if !link_stays_inside(&relative, &link) {
+                return Err(escaping());
+            }
+        }
+
+        entry
+            .unpack(&target)
+            .with_context(|| format!("Failed to extract {}", entry_path.display()))?;
+        extracted.push(target);
+    }
+
+    Ok(extracted)
+}
+
+fn extract_zip(archive_path: &Path, dest: &Path, strip_components: usize) -> Result<Vec<PathBuf>> {
+    let file = File::open(archive_path)?;
+    let mut archive = zip::ZipArchive::new(file).context("Failed to read zip archive")?;
+    let mut extracted = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).context("Failed to read zip entry")?;
+        let name = entry.name().to_string();
+        let Some(enclosed) = entry.enclosed_name() else {
+            return Err(anyhow!("Refusing to extract unsafe path: {}", name));
+        };
+        let Some(relative) = sanitize_entry_path(&enclosed, strip_components)? else {
+            continue;
+        };
+        // zip 中的符号链接在解压时会变成普通文件或指向任意位置，直接拒绝
+        if entry.is_symlink() {
+            return Err(anyhow!("Refusing to extract symlink from zip archive: {}", name));
+        }
+        ensure_no_symlink_parents(dest, &relative)?;
+
+        let target = dest.join(&relative);
+        // 已有的符号链接会让 File::create 写到链接目标上
+        if fs::symlink_metadata(&target).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            fs::remove_file(&target)?;
+        }
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut out = File::create(&target).with_context(|| format!("Failed to create {}", target.display()))?;
+            io::copy(&mut entry, &mut out).with_context(|| format!("Failed to extract {}", name))?;
+
+            #[cfg(unix)]
+            if let Some(mode) = entry.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                let _ = fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o777));
+            }
+        }
+        extracted.push(target);
+    }
+
+    Ok(extracted)
+}
+
+fn extract_gz<R: Read>(reader: R, archive: &Path, dest: &Path) -> Result<Vec<PathBuf>> {
+    let stem = archive
+        .file_stem()
+        .ok_or_else(|| anyhow!("Cannot derive output name from {}", archive.display()))?;
+    let target = dest.join(stem);
+    if target == archive {
+        log_warn("Decompressed file would overwrite the archive itself");
+        return Err(anyhow!("Refusing to overwrite {}", archive.display()));
+    }
+
+    let mut decoder = flate2::read::GzDecoder::new(reader);
+    let mut out = File::create(&target).with_context(|| format!("Failed to create {}", target.display()))?;
+    io::copy(&mut decoder, &mut out).context("Failed to decompress gzip file")?;
+
+    Ok(vec![target])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_kind() {
+        assert_eq!(ArchiveKind::detect(Path::new("a.tar.gz")), Some(ArchiveKind::TarGz));
+        assert_eq!(ArchiveKind::detect(Path::new("a.tar.zst")), Some(ArchiveKind::TarZst));
+        assert_eq!(ArchiveKind::detect(Path::new("a.json.gz")), Some(ArchiveKind::Gz));
+        assert_eq!(ArchiveKind::detect(Path::new("a.iso")), None);
+    }
+
+    #[test]
+    fn test_sanitize_entry_path() {
+        assert_eq!(
+            sanitize_entry_path(Path::new("pkg-1.0/bin/tool"), 1).unwrap(),
+            Some(PathBuf::from("bin/tool"))
+        );
+        assert_eq!(sanitize_entry_path(Path::new("pkg-1.0/"), 1).unwrap(), None);
+        assert!(sanitize_entry_path(Path::new("../etc/passwd"), 0).is_err());
+        assert!(sanitize_entry_path(Path::new("/etc/passwd"), 0).is_err());
+
+        assert!(link_stays_inside(Path::new("bin/tool"), Path::new("../lib/tool")));
+        assert!(!link_stays_inside(Path::new("bin/tool"), Path::new("../../etc/passwd")));
+    }
+
+    // 按原样写入路径和链接目标的 tar 条目（tar::Builder 会拒绝 ".." 等路径）
+    fn raw_entry(builder: &mut tar::Builder<Vec<u8>>, kind: tar::EntryType, path: &str, link: &str, data: &[u8]) {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+        header.set_entry_type(kind);
+        header.set_mode(0o644);
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    // 把条目写成 .tar 文件并解压到一个新目录，返回解压结果和目录
+    fn extract_fixture(name: &str, entries: &[(tar::EntryType, &str, &str, &[u8])], strip: usize) -> (Result<Vec<PathBuf>>, PathBuf) {
+        let root = std::env::temp_dir().join(format!("surf-extract-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        for (kind, path, link, data) in entries {
+            raw_entry(&mut builder, *kind, path, link, data);
+        }
+        let archive = root.join("fixture.tar");
+        fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+        let dest = root.join("out");
+        (extract_archive(&archive, &dest, strip), root)
+    }
+
+    #[test]
+    fn test_extract_tar_rejects_escapes() {
+        use tar::EntryType::{Link, Regular, Symlink};
+
+        // 硬链接目标相对解压目录解析，并去掉前几层目录
+        let (result, root) = extract_fixture(
+            "hardlink",
+            &[(Regular, "pkg/data.txt", "", b"hello"), (Link, "pkg/copy.txt", "pkg/data.txt", b"")],
+            1,
+        );
+        result.unwrap();
+        assert_eq!(fs::read(root.join("out/copy.txt")).unwrap(), b"hello");
+        fs::remove_dir_all(&root).unwrap();
+
+        let (result, root) = extract_fixture("passwd", &[(Link, "passwd", "/etc/passwd", b"")], 0);
+        assert!(result.is_err());
+        assert!(!root.join("out/passwd").exists());
+        fs::remove_dir_all(&root).unwrap();
+
+        let (result, root) = extract_fixture("dotdot", &[(Regular, "../evil.txt", "", b"x")], 0);
+        assert!(result.is_err());
+        assert!(!root.join("evil.txt").exists());
+        fs::remove_dir_all(&root).unwrap();
+
+        // 每一步都留在目录内的符号链接链，合起来指到目录外
+        let (result, root) = extract_fixture(
+            "chain",
+            &[
+                (Symlink, "x/y", "..", b""),
+                (Symlink, "x/y/z", "..", b""),
+                (Regular, "x/y/z/pwned", "", b"x"),
+            ],
+            0,
+        );
+        assert!(result.is_err());
+        assert!(!root.join("pwned").exists() && !root.join("out/pwned").exists());
+        fs::remove_dir_all(&root).unwrap();
+
+        let (result, root) =
+            extract_fixture("linktolink", &[(Symlink, "a/up", "..", b""), (Link, "up", "a/up", b"")], 0);
+        assert!(result.is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_zip_rejects_symlinks() {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let root = std::env::temp_dir().join(format!("surf-extract-test-{}-zip", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::create_dir_all(root.join("outside")).unwrap();
+
+        let write_zip = |name: &str, build: &dyn Fn(&mut zip::ZipWriter<File>)| {
+            let path = root.join(name);
+            let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+            build(&mut writer);
+            writer.finish().unwrap();
+            path
+        };
+
+        let archive = write_zip("link.zip", &|writer| {
+            writer.add_symlink("passwd", "/etc/passwd", SimpleFileOptions::default()).unwrap();
+        });
+        assert!(extract_archive(&archive, &root.join("out"), 0).is_err());
+        assert!(fs::symlink_metadata(root.join("out/passwd")).is_err());
+
+        // 解压目录中已有指向外部的符号链接时，不能经过它写入
+        std::os::unix::fs::symlink(root.join("outside"), root.join("out/sub")).unwrap();
+        let archive = write_zip("through.zip", &|writer| {
+            writer.start_file("sub/pwned", SimpleFileOptions::default()).unwrap();
+            writer.write_all(b"x").unwrap();
+        });
+        assert!(extract_archive(&archive, &root.join("out"), 0).is_err());
+        assert!(!root.join("outside/pwned").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
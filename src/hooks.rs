@@ -0,0 +1,174 @@
+use crate::checksum::{hash_file, HashAlgorithm};
+use crate::config::Profile;
+use crate::log::{log_info, log_warn};
+use anyhow::{anyhow, Context, Result};
+use std::{path::PathBuf, time::Duration};
+use tokio::process::Command;
+
+/// 下载完成/失败时执行的外部命令
+#[derive(Debug, Clone, Default)]
+pub struct DownloadHooks {
+    pub on_complete: Option<String>,
+    pub on_fail: Option<String>,
+}
+
+/// 传给钩子命令的下载结果
+pub struct HookContext<'a> {
+    pub url: &'a str,
+    pub path: Option<&'a PathBuf>,
+    pub duration: Duration,
+    pub error: Option<String>,
+}
+
+impl DownloadHooks {
+    /// 命令行参数优先，其次使用 profile 中的默认值
+    pub fn resolve(on_complete: Option<String>, on_fail: Option<String>, profile: Option<&Profile>) -> Self {
+        Self {
+            on_complete: on_complete.or_else(|| profile.and_then(|p| p.on_complete.clone())),
+            on_fail: on_fail.or_else(|| profile.and_then(|p| p.on_fail.clone())),
+        }
+    }
+
+    /// 执行 on-complete 钩子；命令返回非零状态时视为失败
+    pub async fn run_complete(&self, context: &HookContext<'_>) -> Result<()> {
+        match &self.on_complete {
+            Some(command) => run_hook("on-complete", command, context).await,
+            None => Ok(()),
+        }
+    }
+
+    /// 执行 on-fail 钩子；钩子本身出错只记录警告，不覆盖原始错误
+    pub async fn run_fail(&self, context: &HookContext<'_>) {
+        if let Some(command) = &self.on_fail {
+            if let Err(e) = run_hook("on-fail", command, context).await {
+                log_warn(&e.to_string());
+                eprintln!("Warning: {}", e);
+            }
+        }
+    }
+}
+
+async fn run_hook(name: &str, command: &str, context: &HookContext<'_>) -> Result<()> {
+    let mut cmd = shell_command(command);
+
+    let size = context
+        .path
+        .and_then(|p| std::fs::metadata(p).ok())
+        .map(|m| m.len());
+    let seconds = context.duration.as_secs_f64();
+
+    cmd.env("SURF_URL", context.url)
+        .env("SURF_STATUS", if context.error.is_none() { "completed" } else { "failed" })
+        .env("SURF_DURATION", format!("{:.3}", seconds));
+
+    if let Some(path) = context.path {
+        cmd.env("SURF_PATH", path);
+    }
+    if let Some(size) = size {
+        cmd.env("SURF_SIZE", size.to_string());
+        let speed = if seconds > 0.0 { size as f64 / seconds } else { 0.0 };
+        cmd.env("SURF_SPEED", format!("{:.0}", speed));
+    }
+    if let Some(error) = &context.error {
+        cmd.env("SURF_ERROR", error);
+    }
+
+    // 只在成功时计算校验和，避免对不完整的文件做无用功
+    if let (Some(path), None) = (context.path, &context.error) {
+        let path = path.clone();
+        let checksum = tokio::task::spawn_blocking(move || hash_file(&path, HashAlgorithm::Sha256))
+            .await
+            .map_err(|e| anyhow!("Checksum task failed: {}", e))??;
+        cmd.env("SURF_CHECKSUM", format!("sha256:{}", checksum));
+    }
+
+    log_info(&format!("Running {} hook: {}", name, command));
+    let status = cmd
+        .status()
+        .await
+        .with_context(|| format!("Failed to run {} hook: {}", name, command))?;
+
+    if status.success() {
+        log_info(&format!("{} hook finished successfully", name));
+        Ok(())
+    } else {
+        Err(anyhow!("{} hook exited with {}: {}", name, status, command))
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(not(unix))]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // 运行钩子，把它看到的 SURF_* 环境变量写到 env 文件后读回
+    async fn hook_env(dir: &std::path::Path, context: &HookContext<'_>, failed: bool) -> HashMap<String, String> {
+        let env_file = dir.join("env");
+        let command = format!("env | grep '^SURF_' > '{}'", env_file.display());
+        let hooks = match failed {
+            false => DownloadHooks { on_complete: Some(command), on_fail: None },
+            true => DownloadHooks { on_complete: None, on_fail: Some(command) },
+        };
+        match failed {
+            false => hooks.run_complete(context).await.unwrap(),
+            true => hooks.run_fail(context).await,
+        }
+        std::fs::read_to_string(&env_file)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_hook_environment() {
+        let dir = std::env::temp_dir().join(format!("surf-hooks-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        std::fs::write(&path, "abc").unwrap();
+
+        let context =
+            HookContext { url: "http://x/file.txt", path: Some(&path), duration: Duration::from_secs(2), error: None };
+        let env = hook_env(&dir, &context, false).await;
+        assert_eq!(env["SURF_URL"], "http://x/file.txt");
+        assert_eq!(env["SURF_STATUS"], "completed");
+        assert_eq!(env["SURF_PATH"], path.display().to_string());
+        assert_eq!(env["SURF_SIZE"], "3");
+        assert_eq!(env["SURF_DURATION"], "2.000");
+        assert_eq!(env["SURF_SPEED"], "2");
+        assert_eq!(env["SURF_CHECKSUM"], "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(!env.contains_key("SURF_ERROR"));
+
+        let context = HookContext { error: Some("connection reset".to_string()), ..context };
+        let env = hook_env(&dir, &context, true).await;
+        assert_eq!(env["SURF_STATUS"], "failed");
+        assert_eq!(env["SURF_ERROR"], "connection reset");
+        assert!(!env.contains_key("SURF_CHECKSUM"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failing_hook() {
+        let context = HookContext { url: "http://x", path: None, duration: Duration::ZERO, error: None };
+        let hooks = DownloadHooks { on_complete: Some("exit 3".to_string()), on_fail: Some("exit 3".to_string()) };
+        let error = hooks.run_complete(&context).await.unwrap_err();
+        assert!(error.to_string().contains("on-complete hook exited"), "{}", error);
+        // on-fail 钩子出错只记录警告
+        hooks.run_fail(&context).await;
+    }
+}
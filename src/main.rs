@@ -8,11 +8,33 @@ mod response;
 mod cache;
 mod game;
 mod resume;
+mod checksum;
+mod metalink;
+mod mirror;
+mod stream;
+mod extract;
+mod progress;
+mod hooks;
+mod schedule;
+mod store;
+mod signature;
+mod oci;
+mod bench;
+mod bench_report;
+mod bench_tui;
+mod bench_scenario;
+mod bench_feed;
+mod bench_expect;
+mod bench_conn;
+mod bench_compare;
+#[cfg(test)]
+mod test_server;
 
 
 use anyhow::Result;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    cli::execute().await
+fn main() -> Result<()> {
+    // --progress-fd 要在运行时打开自己的描述符之前确认哪些是继承来的
+    progress::record_inherited_fds();
+    tokio::runtime::Runtime::new()?.block_on(cli::execute())
 }
\ No newline at end of file
@@ -0,0 +1,798 @@
+use crate::checksum::{hash_file, hash_file_range, hashes_match, HashAlgorithm};
+use crate::core::{build_client, create_progress_bar, download_file, ClientType};
+use crate::log::{log_debug, log_error, log_info, log_warn};
+use crate::progress::{self, ProgressEvent};
+use crate::resume::{ChunkInfo, ChunkStatus, DownloadMetadata, DownloadStatus, ResumeManager};
+use crate::schedule::{self, DownloadPaused};
+use anyhow::{anyhow, Context, Result};
+use indicatif::HumanBytes;
+use reqwest::{
+    header::{ACCEPT_RANGES, CONTENT_LENGTH},
+    Client, StatusCode,
+};
+use std::{
+    io::{Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+const METALINK_NAMESPACE: &str = "urn:ietf:params:xml:ns:metalink";
+const DEFAULT_PRIORITY: u32 = u32::MAX;
+
+/// Metalink v4 (RFC 5854) 文档
+#[derive(Debug, Clone)]
+pub struct Metalink {
+    pub files: Vec<MetalinkFile>,
+}
+
+/// Metalink 中描述的单个文件
+#[derive(Debug, Clone)]
+pub struct MetalinkFile {
+    /// 文件名（可包含相对路径）
+    pub name: String,
+    /// 文件大小（字节）
+    pub size: Option<u64>,
+    /// 整个文件的哈希值
+    pub hashes: Vec<MetalinkHash>,
+    /// 分片哈希
+    pub pieces: Option<MetalinkPieces>,
+    /// 镜像地址
+    pub urls: Vec<MetalinkUrl>,
+}
+
+/// 哈希值及其算法名称
+#[derive(Debug, Clone)]
+pub struct MetalinkHash {
+    pub hash_type: String,
+    pub value: String,
+}
+
+/// 分片哈希列表
+#[derive(Debug, Clone)]
+pub struct MetalinkPieces {
+    /// 每个分片的长度（最后一个分片可能更短）
+    pub length: u64,
+    pub hash_type: String,
+    pub hashes: Vec<String>,
+}
+
+/// 镜像地址
+#[derive(Debug, Clone)]
+pub struct MetalinkUrl {
+    pub url: String,
+    /// 优先级，数值越小越优先
+    pub priority: u32,
+    pub location: Option<String>,
+}
+
+impl Metalink {
+    /// 解析 Metalink v4 XML 文档
+    pub fn parse(content: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(content)
+            .map_err(|e| anyhow!("Failed to parse metalink document: {}", e))?;
+
+        let root = document.root_element();
+        if root.tag_name().name() != "metalink" {
+            return Err(anyhow!("Not a metalink document: root element is <{}>", root.tag_name().name()));
+        }
+        if root.tag_name().namespace() != Some(METALINK_NAMESPACE) {
+            log_warn("Metalink document does not use the RFC 5854 namespace, parsing anyway");
+        }
+
+        let mut files = Vec::new();
+        for file_node in root.children().filter(|n| is_element(n, "file")) {
+            let name = file_node
+                .attribute("name")
+                .ok_or_else(|| anyhow!("Metalink <file> element is missing the name attribute"))?
+                .to_string();
+
+            let mut file = MetalinkFile {
+                name,
+                size: None,
+                hashes: Vec::new(),
+                pieces: None,
+                urls: Vec::new(),
+            };
+
+            for child in file_node.children().filter(|n| n.is_element()) {
+                match child.tag_name().name() {
+                    "size" => {
+                        let text = child.text().unwrap_or("").trim();
+                        file.size = Some(
+                            text.parse()
+                                .with_context(|| format!("Invalid size '{}' for {}", text, file.name))?,
+                        );
+                    }
+                    "hash" => {
+                        if let (Some(hash_type), Some(value)) = (child.attribute("type"), child.text()) {
+                            file.hashes.push(MetalinkHash {
+                                hash_type: hash_type.to_string(),
+                                value: value.trim().to_string(),
+                            });
+                        }
+                    }
+                    "pieces" => {
+                        let length = child
+                            .attribute("length")
+                            .and_then(|l| l.parse::<u64>().ok())
+                            .filter(|&l| l > 0)
+                            .ok_or_else(|| anyhow!("Invalid pieces length for {}", file.name))?;
+                        let hash_type = child
+                            .attribute("type")
+                            .ok_or_else(|| anyhow!("Missing pieces hash type for {}", file.name))?
+                            .to_string();
+                        let hashes = child
+                            .children()
+                            .filter(|n| is_element(n, "hash"))
+                            .filter_map(|n| n.text().map(|t| t.trim().to_string()))
+                            .collect();
+
+                        file.pieces = Some(MetalinkPieces { length, hash_type, hashes });
+                    }
+                    "url" => {
+                        if let Some(url) = child.text() {
+                            file.urls.push(MetalinkUrl {
+                                url: url.trim().to_string(),
+                                priority: child
+                                    .attribute("priority")
+                                    .and_then(|p| p.parse().ok())
+                                    .unwrap_or(DEFAULT_PRIORITY),
+                                location: child.attribute("location").map(|l| l.to_string()),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            files.push(file);
+        }
+
+        if files.is_empty() {
+            return Err(anyhow!("Metalink document does not describe any files"));
+        }
+
+        Ok(Self { files })
+    }
+
+    /// 从本地文件或远程 URL 加载 Metalink 文档
+    pub async fn load(source: &str, http3: bool) -> Result<Self> {
+        let content = if source.starts_with("http://") || source.starts_with("https://") {
+            log_info(&format!("Fetching metalink document from: {}", source));
+            let client = build_client(true, 10, http3, vec![], ClientType::Get)?;
+            let response = client.get(source).send().await.context("Failed to fetch metalink document")?;
+            if !response.status().is_success() {
+                return Err(anyhow!("Failed to fetch metalink document: {}", response.status()));
+            }
+            response.text().await.context("Failed to read metalink document")?
+        } else {
+            std::fs::read_to_string(source)
+                .with_context(|| format!("Failed to read metalink file: {}", source))?
+        };
+
+        Self::parse(&content)
+    }
+}
+
+impl MetalinkFile {
+    /// 按优先级排序的镜像列表
+    pub fn mirrors(&self) -> Vec<&MetalinkUrl> {
+        let mut mirrors: Vec<&MetalinkUrl> = self.urls.iter().collect();
+        mirrors.sort_by_key(|m| m.priority);
+        mirrors
+    }
+
+    /// 挑选受支持的最强哈希算法
+    pub fn best_hash(&self) -> Option<(HashAlgorithm, &str)> {
+        self.hashes
+            .iter()
+            .filter_map(|h| HashAlgorithm::from_name(&h.hash_type).map(|a| (a, h.value.as_str())))
+            .max_by_key(|(a, _)| a.strength())
+    }
+
+    /// 校验文件名，拒绝绝对路径和 ".." 以防止路径穿越
+    pub fn relative_path(&self) -> Result<PathBuf> {
+        let path = PathBuf::from(&self.name);
+        let safe = path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if self.name.is_empty() || !safe {
+            return Err(anyhow!("Unsafe file name in metalink: '{}'", self.name));
+        }
+        Ok(path)
+    }
+}
+
+impl MetalinkPieces {
+    /// 分片列表描述的文件大小范围是否包含 size：除最后一个分片外都是完整分片，
+    /// 且每个分片都从文件内开始
+    pub fn fits(&self, size: u64) -> bool {
+        let count = self.hashes.len() as u64;
+        count == 0 || ((count - 1) * self.length < size && size <= count * self.length)
+    }
+}
+
+fn is_element(node: &roxmltree::Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+/// 下载 Metalink 中描述的所有文件，并根据哈希值进行校验，返回校验通过的文件路径
+pub async fn download_metalink(
+    source: &str,
+    output: Option<&PathBuf>,
+    parallel: usize,
+    continue_download: bool,
+    idle_timeout: u64,
+    http3: bool,
+) -> Result<Vec<PathBuf>> {
+    let metalink = Metalink::load(source, http3).await?;
+    log_info(&format!("Metalink describes {} file(s)", metalink.files.len()));
+
+    let single = metalink.files.len() == 1;
+    let mut downloaded = Vec::new();
+    for file in &metalink.files {
+        let relative = file.relative_path()?;
+        let target = match output {
+            Some(path) if single && !path.is_dir() => path.clone(),
+            Some(dir) => dir.join(&relative),
+            None => relative,
+        };
+
+        if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+
+        println!("Downloading {} -> {}", file.name, target.display());
+        download_metalink_file(file, &target, parallel, continue_download, idle_timeout, http3).await?;
+        downloaded.push(target);
+    }
+
+    Ok(downloaded)
+}
+
+async fn download_metalink_file(
+    file: &MetalinkFile,
+    target: &PathBuf,
+    parallel: usize,
+    continue_download: bool,
+    idle_timeout: u64,
+    http3: bool,
+) -> Result<()> {
+    let mirrors = file.mirrors();
+    if mirrors.is_empty() {
+        return Err(anyhow!("No mirror URLs available for {}", file.name));
+    }
+
+    // 有分片哈希时按分片下载，每个分片校验通过后才算完成
+    if download_pieces(file, target, &mirrors, parallel, continue_download, http3).await? {
+        return verify_metalink_file(file, target, &mirrors, http3, true).await;
+    }
+
+    // 依次尝试各个镜像，失败时切换到下一个
+    let mut used_mirror = None;
+    for (attempt, mirror) in mirrors.iter().enumerate() {
+        if attempt > 0 {
+            progress::emit(ProgressEvent::Retry {
+                url: &mirror.url,
+                attempt,
+                reason: format!("Mirror {} failed", mirrors[attempt - 1].url),
+            });
+        }
+        log_info(&format!(
+            "Trying mirror {} (priority: {}, location: {})",
+            mirror.url,
+            mirror.priority,
+            mirror.location.as_deref().unwrap_or("unknown")
+        ));
+
+        match download_file(&mirror.url, target, parallel, continue_download, idle_timeout, http3, false).await {
+            Ok(_) => {
+                used_mirror = Some(mirror.url.clone());
+                break;
+            }
+            Err(e) if e.is::<DownloadPaused>() => return Err(e),
+            Err(e) => {
+                log_warn(&format!("Mirror {} failed: {}", mirror.url, e));
+                eprintln!("Mirror {} failed: {}", mirror.url, e);
+            }
+        }
+    }
+
+    let used_mirror = used_mirror.ok_or_else(|| anyhow!("All mirrors failed for {}", file.name))?;
+
+    let result = verify_metalink_file(file, target, &mirrors, http3, false).await;
+    if let Err(ref e) = result {
+        // 校验失败时记录到断点续传元数据中
+        let resume_manager = ResumeManager::new()?;
+        if let Some(mut metadata) = resume_manager.load_metadata(&used_mirror)? {
+            metadata.mark_failed(&e.to_string());
+            resume_manager.save_metadata(&metadata)?;
+        }
+    }
+
+    result
+}
+
+async fn verify_metalink_file(
+    file: &MetalinkFile,
+    target: &Path,
+    mirrors: &[&MetalinkUrl],
+    http3: bool,
+    pieces_verified: bool,
+) -> Result<()> {
+    let actual_size = std::fs::metadata(target)
+        .with_context(|| format!("Failed to stat downloaded file: {}", target.display()))?
+        .len();
+
+    if let Some(size) = file.size {
+        if size != actual_size {
+            return Err(anyhow!(
+                "Size mismatch for {}: expected {}, got {}",
+                file.name,
+                size,
+                actual_size
+            ));
+        }
+    }
+
+    // 整体下载（镜像不支持 Range）后再按分片校验，修复损坏的分片
+    if let (false, Some(pieces)) = (pieces_verified, &file.pieces) {
+        if let Some(algorithm) = HashAlgorithm::from_name(&pieces.hash_type) {
+            verify_and_repair_pieces(file, pieces, algorithm, target, actual_size, mirrors, http3).await?;
+        }
+    }
+
+    match file.best_hash() {
+        Some((algorithm, expected)) => {
+            let path = target.to_path_buf();
+            let actual = tokio::task::spawn_blocking(move || hash_file(&path, algorithm))
+                .await
+                .context("Hashing task failed")??;
+
+            if !hashes_match(expected, &actual) {
+                log_error(&format!("{} mismatch for {}: expected {}, got {}", algorithm.name(), file.name, expected, actual));
+                return Err(anyhow!("Checksum mismatch for {} ({})", file.name, algorithm.name()));
+            }
+
+            log_info(&format!("{} verified for {}", algorithm.name(), file.name));
+            println!("Verified {} ({}: {})", file.name, algorithm.name(), actual);
+        }
+        None if !file.hashes.is_empty() => {
+            log_warn(&format!("No supported hash type for {}, skipping verification", file.name));
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// 按分片校验文件，对损坏的分片从镜像重新下载
+async fn verify_and_repair_pieces(
+    file: &MetalinkFile,
+    pieces: &MetalinkPieces,
+    algorithm: HashAlgorithm,
+    target: &Path,
+    total_size: u64,
+    mirrors: &[&MetalinkUrl],
+    http3: bool,
+) -> Result<()> {
+    // 没有 <size> 时文件大小只能从下载结果得知；分片超出文件时无法按分片修复
+    if !pieces.fits(total_size) {
+        return Err(anyhow!(
+            "Size mismatch for {}: {} pieces of {} bytes do not match the downloaded size {}",
+            file.name,
+            pieces.hashes.len(),
+            pieces.length,
+            total_size
+        ));
+    }
+
+    let bad_pieces = find_bad_pieces(target, pieces, algorithm, total_size).await?;
+    if bad_pieces.is_empty() {
+        log_info(&format!("All {} pieces verified for {}", pieces.hashes.len(), file.name));
+        return Ok(());
+    }
+
+    log_warn(&format!("{} corrupt piece(s) in {}, repairing", bad_pieces.len(), file.name));
+    println!("Repairing {} corrupt piece(s) in {}", bad_pieces.len(), file.name);
+
+    let client = build_client(true, 10, http3, vec![], ClientType::Download)?;
+    for index in bad_pieces {
+        let start = index as u64 * pieces.length;
+        let end = std::cmp::min(start + pieces.length, total_size);
+        let expected = &pieces.hashes[index];
+
+        let mut repaired = false;
+        for mirror in mirrors {
+            match fetch_piece(&client, &mirror.url, start, end).await {
+                Ok(data) if hashes_match(expected, &algorithm.digest(&data)) => {
+                    write_piece(target, start, &data)?;
+                    log_debug(&format!("Piece {} repaired from {}", index, mirror.url));
+                    repaired = true;
+                    break;
+                }
+                Ok(_) => log_warn(&format!("Piece {} from {} failed verification", index, mirror.url)),
+                Err(e) => log_warn(&format!("Failed to fetch piece {} from {}: {}", index, mirror.url, e)),
+            }
+        }
+
+        if !repaired {
+            return Err(anyhow!(
+                "Piece {} ({}-{}) of {} could not be repaired from any mirror",
+                index,
+                HumanBytes(start),
+                HumanBytes(end),
+                file.name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// 按分片下载：每个分片对应断点续传元数据中的一个 ChunkInfo，下载后立即校验，
+/// 通过后才写入文件并标记为完成，校验失败或出错时从下一个镜像重新获取。
+/// 续传时跳过已完成的分片。没有分片哈希或没有镜像支持 Range 时返回 false，由调用方整体下载
+async fn download_pieces(
+    file: &MetalinkFile,
+    target: &Path,
+    mirrors: &[&MetalinkUrl],
+    parallel: usize,
+    continue_download: bool,
+    http3: bool,
+) -> Result<bool> {
+    let Some(ref pieces) = file.pieces else {
+        return Ok(false);
+    };
+    let Some(algorithm) = HashAlgorithm::from_name(&pieces.hash_type) else {
+        // 不支持的分片哈希不能静默跳过：没有可用的整体哈希时直接报错
+        if file.best_hash().is_none() {
+            return Err(anyhow!(
+                "Unsupported piece hash type '{}' for {} and no supported file hash to verify against",
+                pieces.hash_type,
+                file.name
+            ));
+        }
+        log_warn(&format!(
+            "Unsupported piece hash type '{}' for {}, only the file hash will be verified",
+            pieces.hash_type, file.name
+        ));
+        eprintln!(
+            "Warning: unsupported piece hash type '{}' for {}, only the file hash will be verified",
+            pieces.hash_type, file.name
+        );
+        return Ok(false);
+    };
+
+    let client = build_client(true, 10, http3, vec![], ClientType::Download)?;
+
+    let mut ranged = Vec::new();
+    let mut remote_size = None;
+    for mirror in mirrors {
+        match client.head(&mirror.url).send().await {
+            Ok(response) if response.status().is_success() => {
+                let headers = response.headers();
+                if headers.get(ACCEPT_RANGES).is_some_and(|value| value.as_bytes() == b"bytes") {
+                    remote_size = remote_size.or_else(|| {
+                        headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse::<u64>().ok()
+                    });
+                    ranged.push(mirror.url.clone());
+                } else {
+                    log_debug(&format!("Mirror {} does not support range requests", mirror.url));
+                }
+            }
+            Ok(response) => log_debug(&format!("Mirror {} returned {} for HEAD", mirror.url, response.status())),
+            Err(e) => log_debug(&format!("HEAD request to mirror {} failed: {}", mirror.url, e)),
+        }
+    }
+
+    let Some(total_size) = file.size.or(remote_size).filter(|_| !ranged.is_empty()) else {
+        log_info(&format!("No mirror supports range requests for {}, downloading the whole file", file.name));
+        return Ok(false);
+    };
+    if !pieces.fits(total_size) {
+        return Err(anyhow!(
+            "Size mismatch for {}: {} pieces of {} bytes do not match the file size {}",
+            file.name,
+            pieces.hashes.len(),
+            pieces.length,
+            total_size
+        ));
+    }
+
+    let resume_manager = ResumeManager::new()?;
+    let key = format!("{}#pieces", mirrors[0].url);
+    let existing = if continue_download {
+        resume_manager.load_metadata(&key)?.filter(|metadata| {
+            metadata.output_path == target
+                && metadata.total_size == total_size
+                && metadata.chunks.len() == pieces.hashes.len()
+                && std::fs::metadata(target).is_ok_and(|m| m.len() == total_size)
+        })
+    } else {
+        None
+    };
+
+    let mut metadata = match existing {
+        Some(metadata) => {
+            log_info(&format!(
+                "Resuming {}: {} of {} pieces already verified",
+                file.name,
+                metadata.chunks.iter().filter(|c| c.status == ChunkStatus::Completed).count(),
+                pieces.hashes.len()
+            ));
+            metadata
+        }
+        None => {
+            let mut metadata =
+                DownloadMetadata::new(key, target.to_path_buf(), total_size, true, None, None);
+            metadata.chunks = (0..pieces.hashes.len())
+                .map(|index| {
+                    let start = index as u64 * pieces.length;
+                    ChunkInfo {
+                        index,
+                        start,
+                        end: std::cmp::min(start + pieces.length, total_size),
+                        downloaded: 0,
+                        status: ChunkStatus::Pending,
+                    }
+                })
+                .collect();
+            // 先删除再创建，避免写穿与其他路径共享的硬链接
+            let _ = std::fs::remove_file(target);
+            std::fs::File::create(target)
+                .and_then(|f| f.set_len(total_size))
+                .with_context(|| format!("Failed to create file: {}", target.display()))?;
+            metadata
+        }
+    };
+    metadata.status = DownloadStatus::InProgress;
+    resume_manager.save_metadata(&metadata)?;
+    progress::emit(ProgressEvent::Started { metadata: &metadata });
+
+    let pb = create_progress_bar(total_size, metadata.downloaded);
+    pb.set_message(format!("{} pieces", pieces.hashes.len()));
+
+    let ranged: Arc<[String]> = ranged.into();
+    let semaphore = Arc::new(Semaphore::new(parallel.max(1)));
+    let mut tasks = JoinSet::new();
+    for chunk in metadata.chunks.iter().filter(|c| c.status != ChunkStatus::Completed) {
+        let (index, start, end) = (chunk.index, chunk.start, chunk.end);
+        let expected = pieces.hashes[index].clone();
+        let (client, ranged, semaphore) = (client.clone(), Arc::clone(&ranged), Arc::clone(&semaphore));
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire().await;
+            // 按镜像优先级依次尝试，分片哈希不符时换下一个镜像
+            for url in ranged.iter() {
+                match fetch_piece(&client, url, start, end).await {
+                    Ok(data) if hashes_match(&expected, &algorithm.digest(&data)) => {
+                        log_debug(&format!("Piece {} verified from {}", index, url));
+                        return (index, Ok(data));
+                    }
+                    Ok(_) => log_warn(&format!("Piece {} from {} failed verification", index, url)),
+                    Err(e) => log_warn(&format!("Failed to fetch piece {} from {}: {}", index, url, e)),
+                }
+            }
+            let error = anyhow!("Piece {} ({}-{}) could not be verified from any mirror", index, HumanBytes(start), HumanBytes(end));
+            (index, Err(error))
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = joined.context("Piece download task failed")?;
+        let outcome = match result {
+            Ok(data) => {
+                write_piece(target, metadata.chunks[index].start, &data)?;
+                metadata.update_chunk_progress(index, data.len() as u64);
+                resume_manager.save_metadata(&metadata)?;
+                progress::emit(ProgressEvent::Checkpoint { metadata: &metadata });
+                pb.inc(data.len() as u64);
+                // 按时段限速；下载窗口关闭时已完成的分片都已记录
+                schedule::throttle(data.len()).await
+            }
+            Err(e) => {
+                metadata.set_chunk_status(index, ChunkStatus::Failed);
+                Err(e.context(format!("Failed to download {}", file.name)))
+            }
+        };
+
+        if let Err(e) = outcome {
+            tasks.abort_all();
+            if e.is::<DownloadPaused>() {
+                metadata.mark_paused();
+            } else {
+                metadata.mark_failed(&e.to_string());
+            }
+            resume_manager.save_metadata(&metadata)?;
+            pb.abandon();
+            return Err(e);
+        }
+    }
+
+    metadata.mark_completed();
+    resume_manager.save_metadata(&metadata)?;
+    pb.finish_with_message("pieces verified");
+    log_info(&format!("All {} pieces verified for {}", pieces.hashes.len(), file.name));
+    Ok(true)
+}
+
+async fn find_bad_pieces(
+    target: &Path,
+    pieces: &MetalinkPieces,
+    algorithm: HashAlgorithm,
+    total_size: u64,
+) -> Result<Vec<usize>> {
+    let path = target.to_path_buf();
+    let pieces = pieces.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)
+            .with_context(|| format!("Failed to open file for verification: {}", path.display()))?;
+        let mut bad = Vec::new();
+
+        for (index, expected) in pieces.hashes.iter().enumerate() {
+            let start = index as u64 * pieces.length;
+            let len = std::cmp::min(pieces.length, total_size - start);
+            let actual = hash_file_range(&mut file, start, len, algorithm)?;
+            if !hashes_match(expected, &actual) {
+                bad.push(index);
+            }
+        }
+
+        Ok::<Vec<usize>, anyhow::Error>(bad)
+    })
+        .await
+        .context("Piece verification task failed")?
+}
+
+async fn fetch_piece(client: &Client, url: &str, start: u64, end: u64) -> Result<Vec<u8>> {
+    let response = client
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end - 1))
+        .send()
+        .await
+        .context("Range request failed")?;
+
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(anyhow!("Mirror does not support range requests, status: {}", response.status()));
+    }
+
+    let data = response.bytes().await.context("Failed to read piece data")?;
+    Ok(data.to_vec())
+}
+
+fn write_piece(target: &Path, start: u64, data: &[u8]) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(target)
+        .with_context(|| format!("Failed to open file for repair: {}", target.display()))?;
+    file.seek(SeekFrom::Start(start))?;
+    file.write_all(data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <file name="example.iso">
+    <size>1048576</size>
+    <hash type="sha-256">ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad</hash>
+    <pieces length="524288" type="sha-256">
+      <hash>aaaa</hash>
+      <hash>bbbb</hash>
+    </pieces>
+    <url location="de" priority="2">https://de.example.com/example.iso</url>
+    <url location="us" priority="1">https://us.example.com/example.iso</url>
+  </file>
+</metalink>"#;
+
+    #[test]
+    fn test_parse_metalink() {
+        let metalink = Metalink::parse(SAMPLE).unwrap();
+        assert_eq!(metalink.files.len(), 1);
+
+        let file = &metalink.files[0];
+        assert_eq!(file.name, "example.iso");
+        assert_eq!(file.size, Some(1048576));
+        assert_eq!(file.pieces.as_ref().unwrap().hashes.len(), 2);
+        assert_eq!(file.mirrors()[0].url, "https://us.example.com/example.iso");
+        assert_eq!(file.best_hash().unwrap().0, HashAlgorithm::Sha256);
+    }
+
+    #[test]
+    fn test_unsafe_names_rejected() {
+        let xml = SAMPLE.replace("example.iso\">", "../etc/passwd\">");
+        let metalink = Metalink::parse(&xml).unwrap();
+        assert!(metalink.files[0].relative_path().is_err());
+    }
+
+    #[test]
+    fn test_pieces_fit_size() {
+        let pieces = Metalink::parse(SAMPLE).unwrap().files[0].pieces.clone().unwrap();
+        assert!(pieces.fits(1048576));
+        assert!(pieces.fits(524289));
+        // 第二个分片从文件末尾或之后开始
+        assert!(!pieces.fits(524288));
+        assert!(!pieces.fits(0));
+        assert!(!pieces.fits(1048577));
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_piece_fetched_from_next_mirror() {
+        use crate::test_server::{serve, Reply, Request};
+        use std::sync::Mutex;
+
+        const CONTENT: &[u8] = b"0123456789";
+
+        // 按 Range 返回内容；corrupt 为 true 时第二个分片（字节 4-7）被篡改
+        fn ranged(request: &Request, corrupt: bool) -> Reply {
+            let Some((start, end)) = request
+                .headers
+                .get("range")
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.split_once('-'))
+            else {
+                return Reply::new(200, CONTENT).header("Accept-Ranges", "bytes");
+            };
+            let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+            let mut body = CONTENT[start..=end].to_vec();
+            if corrupt && start == 4 {
+                body[0] = b'x';
+            }
+            Reply::new(206, body)
+                .header("Accept-Ranges", "bytes")
+                .header("Content-Range", &format!("bytes {}-{}/{}", start, end, CONTENT.len()))
+        }
+
+        let primary = serve(|request| ranged(request, true)).await;
+        let backup_ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&backup_ranges);
+        let backup = serve(move |request| {
+            if let Some(range) = request.headers.get("range") {
+                seen.lock().unwrap().push(range.clone());
+            }
+            ranged(request, false)
+        })
+        .await;
+
+        let piece_hashes: String = CONTENT
+            .chunks(4)
+            .map(|piece| format!("<hash>{}</hash>", HashAlgorithm::Sha1.digest(piece)))
+            .collect();
+        let xml = format!(
+            r#"<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <file name="digits.txt">
+    <size>10</size>
+    <hash type="sha-256">{}</hash>
+    <pieces length="4" type="sha-1">{}</pieces>
+    <url priority="1">{}/digits.txt</url>
+    <url priority="2">{}/digits.txt</url>
+  </file>
+</metalink>"#,
+            HashAlgorithm::Sha256.digest(CONTENT),
+            piece_hashes,
+            primary,
+            backup
+        );
+        let file = Metalink::parse(&xml).unwrap().files.remove(0);
+
+        let dir = std::env::temp_dir().join(format!("surf-metalink-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("digits.txt");
+        download_metalink_file(&file, &target, 2, false, 10, false).await.unwrap();
+
+        assert_eq!(std::fs::read(&target).unwrap(), CONTENT);
+        // 只有损坏的分片从备用镜像重新获取
+        assert_eq!(*backup_ranges.lock().unwrap(), vec!["bytes=4-7".to_string()]);
+
+        let resume_manager = ResumeManager::new().unwrap();
+        let key = format!("{}/digits.txt#pieces", primary);
+        let metadata = resume_manager.load_metadata(&key).unwrap().unwrap();
+        assert!(metadata.chunks.iter().all(|chunk| chunk.status == ChunkStatus::Completed));
+        resume_manager.delete_metadata(&key).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,637 @@
+use crate::core::{build_client, download_file, ClientType};
+use crate::log::{log_debug, log_info, log_warn};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+use url::Url;
+
+const STATE_FILE_NAME: &str = ".surf-mirror.json";
+const ROBOTS_USER_AGENT: &str = "surf";
+
+/// 镜像任务的配置
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    /// 最大爬取深度（起始页面为 0）
+    pub depth: usize,
+    /// 输出目录
+    pub dir: PathBuf,
+    /// 只下载匹配这些正则的 URL（为空时不限制）
+    pub include: Vec<String>,
+    /// 跳过匹配这些正则的 URL
+    pub exclude: Vec<String>,
+    /// 两次请求之间的间隔
+    pub delay: Duration,
+    /// 是否忽略 robots.txt
+    pub ignore_robots: bool,
+    pub idle_timeout: u64,
+    pub http3: bool,
+}
+
+/// 镜像状态，保存在输出目录中，用于中断后继续
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MirrorState {
+    pub start_url: String,
+    pub entries: HashMap<String, MirrorEntry>,
+}
+
+/// 单个已处理 URL 的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorEntry {
+    /// 本地文件路径（相对于输出目录）
+    pub local_path: PathBuf,
+    /// 发现该 URL 时的深度
+    pub depth: usize,
+    /// 页面中发现的链接（仅 HTML 页面）
+    pub links: Vec<String>,
+    /// 是否为 HTML 页面
+    pub is_html: bool,
+    /// 是否下载完成
+    pub completed: bool,
+    /// 链接是否已改写为本地路径
+    pub rewritten: bool,
+}
+
+impl MirrorState {
+    fn state_path(dir: &Path) -> PathBuf {
+        dir.join(STATE_FILE_NAME)
+    }
+
+    pub fn load(dir: &Path, start_url: &str) -> Result<Self> {
+        let path = Self::state_path(dir);
+        if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read mirror state")?;
+            let state: MirrorState =
+                serde_json::from_str(&content).context("Failed to parse mirror state")?;
+            if state.start_url == start_url {
+                return Ok(state);
+            }
+            log_warn("Existing mirror state belongs to a different start URL, starting over");
+        }
+
+        Ok(Self {
+            start_url: start_url.to_string(),
+            entries: HashMap::new(),
+        })
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize mirror state")?;
+        fs::write(Self::state_path(dir), content).context("Failed to write mirror state")?;
+        Ok(())
+    }
+}
+
+/// robots.txt 中适用于 surf 的规则
+#[derive(Debug, Clone, Default)]
+pub struct RobotsRules {
+    allow: Vec<String>,
+    disallow: Vec<String>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl RobotsRules {
+    /// 解析 robots.txt，优先使用针对 surf 的分组，否则使用 "*" 分组
+    pub fn parse(content: &str) -> Self {
+        let mut specific = None;
+        let mut wildcard = None;
+        let mut current_agents: Vec<String> = Vec::new();
+        let mut current = RobotsRules::default();
+        let mut in_rules = false;
+
+        let mut finish_group = |agents: &[String], rules: RobotsRules| {
+            if agents.iter().any(|a| a.eq_ignore_ascii_case(ROBOTS_USER_AGENT)) {
+                specific.get_or_insert(rules.clone());
+            }
+            if agents.iter().any(|a| a == "*") {
+                wildcard.get_or_insert(rules);
+            }
+        };
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim().to_string();
+
+            match key.as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        finish_group(&current_agents, std::mem::take(&mut current));
+                        current_agents.clear();
+                        in_rules = false;
+                    }
+                    current_agents.push(value);
+                }
+                "allow" => {
+                    in_rules = true;
+                    if !value.is_empty() {
+                        current.allow.push(value);
+                    }
+                }
+                "disallow" => {
+                    in_rules = true;
+                    if !value.is_empty() {
+                        current.disallow.push(value);
+                    }
+                }
+                "crawl-delay" => {
+                    in_rules = true;
+                    current.crawl_delay = value.parse::<f64>().ok().map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+        finish_group(&current_agents, current);
+
+        specific.or(wildcard).unwrap_or_default()
+    }
+
+    /// 判断路径是否允许访问（最长匹配规则优先，Allow 在长度相同时胜出）
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let longest = |rules: &[String]| {
+            rules
+                .iter()
+                .filter(|r| path.starts_with(r.as_str()))
+                .map(|r| r.len())
+                .max()
+        };
+
+        match (longest(&self.allow), longest(&self.disallow)) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(allow), Some(disallow)) => allow >= disallow,
+        }
+    }
+}
+
+/// 递归镜像站点
+pub async fn mirror_site(start_url: &str, options: &MirrorOptions) -> Result<()> {
+    let start = Url::parse(start_url).with_context(|| format!("Invalid URL: {}", start_url))?;
+    let prefix = scope_prefix(&start);
+    let include = compile_patterns(&options.include)?;
+    let exclude = compile_patterns(&options.exclude)?;
+
+    fs::create_dir_all(&options.dir)
+        .with_context(|| format!("Failed to create output directory: {}", options.dir.display()))?;
+
+    let client = build_client(true, 10, options.http3, vec![], ClientType::Get)?;
+    let robots = if options.ignore_robots {
+        RobotsRules::default()
+    } else {
+        fetch_robots(&client, &start).await
+    };
+    if !robots.is_allowed(start.path()) {
+        return Err(anyhow!("{} is disallowed by robots.txt (use --ignore-robots to mirror it anyway)", start));
+    }
+    let delay = std::cmp::max(options.delay, robots.crawl_delay.unwrap_or_default());
+
+    log_info(&format!(
+        "Mirroring {} (prefix: {}, depth: {}, delay: {}ms)",
+        start,
+        prefix,
+        options.depth,
+        delay.as_millis()
+    ));
+
+    let mut state = MirrorState::load(&options.dir, start.as_str())?;
+    let mut queue: VecDeque<(Url, usize)> = VecDeque::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    queue.push_back((start.clone(), 0));
+    seen.insert(start.to_string());
+
+    let mut downloaded = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+
+    while let Some((url, depth)) = queue.pop_front() {
+        let key = url.to_string();
+
+        let links = match state.entries.get(&key).filter(|e| e.completed) {
+            Some(entry) => {
+                log_debug(&format!("Already mirrored: {}", key));
+                skipped += 1;
+                entry.links.clone()
+            }
+            None => {
+                if downloaded + failed > 0 && !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+
+                let relative = local_path_for(&url);
+                let target = options.dir.join(&relative);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                println!("[depth {}] {}", depth, url);
+                if let Err(e) = download_file(url.as_str(), &target, 1, true, options.idle_timeout, options.http3, false).await {
+                    log_warn(&format!("Failed to mirror {}: {}", url, e));
+                    eprintln!("Failed to mirror {}: {}", url, e);
+                    failed += 1;
+                    continue;
+                }
+                downloaded += 1;
+
+                let is_html = looks_like_html(&target);
+                let links = if is_html {
+                    extract_links(&read_lossy(&target)?, &url)
+                        .into_iter()
+                        .map(|u| u.to_string())
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+
+                state.entries.insert(
+                    key.clone(),
+                    MirrorEntry {
+                        local_path: relative,
+                        depth,
+                        links: links.clone(),
+                        is_html,
+                        completed: true,
+                        rewritten: false,
+                    },
+                );
+                state.save(&options.dir)?;
+                links
+            }
+        };
+
+        if depth >= options.depth {
+            continue;
+        }
+
+        for link in links {
+            let Ok(link_url) = Url::parse(&link) else {
+                continue;
+            };
+            let link_key = link_url.to_string();
+            if seen.contains(&link_key) {
+                continue;
+            }
+            if !link_key.starts_with(&prefix) {
+                continue;
+            }
+            if !include.is_empty() && !include.iter().any(|r| r.is_match(&link_key)) {
+                continue;
+            }
+            if exclude.iter().any(|r| r.is_match(&link_key)) {
+                continue;
+            }
+            if !robots.is_allowed(link_url.path()) {
+                log_debug(&format!("Disallowed by robots.txt: {}", link_key));
+                continue;
+            }
+
+            seen.insert(link_key);
+            queue.push_back((link_url, depth + 1));
+        }
+    }
+
+    // 新下载的文件可能被之前改写过的页面引用，需要重新改写
+    if downloaded > 0 {
+        state.entries.values_mut().for_each(|e| e.rewritten = false);
+    }
+    let rewritten = rewrite_all(&mut state, &options.dir)?;
+    state.save(&options.dir)?;
+
+    println!(
+        "Mirror complete: {} downloaded, {} already present, {} failed, {} page(s) rewritten -> {}",
+        downloaded,
+        skipped,
+        failed,
+        rewritten,
+        options.dir.display()
+    );
+    log_info(&format!(
+        "Mirror completed - downloaded: {}, skipped: {}, failed: {}",
+        downloaded, skipped, failed
+    ));
+
+    Ok(())
+}
+
+/// 起始 URL 所在目录，作为爬取范围
+fn scope_prefix(url: &Url) -> String {
+    let mut prefix = url.clone();
+    prefix.set_query(None);
+    prefix.set_fragment(None);
+    let s = prefix.to_string();
+    match s.rfind('/') {
+        Some(pos) if pos >= prefix.origin().ascii_serialization().len() => s[..=pos].to_string(),
+        _ => format!("{}/", s),
+    }
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|p| Regex::new(p).map_err(|e| anyhow!("Invalid pattern '{}': {}", p, e)))
+        .collect()
+}
+
+async fn fetch_robots(client: &Client, start: &Url) -> RobotsRules {
+    let Ok(robots_url) = start.join("/robots.txt") else {
+        return RobotsRules::default();
+    };
+
+    match client.get(robots_url.as_str()).send().await {
+        Ok(response) if response.status().is_success() => match response.text().await {
+            Ok(content) => {
+                log_info(&format!("Loaded robots.txt from {}", robots_url));
+                RobotsRules::parse(&content)
+            }
+            Err(_) => RobotsRules::default(),
+        },
+        _ => {
+            log_debug("No robots.txt found, crawling without restrictions");
+            RobotsRules::default()
+        }
+    }
+}
+
+fn link_regex() -> Regex {
+    Regex::new(r#"(?i)(\b(?:href|src)\s*=\s*)("[^"]*"|'[^']*'|[^\s>"']+)"#).expect("Invalid link regex")
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim_matches(|c| c == '"' || c == '\'')
+}
+
+fn is_skippable_link(link: &str) -> bool {
+    let lower = link.trim().to_ascii_lowercase();
+    lower.is_empty()
+        || lower.starts_with('#')
+        || lower.starts_with("javascript:")
+        || lower.starts_with("mailto:")
+        || lower.starts_with("data:")
+        || lower.starts_with("tel:")
+}
+
+/// 提取 HTML 中 href/src 指向的绝对 URL（去掉片段）
+pub fn extract_links(html: &str, base: &Url) -> Vec<Url> {
+    let mut links = Vec::new();
+    for caps in link_regex().captures_iter(html) {
+        let raw = unquote(&caps[2]);
+        if is_skippable_link(raw) {
+            continue;
+        }
+        if let Ok(mut url) = base.join(raw.trim()) {
+            if url.scheme() != "http" && url.scheme() != "https" {
+                continue;
+            }
+            url.set_fragment(None);
+            if !links.contains(&url) {
+                links.push(url);
+            }
+        }
+    }
+    links
+}
+
+/// 将 URL 映射为输出目录下的相对路径：host/path，目录映射为 index.html。
+/// 没有扩展名的路径也当作目录（/docs 映射为 docs/index.html），
+/// 否则会与 /docs/intro 需要的 docs 目录冲突
+pub fn local_path_for(url: &Url) -> PathBuf {
+    let mut path = PathBuf::new();
+    let host = match url.port() {
+        Some(port) => format!("{}_{}", url.host_str().unwrap_or("unknown"), port),
+        None => url.host_str().unwrap_or("unknown").to_string(),
+    };
+    path.push(host);
+
+    let segments: Vec<&str> = url.path().split('/').filter(|s| !s.is_empty() && *s != "." && *s != "..").collect();
+    for segment in &segments {
+        path.push(segment);
+    }
+    let is_directory = match segments.last() {
+        Some(last) => url.path().ends_with('/') || !last.contains('.'),
+        None => true,
+    };
+    if is_directory {
+        path.push("index.html");
+    }
+
+    if let Some(query) = url.query() {
+        let hash = crate::checksum::HashAlgorithm::Sha256.digest(query.as_bytes());
+        let file_name = path.file_name().and_then(|f| f.to_str()).unwrap_or("index.html").to_string();
+        let new_name = match file_name.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}_{}.{}", stem, &hash[..8], ext),
+            None => format!("{}_{}", file_name, &hash[..8]),
+        };
+        path.set_file_name(new_name);
+    }
+
+    path
+}
+
+fn looks_like_html(path: &Path) -> bool {
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        let ext = ext.to_ascii_lowercase();
+        if ext == "html" || ext == "htm" || ext == "xhtml" {
+            return true;
+        }
+    }
+
+    let mut head = [0u8; 1024];
+    let read = fs::File::open(path).and_then(|mut f| f.read(&mut head)).unwrap_or(0);
+    let head = String::from_utf8_lossy(&head[..read]).to_ascii_lowercase();
+    head.contains("<!doctype html") || head.contains("<html")
+}
+
+fn read_lossy(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// 计算从 from_dir 到 to 的相对路径
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to_components: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to_components).take_while(|(a, b)| a == b).count();
+
+    let mut result = PathBuf::new();
+    for _ in common..from.len() {
+        result.push("..");
+    }
+    for component in &to_components[common..] {
+        result.push(component);
+    }
+    result
+}
+
+/// 改写所有已下载 HTML 页面中的链接，使其指向本地文件
+fn rewrite_all(state: &mut MirrorState, dir: &Path) -> Result<usize> {
+    let local_paths: HashMap<String, PathBuf> = state
+        .entries
+        .iter()
+        .filter(|(_, e)| e.completed)
+        .map(|(url, e)| (url.clone(), e.local_path.clone()))
+        .collect();
+
+    let mut rewritten = 0;
+    for (url, entry) in state.entries.iter_mut() {
+        if !entry.completed || !entry.is_html || entry.rewritten {
+            continue;
+        }
+        let Ok(base) = Url::parse(url) else {
+            continue;
+        };
+
+        let path = dir.join(&entry.local_path);
+        let html = read_lossy(&path)?;
+        let page_dir = entry.local_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let updated = rewrite_links(&html, &base, &page_dir, &local_paths);
+
+        fs::write(&path, updated).with_context(|| format!("Failed to rewrite {}", path.display()))?;
+        entry.rewritten = true;
+        rewritten += 1;
+    }
+
+    Ok(rewritten)
+}
+
+fn rewrite_links(html: &str, base: &Url, page_dir: &Path, local_paths: &HashMap<String, PathBuf>) -> String {
+    link_regex()
+        .replace_all(html, |caps: &regex::Captures| {
+            let original = caps[0].to_string();
+            let raw = unquote(&caps[2]);
+            if is_skippable_link(raw) {
+                return original;
+            }
+
+            let Ok(mut url) = base.join(raw.trim()) else {
+                return original;
+            };
+            let fragment = url.fragment().map(|f| f.to_string());
+            url.set_fragment(None);
+
+            match local_paths.get(url.as_str()) {
+                Some(local) => {
+                    let mut target = relative_path(page_dir, local).to_string_lossy().replace('\\', "/");
+                    if let Some(fragment) = fragment {
+                        target.push('#');
+                        target.push_str(&fragment);
+                    }
+                    format!("{}\"{}\"", &caps[1], target)
+                }
+                None => original,
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots_rules() {
+        let robots = RobotsRules::parse(
+            "User-agent: *\nDisallow: /private/\nAllow: /private/public/\nCrawl-delay: 2\n\nUser-agent: other\nDisallow: /",
+        );
+        assert!(robots.is_allowed("/docs/index.html"));
+        assert!(!robots.is_allowed("/private/secret.html"));
+        assert!(robots.is_allowed("/private/public/page.html"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_local_paths_and_rewrite() {
+        let base = Url::parse("https://example.com/docs/guide/intro.html").unwrap();
+        assert_eq!(local_path_for(&base), PathBuf::from("example.com/docs/guide/intro.html"));
+        assert_eq!(
+            local_path_for(&Url::parse("https://example.com/docs/").unwrap()),
+            PathBuf::from("example.com/docs/index.html")
+        );
+        assert_eq!(
+            local_path_for(&Url::parse("https://example.com/docs").unwrap()),
+            PathBuf::from("example.com/docs/index.html")
+        );
+
+        let mut local_paths = HashMap::new();
+        local_paths.insert(
+            "https://example.com/docs/style.css".to_string(),
+            PathBuf::from("example.com/docs/style.css"),
+        );
+        let html = r#"<link href="/docs/style.css#top"><a href='https://other.com/x'>x</a>"#;
+        let rewritten = rewrite_links(html, &base, Path::new("example.com/docs/guide"), &local_paths);
+        assert_eq!(rewritten, r#"<link href="../style.css#top"><a href='https://other.com/x'>x</a>"#);
+        assert_eq!(extract_links(html, &base).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mirror_site_end_to_end() {
+        use crate::test_server::{serve, Reply};
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        let base = serve(move |request| {
+            seen.lock().unwrap().push(request.path.clone());
+            let html = |body: &str| Reply::new(200, format!("<html><body>{}</body></html>", body));
+            match request.path.as_str() {
+                "/robots.txt" => Reply::new(200, "User-agent: *\nDisallow: /site/private/\n"),
+                "/site/" => html(
+                    r#"<a href="docs">Docs</a><a href="docs/intro">Intro</a><a href="private/secret.html">Secret</a><link href="style.css">"#,
+                ),
+                "/site/docs" => html(r#"<a href="/site/docs/intro#top">Intro</a>"#),
+                "/site/docs/intro" => html(r#"<a href="../">Home</a>"#),
+                "/site/style.css" => Reply::new(200, "body {}"),
+                _ => Reply::new(404, "not found"),
+            }
+        })
+        .await;
+
+        let dir = std::env::temp_dir().join(format!("surf-mirror-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let options = MirrorOptions {
+            depth: 2,
+            dir: dir.clone(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            delay: Duration::ZERO,
+            ignore_robots: false,
+            idle_timeout: 10,
+            http3: false,
+        };
+        mirror_site(&format!("{}/site/", base), &options).await.unwrap();
+
+        let host = dir.join(local_path_for(&Url::parse(&base).unwrap()).parent().unwrap());
+        let site = host.join("site");
+        let index = fs::read_to_string(site.join("index.html")).unwrap();
+        assert!(index.contains(r#"href="docs/index.html""#));
+        assert!(index.contains(r#"href="docs/intro/index.html""#));
+        assert!(index.contains(r#"href="style.css""#));
+        // robots.txt 禁止的页面不下载，链接保持原样
+        assert!(index.contains(r#"href="private/secret.html""#));
+        assert!(!requests.lock().unwrap().iter().any(|p| p.starts_with("/site/private/")));
+        let docs = fs::read_to_string(site.join("docs/index.html")).unwrap();
+        assert!(docs.contains(r#"href="intro/index.html#top""#));
+        let intro = fs::read_to_string(site.join("docs/intro/index.html")).unwrap();
+        assert!(intro.contains(r#"href="../../index.html""#));
+        assert_eq!(fs::read_to_string(site.join("style.css")).unwrap(), "body {}");
+
+        // 再次运行时从状态文件继续，不重新请求已完成的页面
+        requests.lock().unwrap().clear();
+        mirror_site(&format!("{}/site/", base), &options).await.unwrap();
+        assert_eq!(*requests.lock().unwrap(), vec!["/robots.txt".to_string()]);
+
+        // 起始 URL 本身被 robots.txt 禁止时直接报错
+        let error = mirror_site(&format!("{}/site/private/secret.html", base), &options).await.unwrap_err();
+        assert!(error.to_string().contains("robots.txt"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,574 @@
+use crate::checksum::{ExpectedChecksum, HashAlgorithm};
+use crate::core::{build_client, download_file_with_client, ClientType};
+use crate::log::{log_debug, log_info};
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header, Client, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const DOCKER_HUB_REGISTRY: &str = "registry-1.docker.io";
+
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// 镜像引用，如 ghcr.io/org/app:1.0、alpine、localhost:5000/app@sha256:...
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    /// 标签或摘要
+    pub reference: String,
+}
+
+impl ImageReference {
+    pub fn parse(value: &str) -> Result<Self> {
+        let (name, reference) = match value.split_once('@') {
+            Some((name, digest)) => (name, digest.to_string()),
+            None => match value.rsplit_once(':') {
+                // 冒号后面含 '/' 的是端口号，不是标签
+                Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+                _ => (value, "latest".to_string()),
+            },
+        };
+        if name.is_empty() || reference.is_empty() {
+            return Err(anyhow!("Invalid image reference: {}", value));
+        }
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if host.contains('.') || host.contains(':') || host == "localhost" => {
+                let host = if host == "docker.io" { DOCKER_HUB_REGISTRY } else { host };
+                (host.to_string(), rest.to_string())
+            }
+            _ => (DOCKER_HUB_REGISTRY.to_string(), name.to_string()),
+        };
+        // Docker Hub 的官方镜像位于 library/ 下
+        let repository = if registry == DOCKER_HUB_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+
+        Ok(Self { registry, repository, reference })
+    }
+
+    fn is_digest(&self) -> bool {
+        self.reference.contains(':')
+    }
+}
+
+/// 目标平台，如 linux/amd64、linux/arm64/v8
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut parts = value.split('/');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(os), Some(architecture), variant, None) if !os.is_empty() && !architecture.is_empty() => Ok(Self {
+                os: os.to_string(),
+                architecture: architecture.to_string(),
+                variant: variant.map(|v| v.to_string()),
+            }),
+            _ => Err(anyhow!("Invalid platform '{}', expected os/arch[/variant]", value)),
+        }
+    }
+
+    /// 当前机器对应的 Linux 平台
+    pub fn host() -> Self {
+        let architecture = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "x86" => "386",
+            "aarch64" => "arm64",
+            "powerpc64" => "ppc64le",
+            other => other,
+        };
+        Self {
+            os: "linux".to_string(),
+            architecture: architecture.to_string(),
+            variant: None,
+        }
+    }
+
+    /// 未指定 variant 时匹配任意 variant
+    fn matches(&self, other: &Platform) -> bool {
+        self.os == other.os
+            && self.architecture == other.architecture
+            && (self.variant.is_none() || self.variant == other.variant)
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Descriptor {
+    #[serde(rename = "mediaType", default)]
+    media_type: String,
+    digest: String,
+    size: u64,
+    #[serde(default)]
+    platform: Option<Platform>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(rename = "mediaType", default)]
+    media_type: Option<String>,
+    #[serde(default)]
+    config: Option<Descriptor>,
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+    /// 仅多平台索引才有
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+}
+
+/// oci pull 的设置
+pub struct OciPullOptions {
+    pub dir: PathBuf,
+    pub platform: Option<Platform>,
+    pub plain_http: bool,
+    pub parallel: usize,
+    pub idle_timeout: u64,
+    pub http3: bool,
+}
+
+// Registry v2 API 客户端，按 WWW-Authenticate 质询获取 bearer token
+struct RegistryClient {
+    client: Client,
+    base_url: String,
+    repository: String,
+    token: Option<String>,
+    http3: bool,
+}
+
+impl RegistryClient {
+    fn new(image: &ImageReference, plain_http: bool, http3: bool) -> Result<Self> {
+        let scheme = if plain_http { "http" } else { "https" };
+        Ok(Self {
+            client: build_client(true, 10, http3, vec![], ClientType::Get)?,
+            base_url: format!("{}://{}", scheme, image.registry),
+            repository: image.repository.clone(),
+            token: None,
+            http3,
+        })
+    }
+
+    async fn get(&mut self, path: &str, accept: &str) -> Result<Response> {
+        let url = format!("{}/v2/{}/{}", self.base_url, self.repository, path);
+        let mut response = self.send(&url, accept).await?;
+
+        if response.status() == StatusCode::UNAUTHORIZED && self.token.is_none() {
+            let challenge = response
+                .headers()
+                .get(header::WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| anyhow!("Registry requires authentication but sent no WWW-Authenticate challenge"))?
+                .to_string();
+            self.authenticate(&challenge).await?;
+            response = self.send(&url, accept).await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(anyhow!("Registry request {} failed: HTTP {}", url, response.status()));
+        }
+        Ok(response)
+    }
+
+    async fn send(&self, url: &str, accept: &str) -> Result<Response> {
+        log_debug(&format!("Registry GET {}", url));
+        let mut request = self.client.get(url).header(header::ACCEPT, accept);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.with_context(|| format!("Failed to reach registry: {}", url))
+    }
+
+    async fn authenticate(&mut self, challenge: &str) -> Result<()> {
+        let (scheme, params) = parse_challenge(challenge)?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return Err(anyhow!("Unsupported registry authentication scheme: {}", scheme));
+        }
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| anyhow!("Bearer challenge without realm: {}", challenge))?;
+
+        let mut query = vec![];
+        if let Some(service) = params.get("service") {
+            query.push(("service", service.clone()));
+        }
+        let scope = params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.repository));
+        query.push(("scope", scope));
+
+        log_info(&format!("Requesting anonymous registry token from {}", realm));
+        let response = self
+            .client
+            .get(realm)
+            .query(&query)
+            .send()
+            .await
+            .with_context(|| format!("Failed to request registry token from {}", realm))?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Registry token request failed: HTTP {}", response.status()));
+        }
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let body: TokenResponse = response.json().await.context("Invalid registry token response")?;
+        self.token = Some(
+            body.token
+                .or(body.access_token)
+                .ok_or_else(|| anyhow!("Registry token response contains no token"))?,
+        );
+        Ok(())
+    }
+
+    /// 获取 manifest 并校验摘要，返回（原始内容，媒体类型，摘要）
+    async fn fetch_manifest(&mut self, reference: &str) -> Result<(Vec<u8>, String, String)> {
+        let accept = [OCI_INDEX, DOCKER_MANIFEST_LIST, OCI_MANIFEST, DOCKER_MANIFEST].join(", ");
+        let response = self.get(&format!("manifests/{}", reference), &accept).await?;
+
+        let media_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or(v).trim().to_string());
+        let header_digest = response
+            .headers()
+            .get("docker-content-digest")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let body = response.bytes().await?.to_vec();
+
+        let digest = format!("sha256:{}", HashAlgorithm::Sha256.digest(&body));
+        let expected = if reference.contains(':') { Some(reference.to_string()) } else { header_digest };
+        if let Some(expected) = expected.filter(|d| d.starts_with("sha256:")) {
+            if expected != digest {
+                return Err(anyhow!("Manifest digest mismatch: expected {}, got {}", expected, digest));
+            }
+        }
+
+        let manifest: Manifest = serde_json::from_slice(&body).context("Invalid manifest")?;
+        let media_type = manifest.media_type.or(media_type).unwrap_or_else(|| OCI_MANIFEST.to_string());
+        Ok((body, media_type, digest))
+    }
+
+    /// 通过可续传的下载路径获取 blob 并校验摘要
+    async fn fetch_blob(&self, descriptor: &Descriptor, dir: &Path, options: &OciPullOptions) -> Result<PathBuf> {
+        let checksum = ExpectedChecksum::parse(&descriptor.digest)
+            .with_context(|| format!("Unsupported blob digest: {}", descriptor.digest))?;
+        let path = blob_path(dir, &descriptor.digest)?;
+
+        if path.exists() && checksum.verify(&path).is_ok() {
+            log_info(&format!("Blob {} already present", descriptor.digest));
+            return Ok(path);
+        }
+
+        // 先下载到 .part，校验通过后再放到最终位置，布局中不会出现不完整的 blob
+        let part = path.with_extension("part");
+        let headers = match &self.token {
+            Some(token) => vec![format!("Authorization: Bearer {}", token)],
+            None => vec![],
+        };
+        let client = build_client(true, 10, self.http3, headers, ClientType::Download)?;
+        let url = format!("{}/v2/{}/blobs/{}", self.base_url, self.repository, descriptor.digest);
+        download_file_with_client(&client, &url, &part, options.parallel, true, options.idle_timeout, false).await?;
+
+        let size = fs::metadata(&part)?.len();
+        if size != descriptor.size {
+            fs::remove_file(&part)?;
+            return Err(anyhow!("Blob {} size mismatch: expected {}, got {}", descriptor.digest, descriptor.size, size));
+        }
+        if let Err(e) = checksum.verify(&part) {
+            fs::remove_file(&part)?;
+            return Err(e.context(format!("Blob {} failed digest verification", descriptor.digest)));
+        }
+        fs::rename(&part, &path)?;
+        Ok(path)
+    }
+}
+
+/// 解析 WWW-Authenticate 质询：Bearer realm="...",service="...",scope="..."
+fn parse_challenge(value: &str) -> Result<(String, HashMap<String, String>)> {
+    let (scheme, rest) = value.trim().split_once(' ').unwrap_or((value.trim(), ""));
+    let mut params = HashMap::new();
+    let mut chars = rest.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=')).collect();
+        if key.is_empty() {
+            break;
+        }
+        if chars.next() != Some('=') {
+            return Err(anyhow!("Malformed WWW-Authenticate header: {}", value));
+        }
+        let mut param = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            // 引号内可以有逗号，如 scope="repository:app:pull,push"
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => param.extend(chars.next()),
+                    c => param.push(c),
+                }
+            }
+        } else {
+            param.extend(std::iter::from_fn(|| chars.next_if(|c| *c != ',')));
+        }
+        params.insert(key.trim().to_ascii_lowercase(), param.trim().to_string());
+    }
+
+    Ok((scheme.to_string(), params))
+}
+
+fn blob_path(dir: &Path, digest: &str) -> Result<PathBuf> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .filter(|(_, hex)| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| anyhow!("Invalid digest: {}", digest))?;
+    let dir = dir.join("blobs").join(algorithm);
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(hex))
+}
+
+// 把 manifest 登记到 index.json；同一目录可以存放多个标签，同名标签会被替换
+fn write_index(dir: &Path, descriptor: serde_json::Value) -> Result<()> {
+    const REF_NAME: &str = "/annotations/org.opencontainers.image.ref.name";
+    let path = dir.join("index.json");
+    let mut manifests: Vec<serde_json::Value> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|index| index.get("manifests").and_then(|m| m.as_array()).cloned())
+        .unwrap_or_default();
+
+    manifests.retain(|existing| match descriptor.pointer(REF_NAME) {
+        Some(name) => existing.pointer(REF_NAME) != Some(name),
+        None => existing.get("digest") != descriptor.get("digest"),
+    });
+    manifests.push(descriptor);
+
+    fs::write(
+        path,
+        serde_json::to_string_pretty(&json!({ "schemaVersion": 2, "manifests": manifests }))?,
+    )?;
+    Ok(())
+}
+
+/// 拉取镜像到 OCI image layout 目录（oci-layout、index.json、blobs/），返回 manifest 摘要
+pub async fn pull_image(reference: &str, options: &OciPullOptions) -> Result<String> {
+    let image = ImageReference::parse(reference)?;
+    log_info(&format!(
+        "Pulling {}/{} ({}) from {}",
+        image.registry, image.repository, image.reference, image.registry
+    ));
+    let mut registry = RegistryClient::new(&image, options.plain_http, options.http3)?;
+
+    let (mut body, mut media_type, mut digest) = registry.fetch_manifest(&image.reference).await?;
+    let mut manifest: Manifest = serde_json::from_slice(&body)?;
+
+    // 多平台索引：按平台选出具体的 manifest
+    if media_type == OCI_INDEX || media_type == DOCKER_MANIFEST_LIST || !manifest.manifests.is_empty() {
+        let platform = options.platform.clone().unwrap_or_else(Platform::host);
+        let available: Vec<String> = manifest
+            .manifests
+            .iter()
+            .filter_map(|m| m.platform.as_ref().map(|p| p.to_string()))
+            .collect();
+        let selected = manifest
+            .manifests
+            .iter()
+            .find(|m| m.platform.as_ref().is_some_and(|p| platform.matches(p)))
+            .ok_or_else(|| anyhow!("No image for platform {} (available: {})", platform, available.join(", ")))?
+            .clone();
+        println!("Selected platform {} ({})", platform, selected.digest);
+
+        (body, media_type, digest) = registry.fetch_manifest(&selected.digest).await?;
+        if digest != selected.digest {
+            return Err(anyhow!("Manifest digest mismatch: expected {}, got {}", selected.digest, digest));
+        }
+        manifest = serde_json::from_slice(&body)?;
+    }
+
+    let config = manifest
+        .config
+        .as_ref()
+        .ok_or_else(|| anyhow!("Manifest {} has no config (unsupported media type {})", digest, media_type))?;
+
+    fs::create_dir_all(&options.dir)
+        .with_context(|| format!("Failed to create directory: {}", options.dir.display()))?;
+    fs::write(blob_path(&options.dir, &digest)?, &body)?;
+
+    registry.fetch_blob(config, &options.dir, options).await?;
+    let total = manifest.layers.len();
+    for (index, layer) in manifest.layers.iter().enumerate() {
+        println!(
+            "Layer {}/{}: {} ({}, {})",
+            index + 1,
+            total,
+            layer.digest,
+            indicatif::HumanBytes(layer.size),
+            layer.media_type
+        );
+        registry.fetch_blob(layer, &options.dir, options).await?;
+    }
+
+    let mut descriptor = json!({
+        "mediaType": media_type,
+        "digest": digest,
+        "size": body.len(),
+    });
+    if !image.is_digest() {
+        descriptor["annotations"] = json!({ "org.opencontainers.image.ref.name": image.reference });
+    }
+    fs::write(
+        options.dir.join("oci-layout"),
+        serde_json::to_string(&json!({ "imageLayoutVersion": "1.0.0" }))?,
+    )?;
+    write_index(&options.dir, descriptor)?;
+
+    log_info(&format!("Pulled {} ({}) into {}", reference, digest, options.dir.display()));
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_reference() {
+        let image = ImageReference::parse("alpine").unwrap();
+        assert_eq!(image.registry, DOCKER_HUB_REGISTRY);
+        assert_eq!(image.repository, "library/alpine");
+        assert_eq!(image.reference, "latest");
+
+        let image = ImageReference::parse("localhost:5000/team/app:1.2").unwrap();
+        assert_eq!(image.registry, "localhost:5000");
+        assert_eq!(image.repository, "team/app");
+        assert_eq!(image.reference, "1.2");
+
+        let image = ImageReference::parse("ghcr.io/org/app@sha256:abcd").unwrap();
+        assert_eq!(image.registry, "ghcr.io");
+        assert_eq!(image.reference, "sha256:abcd");
+        assert!(image.is_digest());
+
+        assert!(Platform::parse("linux").is_err());
+        assert!(Platform::parse("linux/arm64").unwrap().matches(&Platform::parse("linux/arm64/v8").unwrap()));
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let (scheme, params) = parse_challenge(
+            r#"Bearer realm="https://auth.example.com/token",service="registry.example.com",scope="repository:app:pull,push""#,
+        )
+        .unwrap();
+        assert_eq!(scheme, "Bearer");
+        assert_eq!(params["realm"], "https://auth.example.com/token");
+        assert_eq!(params["service"], "registry.example.com");
+        assert_eq!(params["scope"], "repository:app:pull,push");
+    }
+
+    #[tokio::test]
+    async fn test_pull_from_local_registry() {
+        use crate::test_server::{serve, Reply};
+
+        let sha256 = |data: &[u8]| format!("sha256:{}", HashAlgorithm::Sha256.digest(data));
+        let config = br#"{"architecture":"amd64","os":"linux"}"#.to_vec();
+        let layer = b"layer contents".to_vec();
+        let manifest = |layer_digest: &str| {
+            json!({
+                "schemaVersion": 2,
+                "mediaType": OCI_MANIFEST,
+                "config": {
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "digest": sha256(&config),
+                    "size": config.len(),
+                },
+                "layers": [{
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar",
+                    "digest": layer_digest,
+                    "size": layer.len(),
+                }],
+            })
+            .to_string()
+        };
+        // "bad" 标签引用的层大小相同，但内容与摘要不符
+        let bad_digest = sha256(b"other contents");
+        let manifests = HashMap::from([
+            ("1.0".to_string(), manifest(&sha256(&layer))),
+            ("bad".to_string(), manifest(&bad_digest)),
+        ]);
+        let expected_digest = sha256(manifests["1.0"].as_bytes());
+        let blobs = HashMap::from([
+            (sha256(&config), config.clone()),
+            (sha256(&layer), layer.clone()),
+            (bad_digest.clone(), b"layer CONTENTS".to_vec()),
+        ]);
+
+        let base = serve(move |request| {
+            let host = request.headers.get("host").cloned().unwrap_or_default();
+            if request.path.starts_with("/token?") {
+                return Reply::new(200, r#"{"token":"secret"}"#);
+            }
+            if request.headers.get("authorization").map(String::as_str) != Some("Bearer secret") {
+                let challenge = format!(r#"Bearer realm="http://{}/token",service="test""#, host);
+                return Reply::new(401, "").header("WWW-Authenticate", &challenge);
+            }
+            let path = request.path.strip_prefix("/v2/app/").unwrap_or_default();
+            if let Some(tag) = path.strip_prefix("manifests/") {
+                return match manifests.get(tag) {
+                    Some(body) => Reply::new(200, body.as_str())
+                        .header("Content-Type", OCI_MANIFEST)
+                        .header("Docker-Content-Digest", &sha256(body.as_bytes())),
+                    None => Reply::new(404, ""),
+                };
+            }
+            match path.strip_prefix("blobs/").and_then(|digest| blobs.get(digest)) {
+                Some(blob) => Reply::new(200, blob.clone()),
+                None => Reply::new(404, ""),
+            }
+        })
+        .await;
+        let registry = base.trim_start_matches("http://");
+
+        let dir = std::env::temp_dir().join(format!("surf-oci-test-{}", std::process::id()));
+        let options =
+            OciPullOptions { dir: dir.clone(), platform: None, plain_http: true, parallel: 1, idle_timeout: 10, http3: false };
+        let digest = pull_image(&format!("{}/app:1.0", registry), &options).await.unwrap();
+        assert_eq!(digest, expected_digest);
+        assert_eq!(fs::read(blob_path(&dir, &sha256(&config)).unwrap()).unwrap(), config);
+        assert_eq!(fs::read(blob_path(&dir, &sha256(&layer)).unwrap()).unwrap(), layer);
+        let index = fs::read_to_string(dir.join("index.json")).unwrap();
+        assert!(index.contains(&digest) && index.contains("\"1.0\""));
+
+        let error = pull_image(&format!("{}/app:bad", registry), &options).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("failed digest verification"), "{:#}", error);
+        assert!(!blob_path(&dir, &bad_digest).unwrap().exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+use crate::resume::{ChunkInfo, DownloadMetadata};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::OnceCell;
+
+static PROGRESS: OnceCell<Arc<ProgressReporter>> = OnceCell::const_new();
+/// 进程启动时已打开的文件描述符，--progress-fd 只能是其中之一
+static INHERITED_FDS: OnceLock<Vec<i32>> = OnceLock::new();
+
+/// 两次 progress 事件之间的最小间隔
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 进度输出格式
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ProgressFormat {
+    /// 终端进度条
+    Human,
+    /// JSON Lines 事件流
+    Json,
+}
+
+/// 进度事件，字段名与 DownloadMetadata 持久化的字段保持一致
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    Started {
+        #[serde(flatten)]
+        metadata: &'a DownloadMetadata,
+    },
+    Progress {
+        url: &'a str,
+        url_hash: &'a str,
+        total_size: u64,
+        downloaded: u64,
+        chunk: &'a ChunkInfo,
+        /// 当前分片速度（字节/秒）
+        speed: f64,
+    },
+    Retry {
+        url: &'a str,
+        attempt: usize,
+        reason: String,
+    },
+    Checkpoint {
+        #[serde(flatten)]
+        metadata: &'a DownloadMetadata,
+    },
+    Completed {
+        #[serde(flatten)]
+        metadata: &'a DownloadMetadata,
+        elapsed_ms: u64,
+        /// 平均速度（字节/秒）
+        average_speed: f64,
+    },
+    Failed {
+        #[serde(flatten)]
+        metadata: &'a DownloadMetadata,
+        error: String,
+    },
+    #[serde(rename = "started")]
+    BenchStarted {
+        url: &'a str,
+        /// 对比压测（--compare/--protocols）中这一方的标签
+        #[serde(skip_serializing_if = "Option::is_none")]
+        variant: Option<&'a str>,
+        requests: usize,
+        concurrency: usize,
+        http3: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+        /// 目标速率（请求/秒），仅恒定速率模式
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rate: Option<f64>,
+    },
+    #[serde(rename = "progress")]
+    BenchProgress {
+        url: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        variant: Option<&'a str>,
+        completed: usize,
+        successful: u32,
+        failed: u32,
+        elapsed_ms: u64,
+        requests_per_second: f64,
+    },
+    #[serde(rename = "completed")]
+    BenchCompleted {
+        url: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        variant: Option<&'a str>,
+        completed: usize,
+        successful: u32,
+        failed: u32,
+        elapsed_ms: u64,
+        requests_per_second: f64,
+    },
+    #[serde(rename = "started")]
+    StreamStarted {
+        url: &'a str,
+        output_path: &'a Path,
+        segments: usize,
+        /// 续传时已存在的分段数
+        completed_segments: usize,
+    },
+    /// 每下载完一个分段输出一次
+    #[serde(rename = "progress")]
+    StreamProgress {
+        url: &'a str,
+        segment: usize,
+        bytes: u64,
+        completed_segments: usize,
+        segments: usize,
+    },
+    #[serde(rename = "completed")]
+    StreamCompleted {
+        url: &'a str,
+        output_path: &'a Path,
+        segments: usize,
+        total_size: u64,
+        elapsed_ms: u64,
+        /// 平均速度（字节/秒）
+        average_speed: f64,
+    },
+    #[serde(rename = "failed")]
+    StreamFailed {
+        url: &'a str,
+        segment: usize,
+        error: String,
+    },
+}
+
+/// JSON Lines 进度输出器
+pub struct ProgressReporter {
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl ProgressReporter {
+    fn emit(&self, source: &str, event: &ProgressEvent) {
+        let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(event) else {
+            return;
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let mut line = serde_json::Map::new();
+        line.insert("timestamp".to_string(), timestamp.into());
+        line.insert("source".to_string(), source.into());
+        line.extend(fields);
+
+        if let Ok(mut sink) = self.sink.lock() {
+            let _ = writeln!(sink, "{}", serde_json::Value::Object(line));
+            let _ = sink.flush();
+        }
+    }
+}
+
+/// 初始化进度输出；human 模式下不做任何事
+pub fn init_progress(format: ProgressFormat, fd: Option<i32>) -> Result<()> {
+    if format == ProgressFormat::Human {
+        // 人类可读的进度不写到描述符，静默忽略会让调用方一直等不到事件
+        return match fd {
+            Some(_) => Err(anyhow!("--progress-fd requires --progress json")),
+            None => Ok(()),
+        };
+    }
+
+    let sink: Box<dyn Write + Send> = match fd {
+        None | Some(2) => Box::new(std::io::stderr()),
+        Some(1) => Box::new(std::io::stdout()),
+        Some(fd) => open_fd(fd)?,
+    };
+
+    PROGRESS
+        .set(Arc::new(ProgressReporter { sink: Mutex::new(sink) }))
+        .map_err(|_| anyhow!("Progress reporter already initialized"))
+}
+
+/// 记录进程启动时已打开的文件描述符；必须在创建 tokio 运行时之前调用，
+/// 否则运行时自己的描述符（epoll、eventfd）也会被当作调用方传入的
+pub fn record_inherited_fds() {
+    #[cfg(unix)]
+    {
+        let listed: Vec<i32> = std::fs::read_dir("/dev/fd")
+            .map(|entries| entries.flatten().filter_map(|e| e.file_name().to_str()?.parse().ok()).collect())
+            .unwrap_or_default();
+        // 列目录本身用到的描述符此时已关闭，去掉它
+        let open = listed
+            .into_iter()
+            .filter(|fd| std::fs::symlink_metadata(format!("/dev/fd/{}", fd)).is_ok())
+            .collect();
+        let _ = INHERITED_FDS.set(open);
+    }
+}
+
+// 接管 --progress-fd 传入的文件描述符。0 是标准输入，负数无效；
+// 未打开或属于本进程的描述符交给 File 会写坏数据，关闭时还会关掉别人的文件
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<Box<dyn Write + Send>> {
+    use std::os::unix::io::FromRawFd;
+
+    if fd < 3 {
+        return Err(anyhow!("--progress-fd {} is not writable; use 1, 2 or an fd of 3 or above", fd));
+    }
+    if !INHERITED_FDS.get().is_some_and(|fds| fds.contains(&fd)) {
+        return Err(anyhow!("--progress-fd {} is not an open file descriptor", fd));
+    }
+    // 调用方通过 --progress-fd 传入一个启动时已打开的文件描述符，所有权交给此处
+    Ok(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+}
+
+#[cfg(not(unix))]
+fn open_fd(fd: i32) -> Result<Box<dyn Write + Send>> {
+    Err(anyhow!("--progress-fd {} is only supported on Unix", fd))
+}
+
+/// 是否启用了 JSON 进度输出（启用时隐藏终端进度条）
+pub fn json_enabled() -> bool {
+    PROGRESS.get().is_some()
+}
+
+/// 输出一条下载事件
+pub fn emit(event: ProgressEvent) {
+    if let Some(reporter) = PROGRESS.get() {
+        reporter.emit("download", &event);
+    }
+}
+
+/// 输出一条压测事件
+pub fn emit_bench(event: ProgressEvent) {
+    if let Some(reporter) = PROGRESS.get() {
+        reporter.emit("bench", &event);
+    }
+}
+
+/// 输出一条 HLS/DASH 分段流下载事件
+pub fn emit_stream(event: ProgressEvent) {
+    if let Some(reporter) = PROGRESS.get() {
+        reporter.emit("stream", &event);
+    }
+}
+
+/// 限制 progress 事件频率并计算区间速度
+pub struct ProgressTicker {
+    last_emit: Instant,
+    last_bytes: u64,
+}
+
+impl ProgressTicker {
+    pub fn new(initial_bytes: u64) -> Self {
+        Self {
+            last_emit: Instant::now(),
+            last_bytes: initial_bytes,
+        }
+    }
+
+    /// 距上次输出超过间隔时返回这段时间的速度（字节/秒）
+    pub fn tick(&mut self, bytes: u64) -> Option<f64> {
+        if !json_enabled() {
+            return None;
+        }
+
+        let elapsed = self.last_emit.elapsed();
+        if elapsed < PROGRESS_EVENT_INTERVAL {
+            return None;
+        }
+
+        let speed = bytes.saturating_sub(self.last_bytes) as f64 / elapsed.as_secs_f64();
+        self.last_emit = Instant::now();
+        self.last_bytes = bytes;
+        Some(speed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resume::ChunkStatus;
+
+    // 可以在写入后取回内容的输出
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn encode(source: &str, event: &ProgressEvent) -> serde_json::Value {
+        let buffer = Buffer::default();
+        let reporter = ProgressReporter { sink: Mutex::new(Box::new(buffer.clone())) };
+        reporter.emit(source, event);
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        serde_json::from_str(&output).unwrap()
+    }
+
+    #[test]
+    fn test_event_encoding() {
+        let chunk = ChunkInfo { index: 1, start: 100, end: 199, downloaded: 50, status: ChunkStatus::Downloading };
+        let line = encode(
+            "download",
+            &ProgressEvent::Progress {
+                url: "http://x/a",
+                url_hash: "abc",
+                total_size: 200,
+                downloaded: 150,
+                chunk: &chunk,
+                speed: 10.0,
+            },
+        );
+        assert_eq!(line["source"], "download");
+        assert_eq!(line["event"], "progress");
+        assert_eq!(line["chunk"]["end"], 199);
+        assert!(line["timestamp"].as_u64().unwrap() > 0);
+
+        // 不同来源的事件共用事件名，按 source 区分
+        let line = encode(
+            "stream",
+            &ProgressEvent::StreamCompleted {
+                url: "http://x/a.m3u8",
+                output_path: Path::new("a.ts"),
+                segments: 3,
+                total_size: 300,
+                elapsed_ms: 5,
+                average_speed: 60.0,
+            },
+        );
+        assert_eq!(line["source"], "stream");
+        assert_eq!(line["event"], "completed");
+        assert_eq!(line["output_path"], "a.ts");
+        assert_eq!(line["segments"], 3);
+
+        let started =
+            ProgressEvent::BenchStarted { url: "http://x", variant: None, requests: 10, concurrency: 2, http3: false, duration_ms: None, rate: None };
+        let line = encode("bench", &started);
+        assert_eq!(line["event"], "started");
+        assert!(line.get("rate").is_none());
+    }
+
+    #[test]
+    fn test_progress_fd_requires_json() {
+        assert!(init_progress(ProgressFormat::Human, Some(2)).is_err());
+        assert!(init_progress(ProgressFormat::Human, None).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_fd_rejects_invalid() {
+        assert!(open_fd(0).is_err());
+        assert!(open_fd(-1).is_err());
+        assert!(open_fd(1 << 20).is_err());
+    }
+}
@@ -214,6 +214,42 @@ impl DownloadMetadata {
         }
     }
 
+    /// 生成 ASCII 分段图：'#' 已完成，'+' 部分完成，'.' 等待中，'x' 失败
+    pub fn segment_map(&self, width: usize) -> String {
+        if self.total_size == 0 || width == 0 {
+            return String::new();
+        }
+
+        (0..width)
+            .map(|cell| {
+                let cell_start = self.total_size * cell as u64 / width as u64;
+                let cell_end = (self.total_size * (cell as u64 + 1) / width as u64).max(cell_start + 1);
+                let mut covered = 0;
+                let mut failed = false;
+
+                for chunk in self.chunks.iter().filter(|c| c.start < cell_end && c.end > cell_start) {
+                    let done_end = if chunk.status == ChunkStatus::Completed {
+                        chunk.end
+                    } else {
+                        chunk.start + chunk.downloaded.min(chunk.end - chunk.start)
+                    };
+                    covered += done_end.min(cell_end).saturating_sub(chunk.start.max(cell_start));
+                    failed |= chunk.status == ChunkStatus::Failed;
+                }
+
+                if covered >= cell_end - cell_start {
+                    '#'
+                } else if failed {
+                    'x'
+                } else if covered > 0 {
+                    '+'
+                } else {
+                    '.'
+                }
+            })
+            .collect()
+    }
+
     /// 标记下载完成
     pub fn mark_completed(&mut self) {
         self.status = DownloadStatus::Completed;
@@ -415,7 +451,13 @@ impl ResumeManager {
                 let file_size = file_metadata.len();
 
                 // 如果文件大小与元数据不符，重新开始
-                if file_size != metadata.downloaded {
+                // （并行下载按偏移写入，文件大小只受总大小约束）
+                let size_mismatch = if metadata.chunks.len() > 1 {
+                    file_size > metadata.total_size
+                } else {
+                    file_size != metadata.downloaded
+                };
+                if size_mismatch {
                     return Ok(None);
                 }
 
@@ -500,6 +542,25 @@ mod tests {
         assert_eq!(metadata.get_progress_percentage(), 50.0);
     }
 
+    #[test]
+    fn test_segment_map() {
+        let mut metadata = DownloadMetadata::new(
+            "https://example.com/file.zip".to_string(),
+            PathBuf::from("/tmp/file.zip"),
+            1000,
+            true,
+            None,
+            None,
+        );
+
+        metadata.initialize_chunks(4);
+        metadata.update_chunk_progress(0, 250);
+        metadata.update_chunk_progress(1, 100);
+        metadata.set_chunk_status(3, ChunkStatus::Failed);
+
+        assert_eq!(metadata.segment_map(8), "##+...xx");
+    }
+
     #[test]
     fn test_validation() {
         let metadata = DownloadMetadata::new(
@@ -0,0 +1,249 @@
+use crate::core::parse_size;
+use crate::log::log_info;
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveTime, Timelike};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+static SCHEDULE: OnceCell<Arc<ScheduleState>> = OnceCell::const_new();
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// 下载窗口关闭，传输已暂停
+#[derive(Debug, Error)]
+#[error("Download window {0} closed, transfer paused")]
+pub struct DownloadPaused(pub TimeWindow);
+
+/// 每天的时间段，支持跨越午夜（如 22:00-06:00）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl std::fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+impl TimeWindow {
+    /// 解析 "HH:MM-HH:MM"
+    pub fn parse(value: &str) -> Result<Self> {
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Invalid time window '{}', expected HH:MM-HH:MM", value))?;
+        Ok(Self {
+            start: parse_time_of_day(start)?,
+            end: parse_time_of_day(end)?,
+        })
+    }
+
+    /// 起止时间相同表示全天
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// 距离窗口打开的时间，已在窗口内时为零
+    pub fn until_open(&self, now: NaiveTime) -> Duration {
+        if self.contains(now) {
+            Duration::ZERO
+        } else {
+            duration_until(now, self.start)
+        }
+    }
+}
+
+/// 解析 "HH:MM" 格式的时间
+pub fn parse_time_of_day(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| anyhow!("Invalid time '{}', expected HH:MM", value.trim()))
+}
+
+/// 从 now 到下一次 target 的时长（已过则为明天）
+pub fn duration_until(now: NaiveTime, target: NaiveTime) -> Duration {
+    let now_secs = now.num_seconds_from_midnight() as u64;
+    let target_secs = target.num_seconds_from_midnight() as u64;
+    Duration::from_secs((target_secs + SECONDS_PER_DAY - now_secs) % SECONDS_PER_DAY)
+}
+
+/// 按时段限速规则，如 "08:00-18:00=500K"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandwidthRule {
+    pub window: TimeWindow,
+    pub bytes_per_sec: u64,
+}
+
+impl BandwidthRule {
+    pub fn parse(value: &str) -> Result<Self> {
+        let (window, rate) = value
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid bandwidth rule '{}', expected HH:MM-HH:MM=RATE", value))?;
+        let bytes_per_sec = parse_size(rate)?;
+        if bytes_per_sec == 0 {
+            return Err(anyhow!("Rate must be positive: {}", rate.trim()));
+        }
+        Ok(Self { window: TimeWindow::parse(window)?, bytes_per_sec })
+    }
+}
+
+/// 下载调度设置
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    /// 开始时间
+    pub at: Option<NaiveTime>,
+    /// 允许传输的时间窗口
+    pub window: Option<TimeWindow>,
+    /// 按时段限速，未覆盖的时段不限速
+    pub bandwidth: Vec<BandwidthRule>,
+}
+
+impl Schedule {
+    pub fn is_empty(&self) -> bool {
+        self.at.is_none() && self.window.is_none() && self.bandwidth.is_empty()
+    }
+
+    /// 当前时段的限速（字节/秒）
+    pub fn rate_at(&self, time: NaiveTime) -> Option<u64> {
+        self.bandwidth
+            .iter()
+            .find(|rule| rule.window.contains(time))
+            .map(|rule| rule.bytes_per_sec)
+    }
+}
+
+struct ScheduleState {
+    schedule: Schedule,
+    /// 令牌桶：下一段数据可以发送的时间点
+    next_slot: Mutex<Instant>,
+}
+
+/// 初始化全局调度；未设置任何调度时不做任何事
+pub fn init_schedule(schedule: Schedule) -> Result<()> {
+    if schedule.is_empty() {
+        return Ok(());
+    }
+
+    SCHEDULE
+        .set(Arc::new(ScheduleState {
+            schedule,
+            next_slot: Mutex::new(Instant::now()),
+        }))
+        .map_err(|_| anyhow!("Download schedule already initialized"))
+}
+
+fn now() -> NaiveTime {
+    Local::now().time()
+}
+
+/// 等待 --at 指定的开始时间
+pub async fn wait_for_start() {
+    let Some(at) = SCHEDULE.get().and_then(|s| s.schedule.at) else {
+        return;
+    };
+
+    let wait = duration_until(now(), at);
+    if !wait.is_zero() {
+        log_info(&format!("Download scheduled at {}, waiting {}s", at.format("%H:%M"), wait.as_secs()));
+        println!("Download scheduled at {} (in {})", at.format("%H:%M"), format_wait(wait));
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// 等待下载窗口打开
+pub async fn wait_for_window() {
+    let Some(window) = SCHEDULE.get().and_then(|s| s.schedule.window) else {
+        return;
+    };
+
+    let wait = window.until_open(now());
+    if !wait.is_zero() {
+        log_info(&format!("Outside download window {}, waiting {}s", window, wait.as_secs()));
+        println!("Outside download window {}, waiting {}", window, format_wait(wait));
+        tokio::time::sleep(wait).await;
+        log_info(&format!("Download window {} opened", window));
+    }
+}
+
+/// 在传输循环中每收到一段数据调用一次：按时段限速，窗口关闭时返回 DownloadPaused
+pub async fn throttle(bytes: usize) -> Result<()> {
+    let Some(state) = SCHEDULE.get() else {
+        return Ok(());
+    };
+
+    check_window(&state.schedule)?;
+
+    if let Some(rate) = state.schedule.rate_at(now()) {
+        let delay = {
+            let mut next_slot = state.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let slot = (*next_slot).max(now);
+            *next_slot = slot + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            slot - now
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+            check_window(&state.schedule)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_window(schedule: &Schedule) -> Result<()> {
+    match schedule.window {
+        Some(window) if !window.contains(now()) => Err(DownloadPaused(window).into()),
+        _ => Ok(()),
+    }
+}
+
+fn format_wait(wait: Duration) -> String {
+    let secs = wait.as_secs();
+    format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(value: &str) -> NaiveTime {
+        parse_time_of_day(value).unwrap()
+    }
+
+    #[test]
+    fn test_time_window() {
+        let night = TimeWindow::parse("22:00-06:00").unwrap();
+        assert!(night.contains(t("23:30")));
+        assert!(night.contains(t("05:59")));
+        assert!(!night.contains(t("06:00")));
+        assert_eq!(night.until_open(t("21:00")), Duration::from_secs(3600));
+
+        let day = TimeWindow::parse("01:00-06:00").unwrap();
+        assert_eq!(day.until_open(t("07:00")), Duration::from_secs(18 * 3600));
+        assert!(TimeWindow::parse("01:00").is_err());
+    }
+
+    #[test]
+    fn test_bandwidth_rules() {
+        assert_eq!(BandwidthRule::parse("01:00-02:00=1.5M").unwrap().bytes_per_sec, 1536 * 1024);
+        assert!(BandwidthRule::parse("01:00-02:00=fast").is_err());
+        assert!(BandwidthRule::parse("01:00-02:00=0").is_err());
+
+        let schedule = Schedule {
+            bandwidth: vec![BandwidthRule::parse("08:00-18:00=1M").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(schedule.rate_at(t("12:00")), Some(1024 * 1024));
+        assert_eq!(schedule.rate_at(t("20:00")), None);
+    }
+}
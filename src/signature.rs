@@ -0,0 +1,313 @@
+use crate::core::{build_client, ClientType};
+use crate::log::{log_info, log_warn};
+use ::signature::Verifier;
+use anyhow::{anyhow, Context, Result};
+use ssh_key::{
+    sha2::{Digest, Sha256, Sha512},
+    HashAlg, PublicKey as SshPublicKey, SshSig,
+};
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 签名与文件不符：内容被改动、由别的密钥签名或命名空间不对。
+/// 签名或公钥无法读取、格式不对时不是这个错误
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct BadSignature(String);
+
+/// 签名文件格式，根据签名内容自动识别
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureFormat {
+    /// minisign（Ed25519）
+    Minisign,
+    /// ssh-keygen -Y sign 生成的 SSH 签名
+    Ssh,
+}
+
+impl SignatureFormat {
+    pub fn detect(signature: &str) -> Option<Self> {
+        let signature = signature.trim_start();
+        if signature.starts_with("-----BEGIN SSH SIGNATURE-----") {
+            Some(Self::Ssh)
+        } else if signature.starts_with("untrusted comment:") {
+            Some(Self::Minisign)
+        } else {
+            None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Minisign => "minisign",
+            Self::Ssh => "SSH",
+        }
+    }
+}
+
+/// 下载完成后的签名校验设置
+#[derive(Debug, Clone)]
+pub struct SignatureCheck {
+    /// 签名文件路径或 URL
+    pub signature: String,
+    /// 公钥内容或公钥文件路径
+    pub pubkey: String,
+    /// SSH 签名的命名空间（ssh-keygen -n）
+    pub namespace: String,
+}
+
+impl SignatureCheck {
+    /// 校验文件签名；签名与文件不符时把文件移到 <文件名>.quarantine 并返回错误。
+    /// 签名或公钥无法获取、读取或解析时只返回错误，文件留在原处
+    pub async fn verify_or_quarantine(&self, path: &Path, http3: bool) -> Result<()> {
+        match self.verify(path, http3).await {
+            Ok(format) => {
+                log_info(&format!("{} signature verified for {}", format.name(), path.display()));
+                println!("Signature OK ({}): {}", format.name(), path.display());
+                Ok(())
+            }
+            Err(e) if !e.is::<BadSignature>() => {
+                Err(e.context(format!("Could not verify the signature of {}", path.display())))
+            }
+            Err(e) => {
+                let quarantine = quarantine(path)?;
+                log_warn(&format!("Moved {} to {}", path.display(), quarantine.display()));
+                Err(anyhow!(
+                    "Signature verification failed for {}: {}; file moved to {}",
+                    path.display(),
+                    e,
+                    quarantine.display()
+                ))
+            }
+        }
+    }
+
+    async fn verify(&self, path: &Path, http3: bool) -> Result<SignatureFormat> {
+        let signature = self.load_signature(http3).await?;
+        let pubkey = read_inline_or_file(&self.pubkey).context("Failed to read public key")?;
+        let namespace = self.namespace.clone();
+        let file = path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || verify_file(&file, &signature, &pubkey, &namespace))
+            .await
+            .map_err(|e| anyhow!("Signature verification task failed: {}", e))?
+    }
+
+    async fn load_signature(&self, http3: bool) -> Result<String> {
+        if self.signature.starts_with("http://") || self.signature.starts_with("https://") {
+            let client = build_client(true, 10, http3, vec![], ClientType::Get)?;
+            let response = client
+                .get(&self.signature)
+                .send()
+                .await
+                .with_context(|| format!("Failed to fetch signature: {}", self.signature))?;
+            if !response.status().is_success() {
+                return Err(anyhow!("Failed to fetch signature {}: HTTP {}", self.signature, response.status()));
+            }
+            Ok(response.text().await?)
+        } else {
+            fs::read_to_string(&self.signature)
+                .with_context(|| format!("Failed to read signature file: {}", self.signature))
+        }
+    }
+}
+
+// 参数是已存在的文件时读取文件内容，否则按公钥字符串处理
+fn read_inline_or_file(value: &str) -> Result<String> {
+    let path = Path::new(value);
+    if path.is_file() {
+        Ok(fs::read_to_string(path)?)
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// 用公钥校验文件的 minisign 或 SSH 签名
+pub fn verify_file(path: &Path, signature: &str, pubkey: &str, namespace: &str) -> Result<SignatureFormat> {
+    let format = SignatureFormat::detect(signature)
+        .ok_or_else(|| anyhow!("Unrecognized signature format (expected minisign or SSH signature)"))?;
+    match format {
+        SignatureFormat::Minisign => verify_minisign(path, signature, pubkey)?,
+        SignatureFormat::Ssh => verify_ssh(path, signature, pubkey, namespace)?,
+    }
+    Ok(format)
+}
+
+fn verify_minisign(path: &Path, signature: &str, pubkey: &str) -> Result<()> {
+    let pubkey = pubkey.trim();
+    let key = if pubkey.starts_with("untrusted comment:") {
+        minisign_verify::PublicKey::decode(pubkey)
+    } else {
+        minisign_verify::PublicKey::from_base64(pubkey)
+    }
+    .map_err(|e| anyhow!("Invalid minisign public key: {}", e))?;
+    let signature = minisign_verify::Signature::decode(signature.trim())
+        .map_err(|e| anyhow!("Invalid minisign signature: {}", e))?;
+
+    match key.verify_stream(&signature) {
+        Ok(mut verifier) => {
+            read_chunks(path, |chunk| verifier.update(chunk))?;
+            verifier.finalize()
+        }
+        // 旧版 minisign 签名的是原始内容，无法流式校验
+        Err(minisign_verify::Error::UnsupportedLegacyMode) => {
+            let content = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            key.verify(&content, &signature, true)
+        }
+        Err(e) => Err(e),
+    }
+    .map_err(|e| BadSignature(format!("Bad minisign signature: {}", e)))?;
+
+    log_info(&format!("minisign trusted comment: {}", signature.trusted_comment()));
+    Ok(())
+}
+
+fn verify_ssh(path: &Path, signature: &str, pubkey: &str, namespace: &str) -> Result<()> {
+    // 公钥文件可能带注释行，取第一行有效的公钥
+    let key_line = pubkey
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| anyhow!("Empty SSH public key"))?;
+    let key = SshPublicKey::from_openssh(key_line).map_err(|e| anyhow!("Invalid SSH public key: {}", e))?;
+    let signature = SshSig::from_pem(signature.trim()).map_err(|e| anyhow!("Invalid SSH signature: {}", e))?;
+
+    if signature.public_key() != key.key_data() {
+        return Err(BadSignature("Bad SSH signature: signed by a different key".to_string()).into());
+    }
+    if signature.namespace() != namespace {
+        return Err(BadSignature(format!(
+            "Bad SSH signature: namespace is '{}', expected '{}'",
+            signature.namespace(),
+            namespace
+        ))
+        .into());
+    }
+
+    // 按 PROTOCOL.sshsig 构造被签名的数据，文件内容只参与流式哈希
+    let hash = match signature.hash_alg() {
+        HashAlg::Sha256 => {
+            let mut hasher = Sha256::new();
+            read_chunks(path, |chunk| hasher.update(chunk))?;
+            hasher.finalize().to_vec()
+        }
+        HashAlg::Sha512 => {
+            let mut hasher = Sha512::new();
+            read_chunks(path, |chunk| hasher.update(chunk))?;
+            hasher.finalize().to_vec()
+        }
+        other => return Err(anyhow!("Unsupported SSH signature hash algorithm: {}", other)),
+    };
+
+    let mut signed_data = b"SSHSIG".to_vec();
+    for field in [
+        signature.namespace().as_bytes(),
+        signature.reserved(),
+        signature.hash_alg().as_str().as_bytes(),
+        &hash,
+    ] {
+        signed_data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        signed_data.extend_from_slice(field);
+    }
+
+    key.key_data()
+        .verify(&signed_data, signature.signature())
+        .map_err(|e| BadSignature(format!("Bad SSH signature: {}", e)).into())
+}
+
+fn read_chunks(path: &Path, mut update: impl FnMut(&[u8])) -> Result<()> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        update(&buffer[..read]);
+    }
+}
+
+// 把未通过校验的文件改名隔离，避免被当作可信文件使用
+fn quarantine(path: &Path) -> Result<PathBuf> {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".quarantine");
+    let target = PathBuf::from(name);
+    if target.exists() {
+        fs::remove_file(&target)?;
+    }
+    fs::rename(path, &target).with_context(|| format!("Failed to quarantine {}", path.display()))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINISIGN_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const MINISIGN_SIG: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+
+    const SSH_KEY: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIDmm9MxdERzYf8ckSTrpcAvcQRywx0DMTlI0elCEi62s test";
+    const SSH_SIG: &str = "-----BEGIN SSH SIGNATURE-----
+U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgOab0zF0RHNh/xyRJOulwC9xBHL
+DHQMxOUjR6UISLrawAAAAEZmlsZQAAAAAAAAAGc2hhNTEyAAAAUwAAAAtzc2gtZWQyNTUx
+OQAAAEBSapRYRJcc+BewOCrDK57aaezGAp/U1iJLIgcptERtuLghe+tPxQNMpfDwkQKXIY
+b38tBFF1kMsbju5C+pvGEH
+-----END SSH SIGNATURE-----";
+
+    #[test]
+    fn test_verify_minisign_and_ssh() {
+        let dir = std::env::temp_dir().join(format!("surf-sig-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good");
+        let bad = dir.join("bad");
+        fs::write(&good, b"test").unwrap();
+        fs::write(&bad, b"Test").unwrap();
+
+        assert_eq!(verify_file(&good, MINISIGN_SIG, MINISIGN_KEY, "file").unwrap(), SignatureFormat::Minisign);
+        assert!(verify_file(&bad, MINISIGN_SIG, MINISIGN_KEY, "file").is_err());
+
+        assert_eq!(verify_file(&good, SSH_SIG, SSH_KEY, "file").unwrap(), SignatureFormat::Ssh);
+        assert!(verify_file(&bad, SSH_SIG, SSH_KEY, "file").is_err());
+        assert!(verify_file(&good, SSH_SIG, SSH_KEY, "git").is_err());
+
+        assert!(verify_file(&good, "not a signature", SSH_KEY, "file").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_only_on_bad_signature() {
+        let dir = std::env::temp_dir().join(format!("surf-sig-quarantine-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let signature = dir.join("file.minisig");
+        fs::write(&signature, MINISIGN_SIG).unwrap();
+        let check = |signature: &Path| SignatureCheck {
+            signature: signature.to_string_lossy().into_owned(),
+            pubkey: MINISIGN_KEY.to_string(),
+            namespace: "file".to_string(),
+        };
+
+        // 签名文件不存在：报错，但文件没有问题，留在原处
+        let file = dir.join("file");
+        fs::write(&file, b"Test").unwrap();
+        assert!(check(&dir.join("missing.minisig")).verify_or_quarantine(&file, false).await.is_err());
+        assert!(file.exists());
+
+        let error = check(&signature).verify_or_quarantine(&file, false).await.unwrap_err();
+        assert!(error.to_string().contains("moved to"));
+        assert!(!file.exists() && dir.join("file.quarantine").exists());
+
+        fs::write(&file, b"test").unwrap();
+        check(&signature).verify_or_quarantine(&file, false).await.unwrap();
+        assert!(file.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,375 @@
+use crate::checksum::{hash_file, ExpectedChecksum, HashAlgorithm};
+use crate::core::{build_client, ClientType};
+use crate::log::{log_debug, log_info, log_warn};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const INDEX_FILE: &str = "index.json";
+const LOCK_FILE: &str = "store.lock";
+
+/// 内容寻址存储中的一个对象（以 SHA-256 命名）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreObject {
+    pub size: u64,
+    pub created: u64,
+    pub last_used: u64,
+    /// 曾经从这些 URL 下载到该内容
+    pub urls: Vec<String>,
+}
+
+/// 存储索引
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreIndex {
+    /// sha256 -> 对象信息
+    objects: HashMap<String, StoreObject>,
+    /// "URL + ETag" -> sha256
+    keys: HashMap<String, String>,
+}
+
+/// 查询结果
+pub enum StoreLookup {
+    /// 已从存储中恢复到输出路径；etag 为按 ETag 命中时的值
+    Hit { sha256: String, etag: Option<String> },
+    /// 存储中没有，需要下载；etag 供下载完成后登记
+    Miss { etag: Option<String> },
+}
+
+/// 本地内容寻址下载存储，位于数据目录下的 surf/store。
+/// 打开期间持有存储目录的排他文件锁，并发的进程不会互相覆盖索引
+pub struct Store {
+    root: PathBuf,
+    index: StoreIndex,
+    _lock: fs::File,
+}
+
+impl Store {
+    pub fn open() -> Result<Self> {
+        let root = dirs::data_dir()
+            .ok_or_else(|| anyhow!("Cannot determine data directory"))?
+            .join("surf")
+            .join("store");
+        Self::open_at(root)
+    }
+
+    pub fn open_at(root: PathBuf) -> Result<Self> {
+        fs::create_dir_all(root.join("objects"))
+            .with_context(|| format!("Failed to create store directory: {}", root.display()))?;
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(root.join(LOCK_FILE))
+            .context("Failed to open store lock")?;
+        lock.lock().context("Failed to lock store")?;
+
+        let index_path = root.join(INDEX_FILE);
+        let index = if index_path.exists() {
+            let content = fs::read_to_string(&index_path).context("Failed to read store index")?;
+            serde_json::from_str(&content).unwrap_or_else(|e| {
+                log_warn(&format!("Store index is corrupt, starting fresh: {}", e));
+                StoreIndex::default()
+            })
+        } else {
+            StoreIndex::default()
+        };
+
+        Ok(Self { root, index, _lock: lock })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.objects.len()
+    }
+
+    fn save(&self) -> Result<()> {
+        // 先写临时文件再重命名，避免并发的 CI 任务读到半个索引
+        let tmp = self.root.join(format!("{}.{}.tmp", INDEX_FILE, std::process::id()));
+        fs::write(&tmp, serde_json::to_string_pretty(&self.index)?).context("Failed to write store index")?;
+        fs::rename(&tmp, self.root.join(INDEX_FILE)).context("Failed to update store index")?;
+        Ok(())
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.root.join("objects").join(&sha256[..2]).join(sha256)
+    }
+
+    fn url_key(url: &str, etag: &str) -> String {
+        format!("{}\n{}", url, etag)
+    }
+
+    /// 按内容哈希查找
+    pub fn find_by_checksum(&self, sha256: &str) -> Option<String> {
+        let sha256 = sha256.to_ascii_lowercase();
+        (self.index.objects.contains_key(&sha256) && self.object_path(&sha256).exists()).then_some(sha256)
+    }
+
+    /// 按 URL + ETag 查找
+    pub fn find_by_url(&self, url: &str, etag: &str) -> Option<String> {
+        self.index
+            .keys
+            .get(&Self::url_key(url, etag))
+            .and_then(|sha256| self.find_by_checksum(sha256))
+    }
+
+    /// 重新计算对象的哈希；对象被改动过（例如经由硬链接写入）时从存储中删除并返回 false
+    pub fn verify_object(&mut self, sha256: &str) -> Result<bool> {
+        let object = self.object_path(sha256);
+        let size = self.index.objects.get(sha256).map(|o| o.size);
+        let intact = fs::metadata(&object).is_ok_and(|m| Some(m.len()) == size)
+            && hash_file(&object, HashAlgorithm::Sha256).is_ok_and(|actual| actual == sha256);
+        if !intact {
+            log_warn(&format!("Store object {} is corrupt, removing it", sha256));
+            if object.exists() {
+                make_removable(&object);
+                fs::remove_file(&object).with_context(|| format!("Failed to remove {}", object.display()))?;
+            }
+            self.index.objects.remove(sha256);
+            self.index.keys.retain(|_, key| key != sha256);
+            self.save()?;
+        }
+        Ok(intact)
+    }
+
+    /// 将存储中的对象硬链接（失败时复制）到输出路径
+    pub fn materialize(&mut self, sha256: &str, output: &Path) -> Result<()> {
+        let object = self.object_path(sha256);
+        if output.exists() {
+            fs::remove_file(output).with_context(|| format!("Failed to replace {}", output.display()))?;
+        }
+        if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        if let Err(e) = fs::hard_link(&object, output) {
+            log_debug(&format!("Hard link failed ({}), copying from store instead", e));
+            fs::copy(&object, output).with_context(|| format!("Failed to copy from store to {}", output.display()))?;
+        }
+
+        if let Some(entry) = self.index.objects.get_mut(sha256) {
+            entry.last_used = now();
+        }
+        self.save()
+    }
+
+    /// 把下载好的文件登记到存储中，返回其 SHA-256
+    pub fn insert(&mut self, file: &Path, url: &str, etag: Option<&str>) -> Result<String> {
+        let sha256 = hash_file(file, HashAlgorithm::Sha256)?;
+        let object = self.object_path(&sha256);
+
+        if !object.exists() {
+            if let Some(parent) = object.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let tmp = object.with_extension("tmp");
+            fs::copy(file, &tmp).with_context(|| format!("Failed to copy {} into store", file.display()))?;
+            // 对象只读，防止经由硬链接被意外修改
+            let mut permissions = fs::metadata(&tmp)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(&tmp, permissions)?;
+            fs::rename(&tmp, &object)?;
+        }
+
+        let size = fs::metadata(&object)?.len();
+        let timestamp = now();
+        let entry = self.index.objects.entry(sha256.clone()).or_insert_with(|| StoreObject {
+            size,
+            created: timestamp,
+            last_used: timestamp,
+            urls: Vec::new(),
+        });
+        entry.last_used = timestamp;
+        if !entry.urls.iter().any(|u| u == url) {
+            entry.urls.push(url.to_string());
+        }
+        if let Some(etag) = etag {
+            self.index.keys.insert(Self::url_key(url, etag), sha256.clone());
+        }
+
+        self.save()?;
+        Ok(sha256)
+    }
+
+    /// 存储占用的总字节数
+    pub fn total_size(&self) -> u64 {
+        self.index.objects.values().map(|o| o.size).sum()
+    }
+
+    /// 按最近最少使用淘汰对象，直到总大小不超过 max_size；返回（删除数量，释放字节数）
+    pub fn gc(&mut self, max_size: u64) -> Result<(usize, u64)> {
+        // 先清理对象文件已丢失的条目
+        let missing: Vec<String> = self
+            .index
+            .objects
+            .keys()
+            .filter(|sha256| !self.object_path(sha256).exists())
+            .cloned()
+            .collect();
+        for sha256 in &missing {
+            self.index.objects.remove(sha256);
+        }
+
+        let mut by_age: Vec<(String, u64, u64)> = self
+            .index
+            .objects
+            .iter()
+            .map(|(sha256, o)| (sha256.clone(), o.last_used, o.size))
+            .collect();
+        by_age.sort_by_key(|(_, last_used, _)| *last_used);
+
+        let mut total = self.total_size();
+        let mut removed = 0;
+        let mut freed = 0;
+        for (sha256, _, size) in by_age {
+            if total <= max_size {
+                break;
+            }
+            let object = self.object_path(&sha256);
+            make_removable(&object);
+            fs::remove_file(&object).with_context(|| format!("Failed to remove {}", object.display()))?;
+            self.index.objects.remove(&sha256);
+            total -= size;
+            freed += size;
+            removed += 1;
+            log_debug(&format!("Evicted {} from store", sha256));
+        }
+
+        let objects = &self.index.objects;
+        self.index.keys.retain(|_, sha256| objects.contains_key(sha256));
+        self.save()?;
+        Ok((removed, freed))
+    }
+}
+
+// Windows 上无法删除只读文件；Unix 上不能改权限，否则会影响硬链接出去的文件
+fn make_removable(_object: &Path) {
+    #[cfg(windows)]
+    if let Ok(metadata) = fs::metadata(_object) {
+        let mut permissions = metadata.permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        let _ = fs::set_permissions(_object, permissions);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// 尝试从存储中恢复：有 sha256 校验和时完全不访问网络，否则用 HEAD 取 ETag 查找。
+/// 恢复前重新校验对象，被改动过的对象会被删除并视为未命中
+pub async fn restore(url: &str, output: &Path, checksum: Option<&ExpectedChecksum>, http3: bool) -> Result<StoreLookup> {
+    if let Some(expected) = checksum.filter(|c| c.algorithm == HashAlgorithm::Sha256) {
+        let mut store = Store::open()?;
+        if let Some(sha256) = store.find_by_checksum(&expected.hex) {
+            if store.verify_object(&sha256)? {
+                store.materialize(&sha256, output)?;
+                log_info(&format!("Restored {} from store by checksum ({})", output.display(), sha256));
+                return Ok(StoreLookup::Hit { sha256, etag: None });
+            }
+        }
+    }
+
+    // HEAD 请求期间不持有存储锁
+    let client = build_client(true, 10, http3, vec![], ClientType::Download)?;
+    let etag = match client.head(url).send().await {
+        Ok(response) if response.status().is_success() => response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string()),
+        Ok(response) => {
+            log_debug(&format!("Store lookup HEAD returned {}", response.status()));
+            None
+        }
+        Err(e) => {
+            log_debug(&format!("Store lookup HEAD failed: {}", e));
+            None
+        }
+    };
+
+    let mut store = Store::open()?;
+    if let Some(sha256) = etag.as_deref().and_then(|etag| store.find_by_url(url, etag)) {
+        if store.verify_object(&sha256)? {
+            store.materialize(&sha256, output)?;
+            log_info(&format!("Restored {} from store by ETag ({})", output.display(), sha256));
+            return Ok(StoreLookup::Hit { sha256, etag });
+        }
+    }
+
+    Ok(StoreLookup::Miss { etag })
+}
+
+/// 下载完成后登记到存储
+pub fn remember(url: &str, output: &Path, etag: Option<&str>) -> Result<String> {
+    let mut store = Store::open()?;
+    let sha256 = store.insert(output, url, etag)?;
+    log_info(&format!("Added {} to store ({})", output.display(), sha256));
+    Ok(sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_insert_lookup_and_gc() {
+        let root = std::env::temp_dir().join(format!("surf-store-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let mut store = Store::open_at(root.join("store")).unwrap();
+
+        let file = root.join("a.bin");
+        fs::write(&file, b"abc").unwrap();
+        let sha256 = store.insert(&file, "https://example.com/a.bin", Some("\"v1\"")).unwrap();
+        assert_eq!(sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(store.find_by_url("https://example.com/a.bin", "\"v1\""), Some(sha256.clone()));
+        assert_eq!(store.find_by_url("https://example.com/a.bin", "\"v2\""), None);
+
+        let restored = root.join("out/a.bin");
+        store.materialize(&sha256, &restored).unwrap();
+        assert_eq!(fs::read(&restored).unwrap(), b"abc");
+        assert!(store.verify_object(&sha256).unwrap());
+
+        assert_eq!(store.gc(0).unwrap(), (1, 3));
+        assert_eq!(store.find_by_checksum(&sha256), None);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_store_discards_modified_object() {
+        let root = std::env::temp_dir().join(format!("surf-store-corrupt-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let mut store = Store::open_at(root.join("store")).unwrap();
+        let file = root.join("a.bin");
+        fs::write(&file, b"abc").unwrap();
+        let sha256 = store.insert(&file, "https://example.com/a.bin", Some("\"v1\"")).unwrap();
+
+        // 模拟以 root 身份经由硬链接改写了对象
+        let object = store.object_path(&sha256);
+        let mut permissions = fs::metadata(&object).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&object, permissions).unwrap();
+        fs::write(&object, b"xyz").unwrap();
+
+        assert!(!store.verify_object(&sha256).unwrap());
+        assert_eq!(store.find_by_url("https://example.com/a.bin", "\"v1\""), None);
+        drop(store);
+        // 索引的修改已保存，并且锁已随 Store 释放
+        let store = Store::open_at(root.join("store")).unwrap();
+        assert_eq!(store.len(), 0);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
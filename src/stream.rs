@@ -0,0 +1,882 @@
+use crate::checksum::HashAlgorithm;
+use crate::core::{build_client, ClientType, TimeoutError};
+use crate::log::{log_debug, log_error, log_info, log_warn};
+use crate::progress::{self, ProgressEvent};
+use crate::schedule;
+use anyhow::{anyhow, Context, Result};
+use futures_util::StreamExt;
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::{Client, StatusCode};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{fs, io::AsyncWriteExt, sync::Semaphore, task::JoinSet};
+use url::Url;
+
+/// 分段流媒体清单类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManifestKind {
+    Hls,
+    Dash,
+}
+
+/// 变体选择条件
+#[derive(Debug, Clone, Default)]
+pub struct StreamSelection {
+    /// 最大带宽（bit/s），选择不超过该值的最高码率
+    pub max_bandwidth: Option<u64>,
+    /// 指定分辨率（宽, 高）
+    pub resolution: Option<(u32, u32)>,
+}
+
+/// 单个媒体分段
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub url: String,
+    /// 字节范围（起始位置, 长度）
+    pub byte_range: Option<(u64, u64)>,
+}
+
+/// 下载计划：初始化分段 + 按顺序排列的媒体分段
+#[derive(Debug, Clone, Default)]
+pub struct StreamPlan {
+    pub init: Option<Segment>,
+    pub segments: Vec<Segment>,
+}
+
+/// 可供选择的变体（HLS 的 variant stream 或 DASH 的 Representation）
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    /// HLS 变体的媒体播放列表地址
+    pub uri: Option<String>,
+    /// DASH 变体已解析好的分段列表
+    pub plan: Option<StreamPlan>,
+}
+
+impl StreamSelection {
+    /// 解析 "1280x720" 形式的分辨率
+    pub fn parse_resolution(value: &str) -> Result<(u32, u32)> {
+        let (w, h) = value
+            .to_ascii_lowercase()
+            .split_once('x')
+            .map(|(w, h)| (w.trim().to_string(), h.trim().to_string()))
+            .ok_or_else(|| anyhow!("Invalid resolution '{}', expected WIDTHxHEIGHT", value))?;
+        Ok((
+            w.parse().with_context(|| format!("Invalid resolution width: {}", w))?,
+            h.parse().with_context(|| format!("Invalid resolution height: {}", h))?,
+        ))
+    }
+
+    /// 根据条件挑选变体，默认选择最高码率
+    pub fn select<'a>(&self, variants: &'a [Variant]) -> Result<&'a Variant> {
+        let candidates: Vec<&Variant> = variants
+            .iter()
+            .filter(|v| self.resolution.is_none() || v.resolution == self.resolution)
+            .filter(|v| self.max_bandwidth.is_none_or(|max| v.bandwidth <= max))
+            .collect();
+
+        candidates
+            .into_iter()
+            .max_by_key(|v| v.bandwidth)
+            .ok_or_else(|| anyhow!("No stream variant matches the requested bandwidth/resolution"))
+    }
+}
+
+/// 根据 URL 扩展名判断是否为 HLS/DASH 清单
+pub fn detect_manifest(url: &str) -> Option<ManifestKind> {
+    let path = Url::parse(url)
+        .map(|u| u.path().to_ascii_lowercase())
+        .unwrap_or_else(|_| url.to_ascii_lowercase());
+
+    if path.ends_with(".m3u8") {
+        Some(ManifestKind::Hls)
+    } else if path.ends_with(".mpd") {
+        Some(ManifestKind::Dash)
+    } else {
+        None
+    }
+}
+
+// ========== HLS ==========
+
+/// 解析 HLS 属性列表（支持带引号的值）
+fn parse_attributes(input: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = input.trim();
+
+    while !rest.is_empty() {
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            match quoted.split_once('"') {
+                Some((value, remaining)) => (value.to_string(), remaining),
+                None => (quoted.to_string(), ""),
+            }
+        } else {
+            match after.split_once(',') {
+                Some((value, remaining)) => (value.to_string(), remaining),
+                None => (after.to_string(), ""),
+            }
+        };
+        attributes.insert(key.trim().to_ascii_uppercase(), value.trim().to_string());
+        rest = remaining.trim_start_matches(',').trim();
+    }
+
+    attributes
+}
+
+/// 解析 "长度@偏移" 形式的字节范围，未给出偏移时接着上一段
+fn parse_byte_range(value: &str, next_offset: u64) -> Result<(u64, u64)> {
+    let (len, offset) = match value.split_once('@') {
+        Some((len, offset)) => (len, offset.trim().parse().context("Invalid byte range offset")?),
+        None => (value, next_offset),
+    };
+    Ok((offset, len.trim().parse().context("Invalid byte range length")?))
+}
+
+/// 判断是否为 HLS 主播放列表
+pub fn is_hls_master(content: &str) -> bool {
+    content.contains("#EXT-X-STREAM-INF")
+}
+
+/// 解析 HLS 主播放列表中的变体
+pub fn parse_hls_master(content: &str, base: &Url) -> Result<Vec<Variant>> {
+    let mut variants = Vec::new();
+    let mut pending: Option<HashMap<String, String>> = None;
+
+    for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(parse_attributes(attrs));
+        } else if !line.starts_with('#') {
+            if let Some(attrs) = pending.take() {
+                variants.push(Variant {
+                    bandwidth: attrs.get("BANDWIDTH").and_then(|b| b.parse().ok()).unwrap_or(0),
+                    resolution: attrs
+                        .get("RESOLUTION")
+                        .and_then(|r| StreamSelection::parse_resolution(r).ok()),
+                    uri: Some(base.join(line)?.to_string()),
+                    plan: None,
+                });
+            }
+        }
+    }
+
+    if variants.is_empty() {
+        return Err(anyhow!("HLS master playlist does not contain any variant streams"));
+    }
+    Ok(variants)
+}
+
+/// 解析 HLS 媒体播放列表
+pub fn parse_hls_media(content: &str, base: &Url) -> Result<StreamPlan> {
+    if !content.trim_start().starts_with("#EXTM3U") {
+        return Err(anyhow!("Not an HLS playlist: missing #EXTM3U header"));
+    }
+
+    let mut plan = StreamPlan::default();
+    let mut pending_range: Option<String> = None;
+    let mut next_offset = 0;
+    let mut ended = false;
+
+    for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-KEY:") {
+            let attrs = parse_attributes(attrs);
+            if attrs.get("METHOD").map(String::as_str).unwrap_or("NONE") != "NONE" {
+                return Err(anyhow!("Encrypted HLS streams are not supported"));
+            }
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-MAP:") {
+            let attrs = parse_attributes(attrs);
+            let uri = attrs.get("URI").ok_or_else(|| anyhow!("EXT-X-MAP is missing URI"))?;
+            let byte_range = match attrs.get("BYTERANGE") {
+                Some(range) => Some(parse_byte_range(range, 0)?),
+                None => None,
+            };
+            plan.init = Some(Segment { url: base.join(uri)?.to_string(), byte_range });
+        } else if let Some(range) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            pending_range = Some(range.to_string());
+        } else if line == "#EXT-X-ENDLIST" {
+            ended = true;
+        } else if !line.starts_with('#') {
+            let byte_range = match pending_range.take() {
+                Some(range) => {
+                    let (offset, len) = parse_byte_range(&range, next_offset)?;
+                    next_offset = offset + len;
+                    Some((offset, len))
+                }
+                None => None,
+            };
+            plan.segments.push(Segment { url: base.join(line)?.to_string(), byte_range });
+        }
+    }
+
+    if !ended {
+        log_warn("HLS playlist has no #EXT-X-ENDLIST (live stream?), downloading currently listed segments only");
+    }
+    if plan.segments.is_empty() {
+        return Err(anyhow!("HLS playlist does not contain any segments"));
+    }
+    Ok(plan)
+}
+
+// ========== DASH ==========
+
+/// 解析 ISO 8601 时长（如 "PT1H2M3.5S"），返回秒数
+pub fn parse_iso_duration(value: &str) -> Option<f64> {
+    let rest = value.trim().strip_prefix('P')?;
+    let (date_part, time_part) = match rest.split_once('T') {
+        Some((d, t)) => (d, t),
+        None => (rest, ""),
+    };
+
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    for (part, is_time) in [(date_part, false), (time_part, true)] {
+        for ch in part.chars() {
+            if ch.is_ascii_digit() || ch == '.' {
+                number.push(ch);
+                continue;
+            }
+            let n: f64 = number.parse().ok()?;
+            number.clear();
+            seconds += n * match (ch, is_time) {
+                ('D', false) => 86400.0,
+                ('H', true) => 3600.0,
+                ('M', true) => 60.0,
+                ('S', true) => 1.0,
+                _ => return None,
+            };
+        }
+    }
+    Some(seconds)
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn children<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn join_base_url(base: &Url, node: roxmltree::Node) -> Result<Url> {
+    match child(node, "BaseURL").and_then(|n| n.text()) {
+        Some(text) => Ok(base.join(text.trim())?),
+        None => Ok(base.clone()),
+    }
+}
+
+/// 展开 SegmentTemplate 中的 $标识符$
+fn expand_template(template: &str, representation_id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            result.push_str(&rest[start..]);
+            return result;
+        };
+        let token = &after[..end];
+        let (name, format) = match token.split_once('%') {
+            Some((name, format)) => (name, Some(format)),
+            None => (token, None),
+        };
+        let value = match name {
+            "" => "$".to_string(),
+            "RepresentationID" => representation_id.to_string(),
+            "Number" | "Bandwidth" | "Time" => {
+                let n = match name {
+                    "Number" => number,
+                    "Bandwidth" => bandwidth,
+                    _ => time,
+                };
+                // 支持 %0Nd 格式的补零宽度
+                let width = format
+                    .and_then(|f| f.trim_start_matches('0').trim_end_matches('d').parse::<usize>().ok())
+                    .unwrap_or(0);
+                format!("{:0width$}", n, width = width)
+            }
+            _ => format!("${}$", token),
+        };
+        result.push_str(&value);
+        rest = &after[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// 读取 SegmentTemplate 属性，Representation 上的属性优先于 AdaptationSet
+fn template_attr<'a>(templates: &[roxmltree::Node<'a, 'a>], name: &str) -> Option<&'a str> {
+    templates.iter().find_map(|t| t.attribute(name))
+}
+
+fn build_template_plan(
+    templates: &[roxmltree::Node],
+    base: &Url,
+    representation_id: &str,
+    bandwidth: u64,
+    period_duration: Option<f64>,
+) -> Result<StreamPlan> {
+    let media = template_attr(templates, "media").ok_or_else(|| anyhow!("SegmentTemplate is missing media"))?;
+    let start_number: u64 = template_attr(templates, "startNumber").and_then(|v| v.parse().ok()).unwrap_or(1);
+    let timescale: u64 = template_attr(templates, "timescale").and_then(|v| v.parse().ok()).unwrap_or(1);
+
+    let mut plan = StreamPlan::default();
+    if let Some(init) = template_attr(templates, "initialization") {
+        let url = expand_template(init, representation_id, bandwidth, 0, 0);
+        plan.init = Some(Segment { url: base.join(&url)?.to_string(), byte_range: None });
+    }
+
+    let timeline = templates.iter().find_map(|t| child(*t, "SegmentTimeline"));
+    if let Some(timeline) = timeline {
+        let mut time = 0u64;
+        let mut number = start_number;
+        for s in children(timeline, "S") {
+            if let Some(t) = s.attribute("t").and_then(|v| v.parse().ok()) {
+                time = t;
+            }
+            let d: u64 = s.attribute("d").and_then(|v| v.parse().ok()).ok_or_else(|| anyhow!("SegmentTimeline entry is missing d"))?;
+            let repeat: i64 = s.attribute("r").and_then(|v| v.parse().ok()).unwrap_or(0);
+            if repeat < 0 {
+                return Err(anyhow!("Open-ended SegmentTimeline repeats are not supported"));
+            }
+            for _ in 0..=repeat {
+                let url = expand_template(media, representation_id, bandwidth, number, time);
+                plan.segments.push(Segment { url: base.join(&url)?.to_string(), byte_range: None });
+                time += d;
+                number += 1;
+            }
+        }
+    } else {
+        let duration: f64 = template_attr(templates, "duration")
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| anyhow!("SegmentTemplate needs either a SegmentTimeline or a duration"))?;
+        let total = period_duration.ok_or_else(|| anyhow!("Cannot determine presentation duration for SegmentTemplate"))?;
+        let count = (total * timescale as f64 / duration).ceil() as u64;
+        for i in 0..count {
+            let number = start_number + i;
+            let time = (i as f64 * duration) as u64;
+            let url = expand_template(media, representation_id, bandwidth, number, time);
+            plan.segments.push(Segment { url: base.join(&url)?.to_string(), byte_range: None });
+        }
+    }
+
+    Ok(plan)
+}
+
+fn build_list_plan(list: roxmltree::Node, base: &Url) -> Result<StreamPlan> {
+    let mut plan = StreamPlan::default();
+    if let Some(init) = child(list, "Initialization") {
+        if let Some(source) = init.attribute("sourceURL") {
+            plan.init = Some(Segment { url: base.join(source)?.to_string(), byte_range: parse_media_range(init.attribute("range"))? });
+        }
+    }
+    for segment in children(list, "SegmentURL") {
+        let url = match segment.attribute("media") {
+            Some(media) => base.join(media)?.to_string(),
+            None => base.to_string(),
+        };
+        plan.segments.push(Segment { url, byte_range: parse_media_range(segment.attribute("mediaRange"))? });
+    }
+    Ok(plan)
+}
+
+/// 解析 DASH "起始-结束" 形式的字节范围
+fn parse_media_range(value: Option<&str>) -> Result<Option<(u64, u64)>> {
+    match value {
+        Some(range) => {
+            let (start, end) = range.split_once('-').ok_or_else(|| anyhow!("Invalid media range: {}", range))?;
+            let start: u64 = start.trim().parse().context("Invalid media range start")?;
+            let end: u64 = end.trim().parse().context("Invalid media range end")?;
+            Ok(Some((start, end.saturating_sub(start) + 1)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// 解析 DASH MPD，返回首个 Period 中视频（或唯一）自适应集的所有 Representation
+pub fn parse_dash(content: &str, base: &Url) -> Result<Vec<Variant>> {
+    let document = roxmltree::Document::parse(content).map_err(|e| anyhow!("Failed to parse MPD: {}", e))?;
+    let mpd = document.root_element();
+    if mpd.tag_name().name() != "MPD" {
+        return Err(anyhow!("Not a DASH manifest: root element is <{}>", mpd.tag_name().name()));
+    }
+    if mpd.attribute("type") == Some("dynamic") {
+        log_warn("Dynamic (live) MPD detected, downloading currently described segments only");
+    }
+
+    let mpd_base = join_base_url(base, mpd)?;
+    let period = child(mpd, "Period").ok_or_else(|| anyhow!("MPD does not contain a Period"))?;
+    let period_base = join_base_url(&mpd_base, period)?;
+    let period_duration = period
+        .attribute("duration")
+        .or_else(|| mpd.attribute("mediaPresentationDuration"))
+        .and_then(parse_iso_duration);
+
+    let adaptation_sets: Vec<roxmltree::Node> = children(period, "AdaptationSet").collect();
+    let is_video = |set: &roxmltree::Node| {
+        set.attribute("contentType") == Some("video")
+            || set.attribute("mimeType").is_some_and(|m| m.starts_with("video/"))
+            || children(*set, "Representation").any(|r| r.attribute("mimeType").is_some_and(|m| m.starts_with("video/")))
+    };
+    let selected: Vec<&roxmltree::Node> = if adaptation_sets.iter().any(is_video) {
+        adaptation_sets.iter().filter(|s| is_video(s)).collect()
+    } else {
+        adaptation_sets.iter().collect()
+    };
+    if adaptation_sets.len() > selected.len() {
+        log_warn("Only the video adaptation set is downloaded; separate audio/subtitle tracks are skipped");
+    }
+
+    let mut variants = Vec::new();
+    for set in selected {
+        let set_base = join_base_url(&period_base, *set)?;
+        let set_template = child(*set, "SegmentTemplate");
+        let set_list = child(*set, "SegmentList");
+
+        for representation in children(*set, "Representation") {
+            let id = representation.attribute("id").unwrap_or("");
+            let bandwidth: u64 = representation.attribute("bandwidth").and_then(|b| b.parse().ok()).unwrap_or(0);
+            let resolution = match (
+                representation.attribute("width").or_else(|| set.attribute("width")),
+                representation.attribute("height").or_else(|| set.attribute("height")),
+            ) {
+                (Some(w), Some(h)) => w.parse().ok().zip(h.parse().ok()),
+                _ => None,
+            };
+            let rep_base = join_base_url(&set_base, representation)?;
+
+            let templates: Vec<roxmltree::Node> = child(representation, "SegmentTemplate")
+                .into_iter()
+                .chain(set_template)
+                .collect();
+            let plan = if !templates.is_empty() {
+                build_template_plan(&templates, &rep_base, id, bandwidth, period_duration)?
+            } else if let Some(list) = child(representation, "SegmentList").or(set_list) {
+                build_list_plan(list, &rep_base)?
+            } else if child(representation, "BaseURL").is_some() {
+                // 单文件 Representation
+                StreamPlan {
+                    init: None,
+                    segments: vec![Segment { url: rep_base.to_string(), byte_range: None }],
+                }
+            } else {
+                log_warn(&format!("Representation '{}' uses an unsupported segment scheme, skipping", id));
+                continue;
+            };
+
+            variants.push(Variant { bandwidth, resolution, uri: None, plan: Some(plan) });
+        }
+    }
+
+    if variants.is_empty() {
+        return Err(anyhow!("MPD does not contain any downloadable representations"));
+    }
+    Ok(variants)
+}
+
+// ========== 下载 ==========
+
+async fn fetch_text(client: &Client, url: &str) -> Result<String> {
+    let response = client.get(url).send().await.with_context(|| format!("Failed to fetch manifest: {}", url))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Failed to fetch manifest {}: {}", url, response.status()));
+    }
+    response.text().await.context("Failed to read manifest")
+}
+
+/// 解析清单并生成下载计划
+async fn resolve_plan(client: &Client, url: &str, kind: ManifestKind, selection: &StreamSelection) -> Result<StreamPlan> {
+    let base = Url::parse(url).with_context(|| format!("Invalid manifest URL: {}", url))?;
+    let content = fetch_text(client, url).await?;
+
+    let describe = |v: &Variant| {
+        format!(
+            "{} bit/s{}",
+            v.bandwidth,
+            v.resolution.map(|(w, h)| format!(", {}x{}", w, h)).unwrap_or_default()
+        )
+    };
+
+    match kind {
+        ManifestKind::Hls if is_hls_master(&content) => {
+            let variants = parse_hls_master(&content, &base)?;
+            let variant = selection.select(&variants)?;
+            println!("Selected HLS variant: {}", describe(variant));
+            log_info(&format!("Selected HLS variant ({} available): {}", variants.len(), describe(variant)));
+
+            let media_url = variant.uri.clone().ok_or_else(|| anyhow!("HLS variant has no URI"))?;
+            let media_base = Url::parse(&media_url)?;
+            let media = fetch_text(client, &media_url).await?;
+            parse_hls_media(&media, &media_base)
+        }
+        ManifestKind::Hls => parse_hls_media(&content, &base),
+        ManifestKind::Dash => {
+            let variants = parse_dash(&content, &base)?;
+            let variant = selection.select(&variants)?;
+            println!("Selected DASH representation: {}", describe(variant));
+            log_info(&format!("Selected DASH representation ({} available): {}", variants.len(), describe(variant)));
+            variant.plan.clone().ok_or_else(|| anyhow!("DASH representation has no segments"))
+        }
+    }
+}
+
+fn segment_dir(output: &Path) -> PathBuf {
+    let mut name = output.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".segments");
+    output.with_file_name(name)
+}
+
+/// 分段目录中记录下载计划的文件
+const PLAN_FILE: &str = "plan.sha256";
+
+// 下载计划的指纹：清单地址和按顺序排列的分段地址与字节范围
+fn plan_fingerprint(url: &str, segments: &[Segment]) -> String {
+    let mut plan = url.to_string();
+    for segment in segments {
+        plan.push('\n');
+        plan.push_str(&segment.url);
+        if let Some((offset, len)) = segment.byte_range {
+            plan.push_str(&format!(" {}@{}", len, offset));
+        }
+    }
+    HashAlgorithm::Sha256.digest(plan.as_bytes())
+}
+
+fn segment_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{:06}.seg", index))
+}
+
+/// 下载单个分段，先写入临时文件，完成后重命名，保证已存在的分段文件都是完整的
+async fn fetch_segment(client: &Client, segment: &Segment, path: &Path, idle_timeout: u64) -> Result<u64> {
+    let mut request = client.get(&segment.url);
+    if let Some((offset, len)) = segment.byte_range {
+        // 长度为 0 的分段无法表示为 Range，也没有内容可下载，只留一个空的分段文件供合并
+        if len == 0 {
+            fs::write(path, b"").await.context("Failed to create segment file")?;
+            return Ok(0);
+        }
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-{}", offset, offset + len - 1));
+    }
+
+    let response = request.send().await.with_context(|| format!("Segment request failed: {}", segment.url))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("Segment {} failed with status: {}", segment.url, status));
+    }
+    if segment.byte_range.is_some() && status != StatusCode::PARTIAL_CONTENT {
+        return Err(anyhow!("Server ignored byte range for segment {}", segment.url));
+    }
+
+    let temp_path = path.with_extension("part");
+    let mut file = fs::File::create(&temp_path).await.context("Failed to create segment file")?;
+    let mut stream = response.bytes_stream();
+    let idle_duration = Duration::from_secs(idle_timeout);
+    let mut written = 0u64;
+
+    loop {
+        match tokio::time::timeout(idle_duration, stream.next()).await {
+            Ok(Some(chunk)) => {
+                let chunk = chunk.context("Error receiving segment data")?;
+                file.write_all(&chunk).await.context("Failed to write segment")?;
+                written += chunk.len() as u64;
+                schedule::throttle(chunk.len()).await?;
+            }
+            Ok(None) => break,
+            Err(_) => return Err(anyhow!(TimeoutError::IdleTimeout(idle_timeout))),
+        }
+    }
+
+    file.flush().await?;
+    drop(file);
+    fs::rename(&temp_path, path).await.context("Failed to finalize segment file")?;
+    Ok(written)
+}
+
+/// 下载 HLS/DASH 分段流并按顺序合并到输出文件
+pub async fn download_stream(
+    url: &str,
+    output: &PathBuf,
+    parallel: usize,
+    continue_download: bool,
+    idle_timeout: u64,
+    http3: bool,
+    selection: &StreamSelection,
+) -> Result<()> {
+    let kind = detect_manifest(url).ok_or_else(|| anyhow!("Unrecognized stream manifest: {}", url))?;
+    log_info(&format!("Starting {:?} stream download from: {}", kind, url));
+
+    let client = build_client(true, 10, http3, vec![], ClientType::Download)?;
+    let plan = resolve_plan(&client, url, kind, selection).await?;
+
+    // 初始化分段（如有）排在最前面
+    let segments: Vec<Segment> = plan.init.iter().cloned().chain(plan.segments.iter().cloned()).collect();
+    println!("Stream has {} segment(s)", segments.len());
+
+    let dir = segment_dir(output);
+    // 分段只按序号命名，换了变体或清单变化后旧分段属于另一个下载计划，不能拼进来
+    let fingerprint = plan_fingerprint(url, &segments);
+    let plan_file = dir.join(PLAN_FILE);
+    let recorded = fs::read_to_string(&plan_file).await.ok();
+    if continue_download && dir.exists() && recorded.as_deref() != Some(fingerprint.as_str()) {
+        log_info("Stream plan changed since the last run, discarding downloaded segments");
+        fs::remove_dir_all(&dir).await.context("Failed to clear old segment directory")?;
+    }
+    if !continue_download && dir.exists() {
+        fs::remove_dir_all(&dir).await.context("Failed to clear old segment directory")?;
+    }
+    fs::create_dir_all(&dir).await.context("Failed to create segment directory")?;
+    fs::write(&plan_file, &fingerprint).await.context("Failed to record stream plan")?;
+
+    // 分段级断点续传：已存在的分段文件视为已完成
+    let pending: Vec<usize> = (0..segments.len()).filter(|&i| !segment_path(&dir, i).exists()).collect();
+    if pending.len() < segments.len() {
+        log_info(&format!(
+            "Resuming stream download: {} of {} segments already downloaded",
+            segments.len() - pending.len(),
+            segments.len()
+        ));
+    }
+
+    let pb = ProgressBar::new(segments.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} segments ({eta}) | {msg}")
+            .expect("Invalid progress bar template")
+            .progress_chars("#>-"),
+    );
+    pb.set_position((segments.len() - pending.len()) as u64);
+    progress::emit_stream(ProgressEvent::StreamStarted {
+        url,
+        output_path: output,
+        segments: segments.len(),
+        completed_segments: segments.len() - pending.len(),
+    });
+    if progress::json_enabled() {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    let start_time = Instant::now();
+    let semaphore = Arc::new(Semaphore::new(parallel.max(1)));
+    let mut tasks = JoinSet::new();
+    let mut task_segments = HashMap::new();
+
+    for index in pending {
+        let client = client.clone();
+        let segment = segments[index].clone();
+        let path = segment_path(&dir, index);
+        let semaphore = Arc::clone(&semaphore);
+        let pb = pb.clone();
+        let url = url.to_string();
+
+        let task = tasks.spawn(async move {
+            let _permit = semaphore.acquire().await?;
+            let bytes = fetch_segment(&client, &segment, &path, idle_timeout).await?;
+            pb.inc(1);
+            pb.set_message(format!("{}", HumanBytes(bytes)));
+            progress::emit_stream(ProgressEvent::StreamProgress {
+                url: &url,
+                segment: index,
+                bytes,
+                completed_segments: pb.position() as usize,
+                segments: pb.length().unwrap_or(0) as usize,
+            });
+            log_debug(&format!("Segment {} downloaded ({} bytes)", index, bytes));
+            Ok::<u64, anyhow::Error>(bytes)
+        });
+        task_segments.insert(task.id(), index);
+    }
+
+    // 任一分段失败时取消其余分段，已完成的分段文件保留用于续传
+    let mut downloaded_bytes = 0;
+    while let Some(result) = tasks.join_next_with_id().await {
+        match result {
+            Ok((_, Ok(bytes))) => downloaded_bytes += bytes,
+            Ok((id, Err(e))) => {
+                let index = task_segments[&id];
+                tasks.abort_all();
+                log_error(&format!("Segment {} failed: {}", index, e));
+                pb.abandon_with_message(format!("Segment {} failed", index));
+                progress::emit_stream(ProgressEvent::StreamFailed { url, segment: index, error: e.to_string() });
+                return Err(e);
+            }
+            Err(e) => {
+                let index = task_segments[&e.id()];
+                tasks.abort_all();
+                pb.abandon_with_message(format!("Segment {} panicked", index));
+                progress::emit_stream(ProgressEvent::StreamFailed { url, segment: index, error: e.to_string() });
+                return Err(anyhow!("Segment task panicked: {}", e));
+            }
+        }
+    }
+
+    // 按顺序合并所有分段
+    pb.set_message("Merging segments...");
+    let mut out = fs::File::create(output)
+        .await
+        .with_context(|| format!("Failed to create output file: {}", output.display()))?;
+    let mut total = 0u64;
+    for index in 0..segments.len() {
+        let data = fs::read(segment_path(&dir, index)).await.with_context(|| format!("Missing segment {}", index))?;
+        out.write_all(&data).await.context("Failed to write output file")?;
+        total += data.len() as u64;
+    }
+    out.flush().await?;
+    fs::remove_dir_all(&dir).await.context("Failed to remove segment directory")?;
+
+    let elapsed = start_time.elapsed();
+    let speed = if elapsed.as_secs_f64() > 0.0 {
+        downloaded_bytes as f64 / elapsed.as_secs_f64()
+    } else {
+        0.0
+    };
+    pb.finish_with_message(format!(
+        "Downloaded {} in {:.2}s (avg: {}/s)",
+        HumanBytes(total),
+        elapsed.as_secs_f64(),
+        HumanBytes(speed as u64)
+    ));
+    progress::emit_stream(ProgressEvent::StreamCompleted {
+        url,
+        output_path: output,
+        segments: segments.len(),
+        total_size: total,
+        elapsed_ms: elapsed.as_millis() as u64,
+        average_speed: speed,
+    });
+    log_info(&format!(
+        "Stream download completed: {} segments, {} written to {}",
+        segments.len(),
+        HumanBytes(total),
+        output.display()
+    ));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hls_master_selection() {
+        let base = Url::parse("https://cdn.example.com/video/master.m3u8").unwrap();
+        let master = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"\n\
+            low/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720\n\
+            mid/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080\n\
+            high/index.m3u8\n";
+
+        let variants = parse_hls_master(master, &base).unwrap();
+        assert_eq!(variants.len(), 3);
+
+        let best = StreamSelection::default().select(&variants).unwrap();
+        assert_eq!(best.uri.as_deref(), Some("https://cdn.example.com/video/high/index.m3u8"));
+
+        let capped = StreamSelection { max_bandwidth: Some(3_000_000), resolution: None };
+        assert_eq!(capped.select(&variants).unwrap().bandwidth, 2_500_000);
+
+        let by_resolution = StreamSelection { max_bandwidth: None, resolution: Some((640, 360)) };
+        assert_eq!(by_resolution.select(&variants).unwrap().bandwidth, 800_000);
+    }
+
+    #[test]
+    fn test_hls_media_playlist() {
+        let base = Url::parse("https://cdn.example.com/video/high/index.m3u8").unwrap();
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MAP:URI=\"init.mp4\"\n\
+            #EXTINF:6.0,\nseg0.m4s\n#EXTINF:6.0,\n#EXT-X-BYTERANGE:100@0\nall.ts\n#EXTINF:6.0,\n#EXT-X-BYTERANGE:50\nall.ts\n#EXT-X-ENDLIST\n";
+
+        let plan = parse_hls_media(media, &base).unwrap();
+        assert_eq!(plan.init.unwrap().url, "https://cdn.example.com/video/high/init.mp4");
+        assert_eq!(plan.segments.len(), 3);
+        assert_eq!(plan.segments[1].byte_range, Some((0, 100)));
+        assert_eq!(plan.segments[2].byte_range, Some((100, 50)));
+    }
+
+    #[tokio::test]
+    async fn test_download_byte_range_stream() {
+        use crate::test_server::{serve, Reply};
+
+        let media: Vec<u8> = (0..30).collect();
+        let base = serve(move |request| {
+            let range = request.headers.get("range").and_then(|r| r.strip_prefix("bytes="));
+            match (request.path.as_str(), range.and_then(|r| r.split_once('-'))) {
+                ("/ok.m3u8", _) => Reply::new(
+                    200,
+                    "#EXTM3U\n#EXT-X-BYTERANGE:0@0\nall.ts\n#EXT-X-BYTERANGE:10@0\nall.ts\n#EXT-X-BYTERANGE:20@10\nall.ts\n#EXT-X-ENDLIST\n",
+                ),
+                ("/bad.m3u8", _) => Reply::new(200, "#EXTM3U\nmissing.ts\n#EXT-X-ENDLIST\n"),
+                ("/all.ts", Some((start, end))) => {
+                    let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+                    Reply::new(206, &media[start..=end])
+                }
+                _ => Reply::new(404, ""),
+            }
+        })
+        .await;
+
+        let dir = std::env::temp_dir().join(format!("surf-stream-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let selection = StreamSelection::default();
+        let output = dir.join("ok.ts");
+        download_stream(&format!("{}/ok.m3u8", base), &output, 2, false, 10, false, &selection).await.unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), (0..30).collect::<Vec<u8>>());
+
+        // 另一个下载计划留下的分段不会被续传拼进来
+        let segments = segment_dir(&output);
+        std::fs::create_dir_all(&segments).unwrap();
+        std::fs::write(segments.join(PLAN_FILE), "other plan").unwrap();
+        std::fs::write(segment_path(&segments, 1), b"stale data").unwrap();
+        download_stream(&format!("{}/ok.m3u8", base), &output, 2, true, 10, false, &selection).await.unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), (0..30).collect::<Vec<u8>>());
+
+        let output = dir.join("bad.ts");
+        let result = download_stream(&format!("{}/bad.m3u8", base), &output, 2, false, 10, false, &selection).await;
+        assert!(result.is_err());
+        assert!(!output.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dash_template() {
+        let base = Url::parse("https://cdn.example.com/dash/manifest.mpd").unwrap();
+        let mpd = r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" mediaPresentationDuration="PT10S">
+  <Period>
+    <AdaptationSet contentType="video">
+      <SegmentTemplate initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/seg-$Number%05d$.m4s" startNumber="1" duration="4" timescale="1"/>
+      <Representation id="720p" bandwidth="3000000" width="1280" height="720"/>
+      <Representation id="360p" bandwidth="800000" width="640" height="360"/>
+    </AdaptationSet>
+    <AdaptationSet contentType="audio">
+      <Representation id="audio" bandwidth="128000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+        let variants = parse_dash(mpd, &base).unwrap();
+        assert_eq!(variants.len(), 2);
+
+        let best = StreamSelection::default().select(&variants).unwrap();
+        let plan = best.plan.as_ref().unwrap();
+        assert_eq!(plan.init.as_ref().unwrap().url, "https://cdn.example.com/dash/720p/init.mp4");
+        assert_eq!(plan.segments.len(), 3);
+        assert_eq!(plan.segments[2].url, "https://cdn.example.com/dash/720p/seg-00003.m4s");
+    }
+
+    #[test]
+    fn test_iso_duration() {
+        assert_eq!(parse_iso_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(parse_iso_duration("P1DT0S"), Some(86400.0));
+        assert_eq!(parse_iso_duration("bogus"), None);
+    }
+}
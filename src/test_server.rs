@@ -0,0 +1,111 @@
+// 测试用的本地 HTTP/1.1 服务器：默认每个连接处理一个请求后关闭，响应要求时保持连接
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// 收到的请求，头部名称为小写
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// 要返回的响应
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// 响应前等待的时间，模拟慢的服务端
+    pub delay: Duration,
+    /// 响应后保持连接，继续处理同一连接上的请求
+    pub keep_alive: bool,
+}
+
+impl Reply {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self { status, headers: Vec::new(), body: body.into(), delay: Duration::ZERO, keep_alive: false }
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn keep_alive(mut self) -> Self {
+        self.keep_alive = true;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// 在随机端口上启动服务器，返回 "http://127.0.0.1:端口"
+pub async fn serve(handler: impl Fn(&Request) -> Reply + Send + Sync + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let handler = Arc::clone(&handler);
+            tokio::spawn(async move {
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let head_end = loop {
+                        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break pos + 4;
+                        }
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => data.extend_from_slice(&buf[..n]),
+                        }
+                    };
+                    let text = String::from_utf8_lossy(&data[..head_end]).into_owned();
+                    let mut lines = text.lines();
+                    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+                    let request = Request {
+                        method: request_line.next().unwrap_or_default().to_string(),
+                        path: request_line.next().unwrap_or_default().to_string(),
+                        headers: lines
+                            .take_while(|line| !line.is_empty())
+                            .filter_map(|line| line.split_once(':'))
+                            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+                            .collect(),
+                    };
+                    // 跳过请求体，保持连接时下一个请求从它之后开始
+                    let body_len: usize = request.headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+                    while data.len() < head_end + body_len {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => data.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    data.drain(..head_end + body_len);
+
+                    let reply = handler(&request);
+                    tokio::time::sleep(reply.delay).await;
+                    let connection = if reply.keep_alive { "keep-alive" } else { "close" };
+                    let mut response = format!("HTTP/1.1 {} X\r\nConnection: {}\r\n", reply.status, connection);
+                    let body = if request.method == "HEAD" { &[][..] } else { &reply.body[..] };
+                    response.push_str(&format!("Content-Length: {}\r\n", reply.body.len()));
+                    for (name, value) in &reply.headers {
+                        response.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    response.push_str("\r\n");
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    let _ = socket.write_all(body).await;
+                    if !reply.keep_alive {
+                        let _ = socket.shutdown().await;
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    base
+}